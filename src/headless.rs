@@ -0,0 +1,331 @@
+use std::{
+    fs,
+    path::{
+        Path,
+        PathBuf
+    },
+    str::FromStr,
+    time::{
+        Duration,
+        Instant
+    }
+};
+
+use glam::{
+    UVec3,
+    Vec3
+};
+
+use crate::{
+    build_headless_wgpu_backend,
+    logic::{
+        Logic,
+        play::DEFAULT_PIPELINE
+    },
+    mesh::{
+        self,
+        contour
+    },
+    renderer::{
+        capture,
+        software_ray_marcher,
+        Renderer
+    },
+    scene::{
+        evaluate::SceneEvaluator,
+        Scene
+    },
+    voxel::{
+        magicavoxel::VoxFile,
+        terrain::Terrain,
+        world::{
+            window_min,
+            World,
+            WINDOW_CHUNKS
+        }
+    },
+    RenderTarget
+};
+
+pub struct HeadlessOptions {
+    pub output: PathBuf,
+    pub frames: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Name of a pipeline the renderer registers.
+    pub pipeline: String,
+    pub scene: Scene,
+    pub seed: u64,
+    pub vox: Option<PathBuf>,
+    pub export: Option<PathBuf>,
+    pub load: Option<PathBuf>,
+    pub save: Option<PathBuf>,
+    pub mesh: Option<PathBuf>,
+    /// OBJ or glTF file the rasterizer draws instead of the cube.
+    pub model: Option<PathBuf>,
+    /// Cells along each side of the `--mesh` bounds.
+    pub mesh_resolution: u32,
+    /// Half size of the cube around the origin `--mesh` extracts.
+    pub mesh_bounds: f32,
+    pub force_fallback_adapter: bool,
+}
+
+impl HeadlessOptions {
+    /// Parses the command line, returning `None` when `--headless` was not requested. Every other flag
+    /// only applies to headless runs, so giving one without `--headless` is an error rather than
+    /// silently opening the window.
+    ///
+    /// Usage: `vox --headless [--output DIR] [--frames N] [--width W] [--height H] [--pipeline rasterizer|ray-marcher|software|voxel|octree|brickmap|world|world-raster|hybrid] [--scene sphere|showcase] [--seed N] [--vox FILE] [--export FILE] [--load FILE] [--save FILE] [--mesh FILE.obj|FILE.stl] [--mesh-resolution N] [--mesh-bounds S] [--model FILE.obj|FILE.gltf|FILE.glb] [--fallback]`
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        let mut options = Self {
+            output: PathBuf::from("frames"),
+            frames: 1,
+            width: 1280,
+            height: 720,
+            pipeline: String::from(DEFAULT_PIPELINE),
+            scene: Scene::new(),
+            seed: 0,
+            vox: None,
+            export: None,
+            load: None,
+            save: None,
+            mesh: None,
+            model: None,
+            mesh_resolution: 128,
+            mesh_bounds: 3.0,
+            force_fallback_adapter: false,
+        };
+
+        let mut headless = false;
+        let mut first_flag = None;
+
+        while let Some(arg) = args.next() {
+            if arg != "--headless" && first_flag.is_none() {
+                first_flag = Some(arg.clone());
+            }
+
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--fallback" => options.force_fallback_adapter = true,
+                "--output" => options.output = PathBuf::from(args.next().expect("Missing value for --output")),
+                "--frames" => options.frames = Self::parse_number(args.next(), "--frames"),
+                "--width" => options.width = Self::parse_size(args.next(), "--width"),
+                "--height" => options.height = Self::parse_size(args.next(), "--height"),
+                "--vox" => options.vox = Some(PathBuf::from(args.next().expect("Missing value for --vox"))),
+                "--export" => options.export = Some(PathBuf::from(args.next().expect("Missing value for --export"))),
+                "--load" => options.load = Some(PathBuf::from(args.next().expect("Missing value for --load"))),
+                "--save" => options.save = Some(PathBuf::from(args.next().expect("Missing value for --save"))),
+                "--mesh" => options.mesh = Some(PathBuf::from(args.next().expect("Missing value for --mesh"))),
+                "--model" => options.model = Some(PathBuf::from(args.next().expect("Missing value for --model"))),
+                "--mesh-resolution" => options.mesh_resolution = Self::parse_number(args.next(), "--mesh-resolution"),
+                "--mesh-bounds" => options.mesh_bounds = Self::parse_number(args.next(), "--mesh-bounds"),
+                "--seed" => options.seed = Self::parse_number(args.next(), "--seed"),
+                "--pipeline" => options.pipeline = Self::parse_pipeline(args.next()),
+                "--scene" => {
+                    options.scene = match args.next().as_deref() {
+                        Some("sphere") => Scene::new(),
+                        Some("showcase") => Scene::showcase(),
+                        other => panic!("Unknown scene {:?}, expected sphere or showcase", other),
+                    };
+                }
+                other => panic!("Unknown argument {:?}", other),
+            }
+        }
+
+        if !headless {
+            if let Some(flag) = first_flag {
+                panic!("{} only applies to headless runs, add --headless", flag);
+            }
+
+            return None;
+        }
+
+        return Some(options);
+    }
+
+    fn parse_number<T: FromStr>(value: Option<String>, name: &str) -> T {
+        let value = value.unwrap_or_else(|| panic!("Missing value for {}", name));
+
+        return value.parse().unwrap_or_else(|_| panic!("Invalid value {:?} for {}", value, name));
+    }
+
+    /// A pipeline the renderer registers, checked before any adapter is requested.
+    fn parse_pipeline(value: Option<String>) -> String {
+        let value = value.expect("Missing value for --pipeline");
+        let names = Renderer::registry().names();
+
+        if !names.contains(&value.as_str()) {
+            panic!("Unknown pipeline {:?} for --pipeline, expected one of {}", value, names.join(", "));
+        }
+
+        return value;
+    }
+
+    /// A side of the frames, which must hold at least one pixel.
+    fn parse_size(value: Option<String>, name: &str) -> u32 {
+        let size = Self::parse_number(value, name);
+
+        if size == 0 {
+            panic!("{} must be at least 1", name);
+        }
+
+        return size;
+    }
+}
+
+fn frame_path(options: &HeadlessOptions, frame: u32) -> PathBuf {
+    return options.output.join(format!("frame_{:04}.png", frame));
+}
+
+/// Renders `options.frames` frames into an offscreen texture and writes each one as a PNG in `options.output`.
+/// Falls back to the CPU ray marcher when no adapter at all is available.
+pub fn run(options: HeadlessOptions) {
+    fs::create_dir_all(&options.output).expect("Failed to create output directory");
+
+    if let Some(path) = &options.mesh {
+        export_mesh(&options, path);
+    }
+
+    let Some(backend) = build_headless_wgpu_backend(options.width, options.height, options.force_fallback_adapter) else {
+        println!("No adapter available, rendering {} headless frame(s) with the CPU ray marcher", options.frames);
+
+        run_software(options);
+        return;
+    };
+
+    let info = backend.adapter.get_info();
+    println!("Rendering {} headless frame(s) with {} ({:?}, {:?})", options.frames, info.name, info.device_type, info.backend);
+
+    let mut logic = Logic::new();
+    logic.play.pipeline = options.pipeline.clone();
+    logic.play.scene = options.scene.clone();
+    logic.play.world = match &options.load {
+        Some(path) => World::open(path).unwrap_or_else(|error| panic!("Failed to open {}: {}", path.display(), error)),
+        None => World::new(Box::new(Terrain::new(options.seed))),
+    };
+
+    if let Some(path) = &options.vox {
+        let bytes = fs::read(path).unwrap_or_else(|error| panic!("Failed to read {}: {}", path.display(), error));
+        let file = VoxFile::parse(&bytes).unwrap_or_else(|error| panic!("Failed to import {}: {}", path.display(), error));
+
+        // Scaled into the bounds of the demo volume, where the voxel pipelines point the camera
+        let mut volume = file.to_volume().unwrap_or_else(|error| panic!("Failed to import {}: {}", path.display(), error));
+        volume.voxel_size = 4.0 / volume.size().max_element() as f32;
+        volume.origin = Vec3::new(-2.0, -1.0, -2.0);
+
+        logic.play.volume = volume;
+    }
+
+    if let Some(path) = &options.model {
+        logic.play.objects = mesh::load(path).unwrap_or_else(|error| panic!("Failed to load {}: {}", path.display(), error));
+
+        let triangles = logic.play.objects.iter().map(|object| object.mesh.triangle_count()).sum::<usize>();
+        println!("Loaded {} object(s), {} triangles, from {}", logic.play.objects.len(), triangles, path.display());
+    }
+
+    let mut renderer = Renderer::new(&backend, &logic);
+
+    if let Some(error) = renderer.scene_error(&logic) {
+        eprintln!("Failed to upload the scene, rendering an empty one: {}", error);
+    }
+
+    let RenderTarget::Offscreen(texture) = &backend.target else {
+        unreachable!("Headless backends always render offscreen");
+    };
+
+    let world = renderer.draws_world(&logic);
+
+    // The whole window first, so frames and their timings do not depend on how fast chunks stream in
+    if world {
+        logic.play.world.load_window(logic.play.camera.position);
+        renderer.update(&backend, &mut logic);
+
+        while renderer.pending(&logic) > 0 {
+            renderer.update(&backend, &mut logic);
+        }
+    }
+
+    let mut render_time = Duration::ZERO;
+
+    for frame in 0..options.frames {
+        let start = Instant::now();
+
+        renderer.render(&backend, &logic);
+
+        // Reading the frame back waits for the GPU, so the time covers the whole frame
+        let pixels = capture::read_texture(&backend, texture);
+        render_time += start.elapsed();
+
+        capture::save_png(&frame_path(&options, frame), backend.config.width, backend.config.height, &pixels).expect("Failed to write frame");
+
+        logic.update(1.0 / 60.0);
+        renderer.update(&backend, &mut logic);
+    }
+
+    for (coord, error) in logic.play.world.take_load_errors() {
+        eprintln!("Failed to load chunk {} from the save, generated it instead: {}", coord, error);
+    }
+
+    println!("Rendered in {:.2} ms per frame, including the read back", render_time.as_secs_f64() * 1000.0 / options.frames.max(1) as f64);
+
+    if let Some(triangles) = renderer.triangle_count(&logic) {
+        println!("The pipeline draws {} triangles", triangles);
+    }
+
+    if let Some(path) = &options.export {
+        export(&mut logic, path, world);
+    }
+
+    if let Some(path) = &options.save {
+        logic.play.world.save(path).unwrap_or_else(|error| panic!("Failed to save {}: {}", path.display(), error));
+    }
+}
+
+/// Writes the voxels of the last frame as a MagicaVoxel file: the window of chunks around the camera
+/// for the pipelines drawing the world, the volume otherwise.
+fn export(logic: &mut Logic, path: &Path, world: bool) {
+    let volume = if world {
+        let world = &mut logic.play.world;
+        world.load_window(logic.play.camera.position);
+
+        world.region(window_min(world.chunk_coord(logic.play.camera.position)), UVec3::splat(WINDOW_CHUNKS as u32))
+    } else {
+        logic.play.volume.clone()
+    };
+
+    fs::write(path, VoxFile::from_volume(&volume).write()).unwrap_or_else(|error| panic!("Failed to write {}: {}", path.display(), error));
+}
+
+/// Writes the surface of the scene as an OBJ or binary STL file, depending on the extension.
+fn export_mesh(options: &HeadlessOptions, path: &Path) {
+    let evaluator = SceneEvaluator::new(&options.scene).expect("Invalid scene");
+    let bounds = Vec3::splat(options.mesh_bounds);
+
+    let mesh = contour::contour(&evaluator, -bounds, bounds, options.mesh_resolution);
+
+    let bytes = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("obj") => mesh.write_obj().into_bytes(),
+        Some("stl") => mesh.write_stl(),
+        other => panic!("Unknown mesh format {:?}, expected obj or stl", other),
+    };
+
+    fs::write(path, bytes).unwrap_or_else(|error| panic!("Failed to write {}: {}", path.display(), error));
+
+    println!("Wrote {} triangles to {}", mesh.triangle_count(), path.display());
+}
+
+fn run_software(options: HeadlessOptions) {
+    let mut logic = Logic::new();
+    let pixel_count = (options.width as usize).checked_mul(options.height as usize).and_then(|count| count.checked_mul(4));
+    let mut pixels = vec![0u8; pixel_count.unwrap_or_else(|| panic!("Frames of {}x{} do not fit in memory", options.width, options.height))];
+
+    let evaluator = SceneEvaluator::new(&options.scene).expect("Invalid scene");
+
+    for frame in 0..options.frames {
+        software_ray_marcher::march_frame(&logic.play.camera, &options.scene, &evaluator, options.width, options.height, &mut pixels);
+
+        capture::save_png(&frame_path(&options, frame), options.width, options.height, &pixels).expect("Failed to write frame");
+
+        logic.update(1.0 / 60.0);
+    }
+}
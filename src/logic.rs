@@ -13,6 +13,7 @@ use crate::logic::{
 };
 
 pub mod camera;
+pub mod cursor;
 pub mod play;
 pub mod menu;
 
@@ -60,13 +61,24 @@ impl Logic {
         }
     }
 
-    pub fn process_mouse_motion(&mut self, delta: (f32, f32)) {
+    pub fn process_mouse_motion(&mut self, window: &Window, delta: (f32, f32)) {
         match self.state {
             LogicState::Playing => {
-                self.play.process_mouse_motion(delta);
+                self.play.process_mouse_motion(window, delta);
             }
             LogicState::Menu => {
-                self.menu.process_mouse_motion(delta);
+                self.menu.process_mouse_motion(window, delta);
+            }
+        }
+    }
+
+    pub fn process_focus(&mut self, window: &Window, focused: bool) {
+        match self.state {
+            LogicState::Playing => {
+                self.play.process_focus(window, focused);
+            }
+            LogicState::Menu => {
+                self.menu.process_focus(window, focused);
             }
         }
     }
@@ -158,6 +158,7 @@ impl CameraController {
         };
     }
 
+    #[allow(clippy::match_single_binding)] // Destructures the event like the other key handlers
     pub fn process_keyboard(&mut self, event: KeyEvent) {
         match event {
             KeyEvent {
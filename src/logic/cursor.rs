@@ -0,0 +1,83 @@
+use winit::{
+    dpi::PhysicalPosition,
+    window::{
+        CursorGrabMode,
+        Window
+    }
+};
+
+/// How the cursor is currently held by the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaptureMode {
+    /// The cursor moves freely.
+    Released,
+    /// The cursor is locked in place by the platform (macOS, Wayland).
+    Locked,
+    /// The cursor is kept inside the window by the platform (Windows, X11).
+    Confined,
+    /// The platform refused both grab modes, the cursor is warped back to the window center on every motion.
+    Recentering,
+}
+
+pub struct CursorCapture {
+    mode: CaptureMode,
+}
+
+impl CursorCapture {
+    pub fn new() -> Self {
+        return Self {
+            mode: CaptureMode::Released,
+        };
+    }
+
+    pub fn mode(&self) -> CaptureMode {
+        return self.mode;
+    }
+
+    pub fn is_captured(&self) -> bool {
+        return self.mode != CaptureMode::Released;
+    }
+
+    /// Captures the cursor with the best mode the platform supports: `Locked`, then `Confined`, then
+    /// manual re-centering. Returns `Released` when none of them is available.
+    pub fn grab(&mut self, window: &Window) -> CaptureMode {
+        self.mode = if window.set_cursor_grab(CursorGrabMode::Locked).is_ok() {
+            CaptureMode::Locked
+        } else if window.set_cursor_grab(CursorGrabMode::Confined).is_ok() {
+            CaptureMode::Confined
+        } else if Self::recenter(window) {
+            CaptureMode::Recentering
+        } else {
+            CaptureMode::Released
+        };
+
+        window.set_cursor_visible(self.mode == CaptureMode::Released);
+
+        return self.mode;
+    }
+
+    pub fn release(&mut self, window: &Window) {
+        if self.mode == CaptureMode::Locked || self.mode == CaptureMode::Confined {
+            // Releasing can only fail on platforms that refused the grab in the first place
+            let _ = window.set_cursor_grab(CursorGrabMode::None);
+        }
+
+        window.set_cursor_visible(true);
+
+        self.mode = CaptureMode::Released;
+    }
+
+    /// Keeps the cursor in the window when the platform could not grab it.
+    pub fn process_mouse_motion(&mut self, window: &Window) {
+        if self.mode == CaptureMode::Recentering && !Self::recenter(window) {
+            self.release(window);
+        }
+    }
+
+    fn recenter(window: &Window) -> bool {
+        let size = window.inner_size();
+        let center = PhysicalPosition::new(size.width / 2, size.height / 2);
+
+        return window.set_cursor_position(center).is_ok();
+    }
+}
@@ -15,11 +15,13 @@ impl Menu {
         return Self {};
     }
 
-    pub fn process_keyboard(&mut self, window: &Window, key_event: KeyEvent) {}
+    pub fn process_keyboard(&mut self, _window: &Window, _key_event: KeyEvent) {}
 
-    pub fn process_mouse_input(&mut self, window: &Window, state: ElementState, mouse_button: MouseButton) {}
+    pub fn process_mouse_input(&mut self, _window: &Window, _state: ElementState, _mouse_button: MouseButton) {}
 
-    pub fn process_mouse_motion(&mut self, delta: (f32, f32)) {}
+    pub fn process_mouse_motion(&mut self, _window: &Window, _delta: (f32, f32)) {}
 
-    pub fn update(&mut self, delta_time: f32) {}
+    pub fn process_focus(&mut self, _window: &Window, _focused: bool) {}
+
+    pub fn update(&mut self, _delta_time: f32) {}
 }
\ No newline at end of file
@@ -12,15 +12,30 @@ use winit::{
         KeyCode,
         PhysicalKey
     },
-    window::{
-        CursorGrabMode,
-        Window
-    }
+    window::Window
 };
 
-use crate::logic::camera::{
-    Camera,
-    CameraController
+use crate::{
+    logic::{
+        camera::{
+            Camera,
+            CameraController
+        },
+        cursor::{
+            CaptureMode,
+            CursorCapture
+        }
+    },
+    mesh::{
+        obj,
+        MeshObject
+    },
+    scene::Scene,
+    voxel::{
+        terrain::Terrain,
+        world::World,
+        VoxelVolume
+    }
 };
 
 #[derive(PartialEq)]
@@ -29,18 +44,23 @@ pub enum PlayState {
     Pause,
 }
 
-#[derive(PartialEq)]
-pub enum PipelineType {
-    TestRasterizer,
-    TestRayMarcher,
-}
+/// Pipeline drawn when the game starts, one of the names the renderer registers.
+pub const DEFAULT_PIPELINE: &str = "rasterizer";
 
 pub struct Play {
     pub camera: Camera,
     pub controller: CameraController,
+    pub cursor: CursorCapture,
+
+    pub scene: Scene,
+    pub volume: VoxelVolume,
+    pub world: World,
+    /// Meshes drawn by the rasterizer.
+    pub objects: Vec<MeshObject>,
 
     pub state: PlayState,
-    pub pipeline: PipelineType,
+    /// Name of the pipeline drawing the game, selected in the renderer's registry.
+    pub pipeline: String,
 }
 
 impl Play {
@@ -48,12 +68,19 @@ impl Play {
         return Self {
             camera: Camera::new(),
             controller: CameraController::new(),
+            cursor: CursorCapture::new(),
+
+            scene: Scene::new(),
+            volume: VoxelVolume::demo(),
+            world: World::new(Box::new(Terrain::new(0))),
+            objects: obj::parse(include_str!("../../assets/cube.obj")).expect("The embedded cube is valid"),
 
             state: PlayState::Pause,
-            pipeline: PipelineType::TestRasterizer,
+            pipeline: String::from(DEFAULT_PIPELINE),
         };
     }
 
+    #[allow(clippy::match_single_binding, clippy::collapsible_match)] // One arm per key, each checking its own state
     pub fn process_keyboard(&mut self, window: &Window, key_event: KeyEvent) {
         match key_event {
             KeyEvent {
@@ -64,28 +91,11 @@ impl Play {
                 match physical_key {
                     PhysicalKey::Code(KeyCode::Escape) => {
                         if state == ElementState::Pressed {
-                            window.set_cursor_grab(CursorGrabMode::None).expect("Failed to set cursor grab mode");
-                            window.set_cursor_visible(true);
-                            self.state = PlayState::Pause;
-                        }
-                    }
-                    PhysicalKey::Code(KeyCode::KeyE) => {
-                        if state == ElementState::Pressed {
-                            self.pipeline = PipelineType::TestRasterizer;
-                            self.camera.position = Vec3::new(0f32, -3f32, 0f32);
-                            self.camera.rotation = Vec3::new(FRAC_PI_2, 0f32, 0f32);
-                        }
-                    }
-                    PhysicalKey::Code(KeyCode::KeyR) => {
-                        if state == ElementState::Pressed {
-                            self.pipeline = PipelineType::TestRayMarcher;
-                            self.camera.position = Vec3::new(0f32, -3f32, 0f32);
-                            self.camera.rotation = Vec3::new(FRAC_PI_2, 0f32, 0f32);
+                            self.pause(window);
                         }
                     }
                     PhysicalKey::Code(KeyCode::Enter) => {
-                        self.camera.position = Vec3::new(0f32, -3f32, 0f32);
-                        self.camera.rotation = Vec3::new(FRAC_PI_2, 0f32, 0f32);
+                        self.reset_camera();
                     }
                     _ => {}
                 }
@@ -95,22 +105,30 @@ impl Play {
         self.controller.process_keyboard(key_event);
     }
 
-    #[cfg(target_os = "windows")]
-    fn grab_cursor(window: &Window) {
-        window.set_cursor_grab(CursorGrabMode::Confined).expect("Failed to set cursor grab mode");
+    /// Switches to another registered pipeline, looking at the origin again.
+    pub fn select_pipeline(&mut self, name: &str) {
+        self.pipeline = String::from(name);
+        self.reset_camera();
+    }
+
+    fn reset_camera(&mut self) {
+        self.camera.position = Vec3::new(0f32, -3f32, 0f32);
+        self.camera.rotation = Vec3::new(FRAC_PI_2, 0f32, 0f32);
     }
 
-    #[cfg(target_os = "macos")]
-    fn grab_cursor(window: &Window) {
-        window.set_cursor_grab(CursorGrabMode::Locked).expect("Failed to set cursor grab mode");
+    fn pause(&mut self, window: &Window) {
+        self.cursor.release(window);
+        self.state = PlayState::Pause;
     }
 
     pub fn process_mouse_input(&mut self, window: &Window, state: ElementState, mouse_button: MouseButton) {
         match mouse_button {
             MouseButton::Left => {
-                if state == ElementState::Pressed {
-                    Self::grab_cursor(window);
-                    window.set_cursor_visible(false);
+                if state == ElementState::Pressed && !self.cursor.is_captured() {
+                    if self.cursor.grab(window) == CaptureMode::Released {
+                        eprintln!("Failed to capture the cursor, mouse look may leave the window");
+                    }
+
                     self.state = PlayState::Playing;
                 }
             }
@@ -122,12 +140,19 @@ impl Play {
         }
     }
 
-    pub fn process_mouse_motion(&mut self, delta: (f32, f32)) {
+    pub fn process_mouse_motion(&mut self, window: &Window, delta: (f32, f32)) {
         if self.state == PlayState::Playing {
+            self.cursor.process_mouse_motion(window);
             self.controller.process_mouse_motion(delta);
         }
     }
 
+    pub fn process_focus(&mut self, window: &Window, focused: bool) {
+        if !focused && self.state == PlayState::Playing {
+            self.pause(window);
+        }
+    }
+
     pub fn update(&mut self, delta_time: f32) {
         if self.state == PlayState::Playing {
             self.controller.update(delta_time, &mut self.camera);
@@ -1,3 +1,9 @@
+// Explicit returns and `new` constructors without `Default` are the house style
+#![allow(
+    clippy::needless_return,
+    clippy::new_without_default
+)]
+
 use std::{
     thread::sleep,
     time::Duration,
@@ -30,6 +36,7 @@ use wgpu::{
     Queue,
     Surface,
     SurfaceConfiguration,
+    Texture,
     TextureFormat,
 };
 use winit::event::DeviceEvent;
@@ -37,15 +44,26 @@ use winit::event::DeviceEvent;
 use crate::logic::Logic;
 use crate::renderer::Renderer;
 
-async fn build_backend(window: &Window) -> (Instance, Surface, SurfaceConfiguration, Adapter, Device, Queue) {
+async fn build_backend(window: &Window) -> Option<(Instance, Surface<'_>, SurfaceConfiguration, Adapter, Device, Queue)> {
     let instance = wgpu::Instance::default();
 
     let surface = instance.create_surface(window).unwrap();
-    let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+
+    let mut adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
         power_preference: wgpu::PowerPreference::default(),
         force_fallback_adapter: false,
         compatible_surface: Some(&surface),
-    }).await.expect("Failed to find an appropriate adapter");
+    }).await;
+
+    if adapter.is_none() {
+        adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: true,
+            compatible_surface: Some(&surface),
+        }).await;
+    }
+
+    let adapter = adapter?;
 
     let (device, queue) = adapter.request_device(
         &wgpu::DeviceDescriptor {
@@ -66,35 +84,152 @@ async fn build_backend(window: &Window) -> (Instance, Surface, SurfaceConfigurat
 
     surface.configure(&device, &config);
 
-    return (instance, surface, config, adapter, device, queue);
+    return Some((instance, surface, config, adapter, device, queue));
+}
+
+async fn build_headless_backend(width: u32, height: u32, force_fallback_adapter: bool) -> Option<(Instance, Texture, SurfaceConfiguration, Adapter, Device, Queue)> {
+    let instance = wgpu::Instance::default();
+
+    let mut adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        force_fallback_adapter,
+        compatible_surface: None,
+    }).await;
+
+    if adapter.is_none() && !force_fallback_adapter {
+        adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: true,
+            compatible_surface: None,
+        }).await;
+    }
+
+    let adapter = adapter?;
+
+    let (device, queue) = adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits()),
+        },
+        None,
+    ).await.expect("Failed to create device");
+
+    // The offscreen target mimics a surface so that pipelines can keep reading the format and size from it
+    let config = SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        format: TextureFormat::Rgba8Unorm,
+        width: width.max(1),
+        height: height.max(1),
+        present_mode: wgpu::PresentMode::Fifo,
+        desired_maximum_frame_latency: 2,
+        alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+        view_formats: vec![],
+    };
+
+    let texture = create_offscreen_texture(&device, &config);
+
+    return Some((instance, texture, config, adapter, device, queue));
+}
+
+fn create_offscreen_texture(device: &Device, config: &SurfaceConfiguration) -> Texture {
+    return device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen render target"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+        view_formats: &[],
+    });
+}
+
+pub enum RenderTarget<'a> {
+    Surface(Surface<'a>),
+    Offscreen(Texture),
 }
 
 pub struct WGPUBackend<'a> {
+    #[allow(dead_code)] // Owns the wgpu context for as long as the backend lives
     instance: Instance,
-    surface: Surface<'a>,
+    target: RenderTarget<'a>,
     config: SurfaceConfiguration,
     adapter: Adapter,
     device: Device,
     queue: Queue,
 }
 
-fn build_wgpu_backed(window: &Window) -> WGPUBackend {
-    let (instance, surface, config, adapter, device, queue) = pollster::block_on(build_backend(&window));
+impl WGPUBackend<'_> {
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.config.width = width.max(1);
+        self.config.height = height.max(1);
 
-    return WGPUBackend {
+        match &mut self.target {
+            RenderTarget::Surface(surface) => surface.configure(&self.device, &self.config),
+            RenderTarget::Offscreen(texture) => *texture = create_offscreen_texture(&self.device, &self.config),
+        }
+    }
+}
+
+fn build_wgpu_backed(window: &Window) -> Option<WGPUBackend<'_>> {
+    let (instance, surface, config, adapter, device, queue) = pollster::block_on(build_backend(window))?;
+
+    return Some(WGPUBackend {
         instance,
-        surface,
+        target: RenderTarget::Surface(surface),
         config,
         adapter,
         device,
         queue,
-    };
+    });
 }
 
+fn build_headless_wgpu_backend(width: u32, height: u32, force_fallback_adapter: bool) -> Option<WGPUBackend<'static>> {
+    let (instance, texture, config, adapter, device, queue) = pollster::block_on(build_headless_backend(width, height, force_fallback_adapter))?;
+
+    return Some(WGPUBackend {
+        instance,
+        target: RenderTarget::Offscreen(texture),
+        config,
+        adapter,
+        device,
+        queue,
+    });
+}
+
+/// Lets the tests needing a GPU adapter pass on machines without one, they fail there otherwise.
+#[cfg(test)]
+const SKIP_GPU_TESTS: &str = "VOX_SKIP_GPU_TESTS";
+
+/// Called by a test that found no adapter: skips it when `VOX_SKIP_GPU_TESTS` is set, fails it otherwise,
+/// so a regression suite never passes without running.
+#[cfg(test)]
+fn no_adapter(test: &str) {
+    if std::env::var_os(SKIP_GPU_TESTS).is_none() {
+        panic!("No adapter available for {}, set {} to skip the tests needing one", test, SKIP_GPU_TESTS);
+    }
+
+    eprintln!("Skipping {}: no adapter available", test);
+}
+
+pub mod headless;
 pub mod logic;
+pub mod mesh;
 pub mod renderer;
+pub mod scene;
+pub mod voxel;
 
 fn main() {
+    if let Some(options) = headless::HeadlessOptions::from_args(std::env::args().skip(1)) {
+        headless::run(options);
+        return;
+    }
+
     let mut event_loop = EventLoop::new().unwrap();
     let builder = WindowBuilder::new();
     let window = builder.with_title("Vox").with_inner_size(LogicalSize::new(1280, 720)).build(&event_loop).unwrap();
@@ -109,10 +244,14 @@ fn main() {
         });
     }
 
-    let mut backend = build_wgpu_backed(&window);
+    let Some(mut backend) = build_wgpu_backed(&window) else {
+        eprintln!("No graphics adapter can draw to the window, not even a software one. Run with --headless to render frames with the CPU ray marcher instead.");
+        std::process::exit(1);
+    };
 
     let mut logic = Logic::new();
     let mut renderer = Renderer::new(&backend, &logic);
+    let mut scene_error = None;
 
     'main: loop {
         let timeout = Some(Duration::ZERO);
@@ -124,17 +263,18 @@ fn main() {
                 } => {
                     match event {
                         WindowEvent::Resized(new_size) => {
-                            backend.config.width = new_size.width.max(1);
-                            backend.config.height = new_size.height.max(1);
-
-                            backend.surface.configure(&backend.device, &backend.config);
+                            backend.resize(new_size.width, new_size.height);
                             renderer.process_resize(&backend, &logic);
                         }
                         WindowEvent::CloseRequested => target.exit(),
+                        WindowEvent::Focused(focused) => logic.process_focus(&window, focused),
                         WindowEvent::KeyboardInput {
                             event,
                             ..
-                        } => logic.process_keyboard(&window, event),
+                        } => {
+                            renderer.process_keyboard(&backend, &mut logic, &event);
+                            logic.process_keyboard(&window, event);
+                        }
                         WindowEvent::MouseInput {
                             state,
                             button,
@@ -144,16 +284,11 @@ fn main() {
                     }
                 }
                 Event::DeviceEvent {
-                    event,
+                    event: DeviceEvent::MouseMotion {
+                        delta: (dx, dy)
+                    },
                     ..
-                } => {
-                    match event {
-                        DeviceEvent::MouseMotion {
-                            delta: (dx, dy)
-                        } => logic.process_mouse_motion((dx as f32, dy as f32)),
-                        _ => {}
-                    }
-                }
+                } => logic.process_mouse_motion(&window, (dx as f32, dy as f32)),
                 _ => {}
             }
         });
@@ -165,7 +300,20 @@ fn main() {
         renderer.render(&backend, &logic);
 
         logic.update(1.0 / 60.0);
-        renderer.update(&backend, &logic);
+        renderer.update(&backend, &mut logic);
+
+        // Reported once per failure, the renderer keeps drawing the last valid scene meanwhile
+        if renderer.scene_error(&logic) != scene_error {
+            scene_error = renderer.scene_error(&logic);
+
+            if let Some(error) = scene_error {
+                eprintln!("Failed to upload the scene, keeping the previous one: {}", error);
+            }
+        }
+
+        for (coord, error) in logic.play.world.take_load_errors() {
+            eprintln!("Failed to load chunk {} from the save, generated it instead: {}", coord, error);
+        }
 
         sleep(Duration::from_millis(16)); // At the moment we just put everything at 60 ticks/per_second
     }
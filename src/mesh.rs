@@ -0,0 +1,217 @@
+use std::{
+    fmt,
+    fmt::Write,
+    fs,
+    io,
+    path::Path
+};
+
+use glam::{
+    Mat4,
+    Vec3,
+    Vec4
+};
+
+pub mod contour;
+pub mod gltf;
+pub mod obj;
+
+/// Binary STL files start with an 80 byte header that readers ignore, as long as it does not start
+/// with `solid` like ASCII files do.
+const STL_HEADER: &[u8] = b"vox binary STL";
+const STL_HEADER_SIZE: usize = 80;
+
+#[derive(Debug)]
+pub enum MeshError {
+    Io(io::Error),
+    /// The extension is not one of a supported format.
+    UnknownFormat,
+    /// A line of an OBJ file that makes no sense, counted from 1.
+    Obj { line: usize, reason: String },
+    Gltf(::gltf::Error),
+    /// A valid glTF file using a feature the loader does not support.
+    Unsupported(String),
+    /// A glTF primitive with a different number of entries in one of its vertex attributes than it
+    /// has positions.
+    AttributeCount { attribute: &'static str, count: usize, positions: usize },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            MeshError::Io(error) => write!(f, "{}", error),
+            MeshError::UnknownFormat => write!(f, "unknown mesh format, expected obj, gltf or glb"),
+            MeshError::Obj { line, reason } => write!(f, "invalid OBJ at line {}: {}", line, reason),
+            MeshError::Gltf(error) => write!(f, "invalid glTF: {}", error),
+            MeshError::Unsupported(reason) => write!(f, "unsupported glTF: {}", reason),
+            MeshError::AttributeCount { attribute, count, positions } => write!(f, "invalid glTF: {} {} for {} positions", count, attribute, positions),
+        };
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+impl From<io::Error> for MeshError {
+    fn from(error: io::Error) -> Self {
+        return MeshError::Io(error);
+    }
+}
+
+/// An indexed triangle mesh with one normal and color per vertex, triangles are counter-clockwise
+/// when seen from outside.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub colors: Vec<[u8; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new() -> Self {
+        return Self {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            indices: Vec::new(),
+        };
+    }
+
+    /// Smooth normals, the average of the normals of the triangles around each vertex weighted by
+    /// their area.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.positions.len()];
+
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| self.positions[index as usize]);
+            let normal = (b - a).cross(c - a);
+
+            for index in triangle {
+                normals[*index as usize] += normal;
+            }
+        }
+
+        self.normals = normals.into_iter().map(|normal| normal.normalize_or_zero()).collect();
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        return self.indices.len() / 3;
+    }
+
+    /// Corner positions of every triangle.
+    pub fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        return self.indices.chunks_exact(3).map(|triangle| {
+            return [
+                self.positions[triangle[0] as usize],
+                self.positions[triangle[1] as usize],
+                self.positions[triangle[2] as usize],
+            ];
+        });
+    }
+
+    /// Wavefront OBJ text, with the vertex normals shared by the faces.
+    pub fn write_obj(&self) -> String {
+        let mut obj = String::new();
+
+        writeln!(obj, "# vox mesh, {} vertices, {} triangles", self.positions.len(), self.triangle_count()).unwrap();
+
+        for position in self.positions.iter() {
+            writeln!(obj, "v {} {} {}", position.x, position.y, position.z).unwrap();
+        }
+
+        for normal in self.normals.iter() {
+            writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z).unwrap();
+        }
+
+        // OBJ indices start at 1
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+
+            writeln!(obj, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c).unwrap();
+        }
+
+        return obj;
+    }
+
+    /// Binary STL, which only stores triangles with a face normal, as expected by slicers.
+    pub fn write_stl(&self) -> Vec<u8> {
+        let mut bytes = Vec::<u8>::with_capacity(STL_HEADER_SIZE + 4 + self.triangle_count() * 50);
+
+        bytes.extend_from_slice(STL_HEADER);
+        bytes.resize(STL_HEADER_SIZE, 0);
+        bytes.extend_from_slice(&(self.triangle_count() as u32).to_le_bytes());
+
+        for [a, b, c] in self.triangles() {
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+
+            for vector in [normal, a, b, c] {
+                for component in vector.to_array() {
+                    bytes.extend_from_slice(&component.to_le_bytes());
+                }
+            }
+
+            // Attribute byte count, unused
+            bytes.extend_from_slice(&0u16.to_le_bytes());
+        }
+
+        return bytes;
+    }
+}
+
+/// A mesh placed in the world by its own model matrix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshObject {
+    pub name: String,
+    pub mesh: Mesh,
+    pub transform: Mat4,
+}
+
+/// OBJ and glTF files are Y up, like the tools exporting them, the world is Z up.
+pub fn y_up_to_z_up() -> Mat4 {
+    return Mat4::from_cols(Vec4::X, Vec4::Z, Vec4::NEG_Y, Vec4::W);
+}
+
+/// Loads the objects of an OBJ, glTF or binary glTF file, depending on its extension.
+pub fn load(path: &Path) -> Result<Vec<MeshObject>, MeshError> {
+    return match path.extension().and_then(|extension| extension.to_str()) {
+        Some("obj") => obj::parse(&fs::read_to_string(path)?),
+        Some("gltf") | Some("glb") => gltf::parse(&fs::read(path)?, path.parent()),
+        _ => Err(MeshError::UnknownFormat),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::Mesh;
+
+    fn triangle() -> Mesh {
+        return Mesh {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::new(0.0, 0.5, 0.0)],
+            normals: vec![Vec3::Z; 3],
+            colors: vec![[255; 4]; 3],
+            indices: vec![0, 1, 2],
+        };
+    }
+
+    #[test]
+    fn meshes_export_as_obj_and_binary_stl() {
+        let mesh = triangle();
+
+        let obj = mesh.write_obj();
+        let lines = obj.lines().skip(1).collect::<Vec<_>>();
+
+        assert_eq!(lines, ["v 0 0 0", "v 1 0 0", "v 0 0.5 0", "vn 0 0 1", "vn 0 0 1", "vn 0 0 1", "f 1//1 2//2 3//3"]);
+
+        let stl = mesh.write_stl();
+
+        assert_eq!(stl.len(), 80 + 4 + 50);
+        assert!(!stl.starts_with(b"solid"));
+        assert_eq!(u32::from_le_bytes(stl[80..84].try_into().unwrap()), 1);
+
+        let floats = stl[84..132].chunks_exact(4).map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())).collect::<Vec<_>>();
+
+        assert_eq!(floats, [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.5, 0.0]);
+    }
+}
\ No newline at end of file
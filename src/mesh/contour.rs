@@ -0,0 +1,284 @@
+use glam::{
+    Mat3,
+    UVec3,
+    Vec3
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    mesh::Mesh,
+    scene::evaluate::{
+        sd_box,
+        SceneEvaluator,
+        NORMAL_EPSILON
+    }
+};
+
+/// Pulls cell vertices towards the mass point of their edge crossings, so the solve stays stable on
+/// flat cells where the tangent planes do not pin down a single point.
+const MASS_POINT_WEIGHT: f32 = 0.05;
+
+/// Marks cells the surface does not cross.
+const NO_VERTEX: u32 = u32::MAX;
+
+/// Distance field of a scene intersected with a box, so meshes of unbounded scenes such as floor
+/// planes are still closed.
+struct ClippedField<'a> {
+    evaluator: &'a SceneEvaluator,
+    center: Vec3,
+    half_extents: Vec3,
+}
+
+impl ClippedField<'_> {
+    fn distance(&self, p: Vec3) -> f32 {
+        return self.evaluator.distance(p).max(sd_box(p - self.center, self.half_extents.extend(0.0)));
+    }
+
+    /// Central differences, like `SceneEvaluator::normal` but of the clipped field.
+    fn gradient(&self, p: Vec3) -> Vec3 {
+        let mut gradient = Vec3::ZERO;
+
+        for axis in 0..3 {
+            let mut e = Vec3::ZERO;
+            e[axis] = NORMAL_EPSILON;
+
+            gradient[axis] = self.distance(p + e) - self.distance(p - e);
+        }
+
+        return gradient.normalize_or_zero();
+    }
+}
+
+/// Distances sampled at the corners of a grid of cubic cells.
+struct Grid {
+    origin: Vec3,
+    cell_size: f32,
+    cells: UVec3,
+    distances: Vec<f32>,
+}
+
+impl Grid {
+    fn sample(field: &ClippedField, origin: Vec3, cell_size: f32, cells: UVec3) -> Self {
+        let corners = cells + UVec3::ONE;
+
+        let distances = (0..corners.x * corners.y * corners.z).into_par_iter().map(|index| {
+            let corner = UVec3::new(index % corners.x, index / corners.x % corners.y, index / (corners.x * corners.y));
+
+            return field.distance(origin + corner.as_vec3() * cell_size);
+        }).collect();
+
+        return Self {
+            origin,
+            cell_size,
+            cells,
+            distances,
+        };
+    }
+
+    fn position(&self, corner: UVec3) -> Vec3 {
+        return self.origin + corner.as_vec3() * self.cell_size;
+    }
+
+    fn distance(&self, corner: UVec3) -> f32 {
+        let corners = self.cells + UVec3::ONE;
+
+        return self.distances[(corner.x + corners.x * (corner.y + corners.y * corner.z)) as usize];
+    }
+
+    fn cell_index(&self, cell: UVec3) -> usize {
+        return (cell.x + self.cells.x * (cell.y + self.cells.y * cell.z)) as usize;
+    }
+}
+
+fn unit(axis: usize) -> UVec3 {
+    let mut unit = UVec3::ZERO;
+    unit[axis] = 1;
+
+    return unit;
+}
+
+/// Places the vertex of a cell the surface crosses where the tangent planes at its edge crossings
+/// meet, which keeps sharp edges and corners, or returns `None` when the surface misses the cell.
+fn cell_vertex(field: &ClippedField, grid: &Grid, cell: UVec3) -> Option<Vec3> {
+    let mut normals = Mat3::ZERO;
+    let mut crossings = Vec::<(Vec3, Vec3)>::with_capacity(12);
+
+    for axis in 0..3 {
+        let (u, v) = (unit((axis + 1) % 3), unit((axis + 2) % 3));
+
+        for offset in [UVec3::ZERO, u, u + v, v] {
+            let (a, b) = (cell + offset, cell + offset + unit(axis));
+            let (distance_a, distance_b) = (grid.distance(a), grid.distance(b));
+
+            if (distance_a < 0.0) == (distance_b < 0.0) {
+                continue;
+            }
+
+            let point = grid.position(a).lerp(grid.position(b), distance_a / (distance_a - distance_b));
+            let normal = field.gradient(point);
+
+            normals += Mat3::from_cols(normal * normal.x, normal * normal.y, normal * normal.z);
+            crossings.push((point, normal));
+        }
+    }
+
+    if crossings.is_empty() {
+        return None;
+    }
+
+    // Least squares distance to the tangent planes, solved around the mass point of the crossings
+    let mass_point = crossings.iter().map(|(point, _)| *point).sum::<Vec3>() / crossings.len() as f32;
+    let offsets = crossings.iter().map(|(point, normal)| *normal * normal.dot(*point - mass_point)).sum::<Vec3>();
+
+    let vertex = mass_point + (normals + Mat3::from_diagonal(Vec3::splat(MASS_POINT_WEIGHT))).inverse() * offsets;
+
+    let cell_min = grid.position(cell);
+
+    return Some(vertex.clamp(cell_min, cell_min + Vec3::splat(grid.cell_size)));
+}
+
+/// Extracts the surface of a scene inside `min..max` with dual contouring, `resolution` being the
+/// number of cells along the longest side of the bounds. Normals come from the gradient of the
+/// distance field, and the surface is closed along the bounds so meshes can be printed.
+pub fn contour(evaluator: &SceneEvaluator, min: Vec3, max: Vec3, resolution: u32) -> Mesh {
+    let field = ClippedField {
+        evaluator,
+        center: (min + max) * 0.5,
+        half_extents: (max - min) * 0.5,
+    };
+
+    // One more cell on each side, so the corners around the bounds are all outside the clipped field
+    let cell_size = (max - min).max_element() / resolution.max(1) as f32;
+    let cells = ((max - min) / cell_size).ceil().as_uvec3().max(UVec3::ONE) + UVec3::splat(2);
+
+    let grid = Grid::sample(&field, min - Vec3::splat(cell_size), cell_size, cells);
+
+    let mut mesh = Mesh::new();
+    let mut cell_vertices = vec![NO_VERTEX; (cells.x * cells.y * cells.z) as usize];
+
+    for z in 0..cells.z {
+        for y in 0..cells.y {
+            for x in 0..cells.x {
+                let cell = UVec3::new(x, y, z);
+
+                if let Some(vertex) = cell_vertex(&field, &grid, cell) {
+                    cell_vertices[grid.cell_index(cell)] = mesh.positions.len() as u32;
+
+                    mesh.positions.push(vertex);
+                    mesh.normals.push(field.gradient(vertex));
+                    mesh.colors.push([255; 4]);
+                }
+            }
+        }
+    }
+
+    // Every grid edge the surface crosses becomes a quad joining the vertices of its four cells
+    for z in 0..=cells.z {
+        for y in 0..=cells.y {
+            for x in 0..=cells.x {
+                let corner = UVec3::new(x, y, z);
+
+                for axis in 0..3 {
+                    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+                    if corner[axis] == cells[axis] || corner[u] == 0 || corner[v] == 0 || corner[u] == cells[u] || corner[v] == cells[v] {
+                        continue;
+                    }
+
+                    let inside = grid.distance(corner) < 0.0;
+
+                    if inside == (grid.distance(corner + unit(axis)) < 0.0) {
+                        continue;
+                    }
+
+                    // Counter-clockwise around the edge when seen from its positive end
+                    let base = corner - unit(u) - unit(v);
+                    let mut quad = [base, base + unit(u), base + unit(u) + unit(v), base + unit(v)].map(|cell| cell_vertices[grid.cell_index(cell)]);
+
+                    // The surface faces away from the inside, towards the positive end when the edge starts inside
+                    if !inside {
+                        quad.reverse();
+                    }
+
+                    mesh.indices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+                }
+            }
+        }
+    }
+
+    return mesh;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::Vec3;
+
+    use crate::{
+        mesh::Mesh,
+        scene::{
+            evaluate::SceneEvaluator,
+            Scene,
+            SceneNode
+        }
+    };
+
+    use super::contour;
+
+    /// Every edge is shared by exactly two triangles going around it in opposite directions.
+    fn assert_closed(mesh: &Mesh) {
+        let mut edges = HashMap::<(u32, u32), u32>::new();
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            for i in 0..3 {
+                *edges.entry((triangle[i], triangle[(i + 1) % 3])).or_default() += 1;
+            }
+        }
+
+        for (&(a, b), &count) in edges.iter() {
+            assert_eq!(count, 1, "Edge {} {} is used {} times", a, b, count);
+            assert_eq!(edges.get(&(b, a)), Some(&1), "Edge {} {} has no opposite", a, b);
+        }
+    }
+
+    #[test]
+    fn spheres_contour_to_closed_meshes_on_their_surface() {
+        let evaluator = SceneEvaluator::new(&Scene::new()).unwrap();
+        let mesh = contour(&evaluator, Vec3::splat(-1.5), Vec3::splat(1.5), 16);
+
+        assert!(mesh.triangle_count() > 500);
+        assert_closed(&mesh);
+
+        for (position, normal) in mesh.positions.iter().zip(mesh.normals.iter()) {
+            assert!((position.length() - 1.0).abs() < 0.02, "Vertex {} is off the sphere", position);
+            assert!(normal.dot(position.normalize()) > 0.99, "Normal {} at {} does not point outwards", normal, position);
+        }
+
+        // Counter-clockwise seen from outside
+        for [a, b, c] in mesh.triangles() {
+            assert!((b - a).cross(c - a).dot(a + b + c) > 0.0);
+        }
+    }
+
+    #[test]
+    fn unbounded_scenes_are_closed_along_the_bounds() {
+        let scene = Scene {
+            root: SceneNode::plane(Vec3::Z, 0.25),
+            ..Scene::new()
+        };
+
+        let evaluator = SceneEvaluator::new(&scene).unwrap();
+        let mesh = contour(&evaluator, Vec3::splat(-1.0), Vec3::splat(1.0), 8);
+
+        assert_closed(&mesh);
+
+        // A slab below the plane, with sharp corners at the bounds
+        let min = mesh.positions.iter().fold(Vec3::INFINITY, |min, position| min.min(*position));
+        let max = mesh.positions.iter().fold(Vec3::NEG_INFINITY, |max, position| max.max(*position));
+
+        assert!(min.abs_diff_eq(Vec3::splat(-1.0), 1e-3), "Mesh starts at {}", min);
+        assert!(max.abs_diff_eq(Vec3::new(1.0, 1.0, 0.25), 1e-3), "Mesh ends at {}", max);
+    }
+}
\ No newline at end of file
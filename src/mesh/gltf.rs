@@ -0,0 +1,257 @@
+use std::{
+    fs,
+    path::Path
+};
+
+use glam::{
+    Mat4,
+    Vec3,
+    Vec4
+};
+
+use gltf::{
+    buffer::Source,
+    mesh::Mode,
+    Gltf,
+    Node
+};
+
+use crate::mesh::{
+    y_up_to_z_up,
+    Mesh,
+    MeshError,
+    MeshObject
+};
+
+/// Marks buffers embedded in the file as base64 data URIs.
+const BASE64_DATA: &str = ";base64,";
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut bit_count) = (0u32, 0);
+
+    for character in text.bytes().take_while(|character| *character != b'=') {
+        let value = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+
+    return Some(bytes);
+}
+
+/// Contents of every buffer: the binary chunk of a .glb, data URIs, or files next to the .gltf.
+fn load_buffers(gltf: &Gltf, base: Option<&Path>) -> Result<Vec<Vec<u8>>, MeshError> {
+    let mut buffers = Vec::new();
+
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            Source::Bin => gltf.blob.clone().ok_or_else(|| MeshError::Unsupported(String::from("binary buffer outside of a .glb")))?,
+            Source::Uri(uri) if uri.starts_with("data:") => {
+                let (_, encoded) = uri.split_once(BASE64_DATA).ok_or_else(|| MeshError::Unsupported(String::from("data URI not in base64")))?;
+
+                decode_base64(encoded).ok_or_else(|| MeshError::Unsupported(String::from("invalid base64 in a data URI")))?
+            }
+            Source::Uri(uri) => {
+                let base = base.ok_or_else(|| MeshError::Unsupported(format!("external buffer {:?} without a directory to load it from", uri)))?;
+
+                fs::read(base.join(uri))?
+            }
+        };
+
+        if data.len() < buffer.length() {
+            return Err(MeshError::Unsupported(format!("buffer {} holds {} bytes, {} expected", buffer.index(), data.len(), buffer.length())));
+        }
+
+        buffers.push(data);
+    }
+
+    return Ok(buffers);
+}
+
+/// Merges the primitives of a mesh, colored by their vertex colors times their base color.
+fn read_mesh(mesh: gltf::Mesh, buffers: &[Vec<u8>]) -> Result<Mesh, MeshError> {
+    let mut merged = Mesh::new();
+
+    for primitive in mesh.primitives() {
+        if primitive.mode() != Mode::Triangles {
+            return Err(MeshError::Unsupported(format!("primitives drawn as {:?}", primitive.mode())));
+        }
+
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let Some(positions) = reader.read_positions() else {
+            return Err(MeshError::Unsupported(String::from("primitives without positions")));
+        };
+
+        let mut part = Mesh::new();
+        part.positions = positions.map(Vec3::from_array).collect();
+
+        let base_color = Vec4::from_array(primitive.material().pbr_metallic_roughness().base_color_factor());
+
+        part.colors = match reader.read_colors(0) {
+            Some(colors) => colors.into_rgba_f32().map(|color| Vec4::from_array(color) * base_color).collect(),
+            None => vec![base_color; part.positions.len()],
+        }.into_iter().map(|color| (color.clamp(Vec4::ZERO, Vec4::ONE) * 255.0).round().to_array().map(|channel| channel as u8)).collect();
+
+        part.indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..part.positions.len() as u32).collect(),
+        };
+
+        if part.indices.iter().any(|index| *index as usize >= part.positions.len()) {
+            return Err(MeshError::Unsupported(String::from("indices past the last vertex")));
+        }
+
+        match reader.read_normals() {
+            Some(normals) => part.normals = normals.map(Vec3::from_array).collect(),
+            None => part.compute_normals(),
+        }
+
+        // Vertices are uploaded by indexing every attribute with the position index
+        for (attribute, count) in [("colors", part.colors.len()), ("normals", part.normals.len())] {
+            if count != part.positions.len() {
+                return Err(MeshError::AttributeCount { attribute, count, positions: part.positions.len() });
+            }
+        }
+
+        let offset = merged.positions.len() as u32;
+
+        merged.positions.extend(part.positions);
+        merged.normals.extend(part.normals);
+        merged.colors.extend(part.colors);
+        merged.indices.extend(part.indices.into_iter().map(|index| index + offset));
+    }
+
+    return Ok(merged);
+}
+
+fn visit(node: Node, parent: Mat4, buffers: &[Vec<u8>], objects: &mut Vec<MeshObject>) -> Result<(), MeshError> {
+    let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        let name = node.name().or(mesh.name()).map_or_else(|| format!("node {}", node.index()), String::from);
+
+        objects.push(MeshObject {
+            name,
+            mesh: read_mesh(mesh, buffers)?,
+            transform,
+        });
+    }
+
+    for child in node.children() {
+        visit(child, transform, buffers, objects)?;
+    }
+
+    return Ok(());
+}
+
+/// Parses a glTF 2.0 file, JSON or binary, into one object per node with a mesh, placed by the
+/// transforms of the node and its ancestors in the default scene. External buffers are read from
+/// `base`, images are ignored.
+pub fn parse(bytes: &[u8], base: Option<&Path>) -> Result<Vec<MeshObject>, MeshError> {
+    let gltf = Gltf::from_slice(bytes).map_err(MeshError::Gltf)?;
+    let buffers = load_buffers(&gltf, base)?;
+
+    let Some(scene) = gltf.default_scene().or_else(|| gltf.scenes().next()) else {
+        return Ok(Vec::new());
+    };
+
+    let mut objects = Vec::new();
+
+    for node in scene.nodes() {
+        visit(node, y_up_to_z_up(), &buffers, &mut objects)?;
+    }
+
+    return Ok(objects);
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{
+        Mat4,
+        Vec3
+    };
+
+    use crate::mesh::{
+        y_up_to_z_up,
+        MeshError
+    };
+
+    use super::{
+        decode_base64,
+        parse
+    };
+
+    /// A triangle under a translated parent, scaled by its own node. The buffer holds three positions
+    /// then three u32 indices.
+    const NESTED_TRIANGLE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "name": "parent", "translation": [1, 0, 0], "children": [1] },
+            { "name": "child", "scale": [2, 2, 2], "mesh": 0 }
+        ],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 }] }],
+        "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [1, 0.5, 0, 1] } }],
+        "buffers": [{ "byteLength": 48, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAEAAAACAAAA" }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 12 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5125, "count": 3, "type": "SCALAR" }
+        ]
+    }"#;
+
+    #[test]
+    fn nodes_are_placed_by_their_ancestors() {
+        let objects = parse(NESTED_TRIANGLE.as_bytes(), None).unwrap();
+
+        assert_eq!(objects.len(), 1);
+
+        let object = &objects[0];
+
+        assert_eq!(object.name, "child");
+        assert_eq!(object.transform, y_up_to_z_up() * Mat4::from_translation(Vec3::X) * Mat4::from_scale(Vec3::splat(2.0)));
+        assert_eq!(object.mesh.positions, [Vec3::ZERO, Vec3::X, Vec3::Y]);
+        assert_eq!(object.mesh.indices, [0, 1, 2]);
+        assert_eq!(object.mesh.normals, [Vec3::Z; 3]);
+        assert_eq!(object.mesh.colors, [[255, 128, 0, 255]; 3]);
+    }
+
+    #[test]
+    fn attributes_must_match_the_positions() {
+        // Two normals read from the start of the positions, for three vertices
+        let short_normals = NESTED_TRIANGLE
+            .replace(r#""attributes": { "POSITION": 0 }"#, r#""attributes": { "POSITION": 0, "NORMAL": 2 }"#)
+            .replace(r#""type": "SCALAR" }"#, r#""type": "SCALAR" },
+            { "bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3" }"#);
+
+        let error = parse(short_normals.as_bytes(), None).expect_err("Short normals should be rejected");
+
+        assert!(matches!(error, MeshError::AttributeCount { attribute: "normals", count: 2, positions: 3 }), "{}", error);
+    }
+
+    #[test]
+    fn base64_decodes_with_and_without_padding() {
+        assert_eq!(decode_base64("aGVsbG8="), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64("aGVsbG8h"), Some(b"hello!".to_vec()));
+        assert_eq!(decode_base64("aGV$"), None);
+    }
+}
\ No newline at end of file
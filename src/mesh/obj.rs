@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::mesh::{
+    y_up_to_z_up,
+    Mesh,
+    MeshError,
+    MeshObject
+};
+
+/// Corner of a face, the position and normal indices of an `f` statement.
+type Corner = (usize, Option<usize>);
+
+/// Object being read, from its `o` or `g` statement to the next one.
+struct ObjectBuilder {
+    name: String,
+    mesh: Mesh,
+    vertices: HashMap<Corner, u32>,
+    /// Whether every corner so far came with a normal, otherwise they are computed.
+    has_normals: bool,
+}
+
+impl ObjectBuilder {
+    fn new(name: String) -> Self {
+        return Self {
+            name,
+            mesh: Mesh::new(),
+            vertices: HashMap::new(),
+            has_normals: true,
+        };
+    }
+
+    /// Index of the vertex of a corner, corners sharing both indices share a vertex.
+    fn vertex(&mut self, corner: Corner, positions: &[Vec3], colors: &[[u8; 4]], normals: &[Vec3]) -> u32 {
+        let (position, normal) = corner;
+
+        self.has_normals &= normal.is_some();
+
+        return *self.vertices.entry(corner).or_insert_with(|| {
+            self.mesh.positions.push(positions[position]);
+            self.mesh.colors.push(colors[position]);
+            self.mesh.normals.push(normal.map_or(Vec3::ZERO, |normal| normals[normal]));
+
+            return self.mesh.positions.len() as u32 - 1;
+        });
+    }
+
+    fn finish(mut self) -> Option<MeshObject> {
+        if self.mesh.indices.is_empty() {
+            return None;
+        }
+
+        if !self.has_normals {
+            self.mesh.compute_normals();
+        }
+
+        return Some(MeshObject {
+            name: self.name,
+            mesh: self.mesh,
+            transform: y_up_to_z_up(),
+        });
+    }
+}
+
+fn parse_floats(arguments: &[&str]) -> Result<Vec<f32>, String> {
+    return arguments.iter()
+        .map(|argument| argument.parse::<f32>().map_err(|_| format!("invalid number {:?}", argument)))
+        .collect();
+}
+
+/// Resolves a 1-based index, or a negative one counting back from the last element read so far.
+fn resolve(index: &str, count: usize) -> Result<usize, String> {
+    let value = index.parse::<i64>().map_err(|_| format!("invalid index {:?}", index))?;
+
+    let resolved = match value {
+        1.. => value - 1,
+        ..=-1 => count as i64 + value,
+        0 => -1,
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} out of range, {} defined", value, count));
+    }
+
+    return Ok(resolved as usize);
+}
+
+/// Parses a corner in any of the `v`, `v/vt`, `v//vn` or `v/vt/vn` forms.
+fn parse_corner(argument: &str, position_count: usize, normal_count: usize) -> Result<Corner, String> {
+    let mut parts = argument.split('/');
+
+    let position = resolve(parts.next().unwrap_or_default(), position_count)?;
+    let normal = match parts.nth(1) {
+        Some(normal) if !normal.is_empty() => Some(resolve(normal, normal_count)?),
+        _ => None,
+    };
+
+    return Ok((position, normal));
+}
+
+/// Parses a Wavefront OBJ file into one object per `o` or `g` statement. Vertex colors come from the
+/// common `v x y z r g b` extension, vertices without one are white. Polygons are split into fans of
+/// triangles, and objects without normals get smooth ones.
+pub fn parse(text: &str) -> Result<Vec<MeshObject>, MeshError> {
+    let mut positions = Vec::<Vec3>::new();
+    let mut colors = Vec::<[u8; 4]>::new();
+    let mut normals = Vec::<Vec3>::new();
+
+    let mut objects = Vec::new();
+    let mut object = ObjectBuilder::new(String::from("default"));
+
+    for (index, line) in text.lines().enumerate() {
+        let error = |reason: String| MeshError::Obj { line: index + 1, reason };
+
+        let content = line.split('#').next().unwrap_or_default();
+        let mut tokens = content.split_whitespace();
+
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        let arguments = tokens.collect::<Vec<_>>();
+
+        match keyword {
+            "v" => {
+                let values = parse_floats(&arguments).map_err(error)?;
+
+                // An optional w, or the color extension
+                let color = match values.len() {
+                    3 | 4 => [255; 3],
+                    6 => [values[3], values[4], values[5]].map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8),
+                    count => return Err(error(format!("expected 3, 4 or 6 values, found {}", count))),
+                };
+
+                positions.push(Vec3::new(values[0], values[1], values[2]));
+                colors.push([color[0], color[1], color[2], 255]);
+            }
+            "vn" => {
+                let values = parse_floats(&arguments).map_err(error)?;
+
+                if values.len() != 3 {
+                    return Err(error(format!("expected 3 values, found {}", values.len())));
+                }
+
+                normals.push(Vec3::new(values[0], values[1], values[2]).normalize_or_zero());
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(format!("faces need 3 corners, found {}", arguments.len())));
+                }
+
+                let corners = arguments.iter()
+                    .map(|argument| parse_corner(argument, positions.len(), normals.len()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+
+                let vertices = corners.into_iter().map(|corner| object.vertex(corner, &positions, &colors, &normals)).collect::<Vec<_>>();
+
+                for i in 1..vertices.len() - 1 {
+                    object.mesh.indices.extend_from_slice(&[vertices[0], vertices[i], vertices[i + 1]]);
+                }
+            }
+            "o" | "g" => {
+                let next = ObjectBuilder::new(arguments.join(" "));
+
+                objects.extend(std::mem::replace(&mut object, next).finish());
+            }
+            // Texture coordinates, materials, smoothing groups and lines are not drawn
+            _ => {}
+        }
+    }
+
+    objects.extend(object.finish());
+
+    return Ok(objects);
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::mesh::{
+        y_up_to_z_up,
+        MeshError
+    };
+
+    use super::parse;
+
+    const TWO_OBJECTS: &str = "
+# A colored quad with normals, then a triangle without
+v 0 0 0 1 0 0
+v 1 0 0 0 1 0
+v 1 1 0 0 0 1
+v 0 1 0
+vn 0 0 2
+o quad
+f 1//1 2//1 3//1 4//1
+o triangle
+v 0 0 1
+f -1 1/5 2
+";
+
+    #[test]
+    fn objects_are_read_with_colors_and_normals() {
+        let objects = parse(TWO_OBJECTS).unwrap();
+
+        assert_eq!(objects.len(), 2);
+
+        let quad = &objects[0];
+
+        assert_eq!(quad.name, "quad");
+        assert_eq!(quad.transform, y_up_to_z_up());
+        assert_eq!(quad.mesh.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(quad.mesh.colors, [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255; 4]]);
+        assert_eq!(quad.mesh.normals, [Vec3::Z; 4]);
+
+        let triangle = &objects[1];
+
+        assert_eq!(triangle.name, "triangle");
+        assert_eq!(triangle.mesh.positions, [Vec3::Z, Vec3::ZERO, Vec3::X]);
+        assert_eq!(triangle.mesh.normals, [Vec3::NEG_Y; 3]);
+    }
+
+    #[test]
+    fn invalid_statements_report_their_line() {
+        for (text, line) in [("v 0 0 0\nv 1 0 0\nf 1 2 3", 3), ("\n\nv 1 x 0", 3), ("v 0 0 0\nf 1 1", 2), ("v 0 0 0\nf 1//1 1 1", 2)] {
+            match parse(text) {
+                Err(MeshError::Obj { line: reported, .. }) => assert_eq!(reported, line, "{:?}", text),
+                other => panic!("Expected an error at line {} of {:?}, got {:?}", line, text, other),
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -1,91 +1,224 @@
-use wgpu::{
-    LoadOp,
-    Operations,
-    RenderPassColorAttachment,
-    RenderPassDescriptor,
-    util::DeviceExt
+use winit::{
+    event::{
+        ElementState,
+        KeyEvent
+    },
+    keyboard::{
+        KeyCode,
+        PhysicalKey
+    }
 };
 
 use crate::{
     logic::Logic,
+    renderer::{
+        graph::{
+            Pass,
+            RenderGraph,
+            SURFACE
+        },
+        registry::{
+            Pipeline,
+            PipelineRegistry
+        }
+    },
+    scene::SceneError,
+    RenderTarget,
     WGPUBackend
 };
 
-use crate::logic::{
-    LogicState,
-    play::PipelineType
-};
+use crate::logic::LogicState;
 
+pub mod brickmap_ray_marcher;
+pub mod capture;
+pub mod graph;
+pub mod octree_ray_marcher;
 pub mod pipeline;
 pub mod rasterizer;
 pub mod ray_marcher;
+pub mod registry;
+pub mod software_ray_marcher;
+pub mod voxel_ray_marcher;
+pub mod world_rasterizer;
+pub mod world_ray_marcher;
+
+#[cfg(test)]
+mod golden;
+#[cfg(test)]
+pub mod probe;
 
 pub struct Renderer {
-    rasterizer: rasterizer::TestRasterizer,
-    ray_marcher: ray_marcher::TestRayMarcher,
+    pipelines: PipelineRegistry,
+
+    graph: RenderGraph<Renderer>,
 }
 
+/// Pass drawing the selected pipeline, and the depth buffer it shares between ray-marched and
+/// rasterized geometry.
+const SCENE_PASS: &str = "scene";
+const DEPTH: &str = "depth";
+
 impl Renderer {
     pub fn new(wgpu_backend: &WGPUBackend, logic: &Logic) -> Self {
-        let rasterizer = rasterizer::TestRasterizer::new(wgpu_backend, &logic.play);
-        let ray_marcher = ray_marcher::TestRayMarcher::new(wgpu_backend, &logic.play);
+        let play = &logic.play;
+
+        // Only the selected pipeline is created, the others once they are selected
+        let mut pipelines = Self::registry();
+
+        if !pipelines.build(&play.pipeline, wgpu_backend, play) {
+            panic!("Unknown pipeline {:?}, expected one of {}", play.pipeline, pipelines.names().join(", "));
+        }
+
+        let clear_color = pipelines.layers(&play.pipeline).and_then(|mut active| active.next()).expect("Pipelines have at least one layer").clear_color();
+
+        let graph = Self::build_graph(wgpu_backend, clear_color);
 
         return Self {
-            rasterizer,
-            ray_marcher,
+            pipelines,
+
+            graph,
+        };
+    }
+
+    /// Every pipeline the renderer can draw with, none of them created yet. The names are known
+    /// without an adapter, to check the pipeline asked for before starting.
+    pub fn registry() -> PipelineRegistry {
+        let mut pipelines = PipelineRegistry::new();
+        pipelines.register::<rasterizer::TestRasterizer>("rasterizer", Some(KeyCode::KeyE));
+        pipelines.register::<ray_marcher::TestRayMarcher>("ray-marcher", Some(KeyCode::KeyR));
+        pipelines.register::<software_ray_marcher::SoftwareRayMarcher>("software", Some(KeyCode::KeyT));
+        pipelines.register::<voxel_ray_marcher::VoxelRayMarcher>("voxel", Some(KeyCode::KeyY));
+        pipelines.register::<octree_ray_marcher::OctreeRayMarcher>("octree", Some(KeyCode::KeyU));
+        pipelines.register::<brickmap_ray_marcher::BrickmapRayMarcher>("brickmap", Some(KeyCode::KeyI));
+        pipelines.register::<world_ray_marcher::WorldRayMarcher>("world", Some(KeyCode::KeyO));
+        pipelines.register::<world_rasterizer::WorldRasterizer>("world-raster", Some(KeyCode::KeyP));
+
+        // The ray-marched scene and the meshes in one frame, sharing the depth buffer: the ray marcher
+        // covers every pixel and sets its depth, meshes are then depth tested against it
+        pipelines.register_layers("hybrid", Some(KeyCode::KeyH), &["ray-marcher", "rasterizer"]);
+
+        return pipelines;
+    }
+
+    fn build_graph(wgpu_backend: &WGPUBackend, clear_color: wgpu::Color) -> RenderGraph<Renderer> {
+        let mut graph = RenderGraph::new();
+        graph.add_texture(DEPTH, pipeline::DEPTH_FORMAT);
+
+        // Every pipeline draws into the depth buffer, the full screen ones without testing or writing it
+        graph.add_pass(Pass::new(SCENE_PASS, Self::record_scene)
+            .with_color(SURFACE, Some(clear_color))
+            .with_depth(DEPTH, Some(1.0)));
+
+        graph.compile().expect("The render graph is valid");
+        graph.resize(wgpu_backend);
+
+        return graph;
+    }
+
+    /// The pipelines selected by `Play`, which `new` checked is registered and `update` creates, bottom
+    /// first.
+    fn active(&self, logic: &Logic) -> impl Iterator<Item = &dyn Pipeline> + '_ {
+        return self.pipelines.layers(&logic.play.pipeline).expect("The selected pipeline is registered");
+    }
+
+    /// Background of the bottom layer, the others draw over it.
+    fn clear_color(&self, logic: &Logic) -> wgpu::Color {
+        return self.active(logic).next().expect("Pipelines have at least one layer").clear_color();
+    }
+
+    fn record_scene<'a>(&'a self, logic: &'a Logic, pass: &mut wgpu::RenderPass<'a>) {
+        match logic.state {
+            LogicState::Playing => {
+                for pipeline in self.active(logic) {
+                    pipeline.render(pass);
+                }
+            }
+            LogicState::Menu => {},
+        }
+    }
+
+    /// Selects a pipeline by its key, or the next one with `registry::CYCLE_KEY`, and updates it so its
+    /// first frame shows the current `Play`, creating it on its first selection.
+    pub fn process_keyboard(&mut self, wgpu_backend: &WGPUBackend, logic: &mut Logic, key_event: &KeyEvent) {
+        if logic.state != LogicState::Playing || key_event.state != ElementState::Pressed || key_event.repeat {
+            return;
+        }
+
+        let PhysicalKey::Code(key) = key_event.physical_key else {
+            return;
+        };
+
+        let Some(name) = self.pipelines.select(&logic.play.pipeline, key) else {
+            return;
         };
+
+        if name != logic.play.pipeline {
+            logic.play.select_pipeline(name);
+
+            self.update(wgpu_backend, logic);
+        }
+    }
+
+    /// Updates the selected pipeline only, the others catch up with `Play` once selected.
+    pub fn update(&mut self, wgpu_backend: &WGPUBackend, logic: &mut Logic) {
+        // Only stream chunks while the world is shown, the window follows the camera anyway
+        if self.draws_world(logic) {
+            logic.play.world.stream(logic.play.camera.position);
+        }
+
+        self.pipelines.update(&logic.play.pipeline, wgpu_backend, &logic.play);
+
+        self.graph.set_clear_color(SCENE_PASS, self.clear_color(logic));
     }
 
-    pub fn update(&mut self, wgpu_backend: &WGPUBackend, logic: &Logic) {
-        self.rasterizer.update(wgpu_backend, &logic.play);
-        self.ray_marcher.update(wgpu_backend, &logic.play);
+    /// Work the selected pipeline has left, such as chunks waiting for an upload or a mesh.
+    pub fn pending(&self, logic: &Logic) -> usize {
+        return self.active(logic).map(|pipeline| pipeline.pending()).sum();
+    }
+
+    /// Triangles the selected pipeline draws, to compare rasterizing with ray marching.
+    pub fn triangle_count(&self, logic: &Logic) -> Option<u32> {
+        return self.active(logic).filter_map(|pipeline| pipeline.triangle_count()).reduce(|a, b| a + b);
+    }
+
+    pub fn draws_world(&self, logic: &Logic) -> bool {
+        return self.active(logic).any(|pipeline| pipeline.draws_world());
+    }
+
+    /// Why the selected pipeline could not upload the scene, if it could not.
+    pub fn scene_error(&self, logic: &Logic) -> Option<SceneError> {
+        return self.active(logic).find_map(|pipeline| pipeline.scene_error());
     }
 
     pub fn process_resize(&mut self, wgpu_backend: &WGPUBackend, logic: &Logic) {
-        self.ray_marcher.process_resize(wgpu_backend, &logic.play);
+        self.graph.resize(wgpu_backend);
+        self.pipelines.resize(wgpu_backend, &logic.play);
+
+        // The others catch up once selected, so hidden pipelines do no work per resize
+        self.pipelines.update(&logic.play.pipeline, wgpu_backend, &logic.play);
     }
 
     pub fn render(&self, wgpu_backend: &WGPUBackend, logic: &Logic) {
-        let frame = wgpu_backend.surface.get_current_texture().expect("Failed to acquire next swap chain texture");
-        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let frame = match &wgpu_backend.target {
+            RenderTarget::Surface(surface) => Some(surface.get_current_texture().expect("Failed to acquire next swap chain texture")),
+            RenderTarget::Offscreen(_) => None,
+        };
+
+        let view = match (&frame, &wgpu_backend.target) {
+            (Some(frame), _) => frame.texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            (None, RenderTarget::Offscreen(texture)) => texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            (None, RenderTarget::Surface(_)) => unreachable!(),
+        };
         let mut encoder = wgpu_backend.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: None,
         });
 
-        {
-            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 1.0,
-                            b: 1.0,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-
-            match logic.state {
-                LogicState::Playing => {
-                    if logic.play.pipeline == PipelineType::TestRasterizer {
-                        self.rasterizer.render(&mut pass);
-                    } else {
-                        self.ray_marcher.render(&mut pass);
-                    }
-                },
-                LogicState::Menu => {},
-            }
-        }
+        self.graph.execute(self, logic, &mut encoder, &view);
 
         wgpu_backend.queue.submit(Some(encoder.finish()));
-        frame.present();
+
+        if let Some(frame) = frame {
+            frame.present();
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,371 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    WGPUBackend,
+    logic::play::Play,
+    renderer::{
+        pipeline,
+        pipeline::SimpleVertex,
+        registry::Pipeline
+    },
+    voxel::{
+        brickmap::{
+            Brickmap,
+            BrickmapUniform,
+            BRICK_SIZE
+        },
+        PaletteUniform
+    }
+};
+
+/// Renders the volume of `Play` through a brickmap mirroring it, only the cells and bricks that
+/// changed are uploaded when the volume does.
+pub struct BrickmapRayMarcher {
+    pipeline: pipeline::VoxelPipeline,
+
+    camera_position_buffer: wgpu::Buffer,
+    camera_inverted_projection_buffer: wgpu::Buffer,
+    camera_inverted_view_buffer: wgpu::Buffer,
+    surface_configuration_buffer: wgpu::Buffer,
+    brickmap_buffer: wgpu::Buffer,
+    palette_buffer: wgpu::Buffer,
+
+    brickmap: Brickmap,
+    grid_texture: wgpu::Texture,
+    pool_texture: wgpu::Texture,
+    uploaded_revision: u64,
+
+    bind_group: wgpu::BindGroup,
+
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+}
+
+impl Pipeline for BrickmapRayMarcher {
+    fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self {
+        let pipeline = pipeline::VoxelPipeline::new(wgpu_backend, pipeline::VoxelTraversal::Brickmap);
+
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+        let camera_position_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_position_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_inverted_projection_data = play.camera.get_inverted_projection_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let camera_inverted_projection_ref: &[f32; 16] = camera_inverted_projection_data.as_ref();
+        let camera_inverted_projection_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_inverted_projection_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_inverted_view_data = play.camera.get_inverted_view_matrix();
+        let camera_inverted_view_ref: &[f32; 16] = camera_inverted_view_data.as_ref();
+        let camera_inverted_view_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_inverted_view_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let surface_configuration_data = [wgpu_backend.config.width as f32, wgpu_backend.config.height as f32];
+        let surface_configuration_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(surface_configuration_data.as_ref()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let mut brickmap = Brickmap::from_volume(&play.volume);
+        brickmap.take_changes();
+
+        let brickmap_data = BrickmapUniform::new(&brickmap);
+        let brickmap_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&brickmap_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let palette_data = PaletteUniform::new(&play.volume.palette);
+        let palette_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&palette_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let grid_texture = Self::create_grid_texture(wgpu_backend, &brickmap);
+        let pool_texture = Self::create_pool_texture(wgpu_backend, &brickmap);
+
+        let bind_group = Self::create_bind_group(
+            wgpu_backend,
+            &pipeline,
+            [&camera_position_buffer, &camera_inverted_projection_buffer, &camera_inverted_view_buffer, &surface_configuration_buffer, &brickmap_buffer, &palette_buffer],
+            [&grid_texture, &pool_texture],
+        );
+
+        let vertices = [
+            SimpleVertex { position: [-1.0, 1.0] },
+            SimpleVertex { position: [-1.0, -1.0] },
+            SimpleVertex { position: [1.0, -1.0] },
+            SimpleVertex { position: [1.0, 1.0] },
+        ];
+
+        let vertex_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let indices: [u16; 6] = [0, 1, 2, 2, 3, 0];
+
+        let index_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        return Self {
+            pipeline,
+
+            camera_position_buffer,
+            camera_inverted_projection_buffer,
+            camera_inverted_view_buffer,
+            surface_configuration_buffer,
+            brickmap_buffer,
+            palette_buffer,
+
+            brickmap,
+            grid_texture,
+            pool_texture,
+            uploaded_revision: play.volume.revision(),
+
+            bind_group,
+
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+        };
+    }
+
+    fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_position_buffer, 0, bytemuck::cast_slice(camera_position_ref));
+
+        let camera_inverted_view_data = play.camera.get_inverted_view_matrix();
+        let camera_inverted_view_ref: &[f32; 16] = camera_inverted_view_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_inverted_view_buffer, 0, bytemuck::cast_slice(camera_inverted_view_ref));
+
+        if play.volume.revision() != self.uploaded_revision {
+            if play.volume.size() != self.brickmap.size() {
+                self.brickmap = Brickmap::from_volume(&play.volume);
+                self.brickmap.take_changes();
+
+                self.grid_texture = Self::create_grid_texture(wgpu_backend, &self.brickmap);
+                self.pool_texture = Self::create_pool_texture(wgpu_backend, &self.brickmap);
+                self.rebind(wgpu_backend);
+            } else {
+                self.brickmap.sync_with(&play.volume);
+                self.upload_changes(wgpu_backend);
+            }
+
+            self.uploaded_revision = play.volume.revision();
+        }
+
+        // Placement and palette are tiny, only the voxels are worth skipping
+        self.brickmap.origin = play.volume.origin;
+        self.brickmap.voxel_size = play.volume.voxel_size;
+
+        wgpu_backend.queue.write_buffer(&self.brickmap_buffer, 0, bytemuck::bytes_of(&BrickmapUniform::new(&self.brickmap)));
+        wgpu_backend.queue.write_buffer(&self.palette_buffer, 0, bytemuck::bytes_of(&PaletteUniform::new(&play.volume.palette)));
+    }
+
+    fn resize(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let surface_configuration_data = [wgpu_backend.config.width as f32, wgpu_backend.config.height as f32];
+        wgpu_backend.queue.write_buffer(&self.surface_configuration_buffer, 0, bytemuck::cast_slice(surface_configuration_data.as_ref()));
+
+        let camera_inverted_projection_data = play.camera.get_inverted_projection_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let camera_inverted_projection_ref: &[f32; 16] = camera_inverted_projection_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_inverted_projection_buffer, 0, bytemuck::cast_slice(camera_inverted_projection_ref));
+    }
+
+    fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        pass.set_pipeline(&self.pipeline.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
+
+impl BrickmapRayMarcher {
+    fn create_grid_texture(wgpu_backend: &WGPUBackend, brickmap: &Brickmap) -> wgpu::Texture {
+        let size = brickmap.grid_size();
+
+        return wgpu_backend.device.create_texture_with_data(
+            &wgpu_backend.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Brickmap grid"),
+                size: wgpu::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: size.z,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::R32Uint,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(brickmap.grid()),
+        );
+    }
+
+    fn create_pool_texture(wgpu_backend: &WGPUBackend, brickmap: &Brickmap) -> wgpu::Texture {
+        let size = brickmap.pool_size();
+
+        return wgpu_backend.device.create_texture_with_data(
+            &wgpu_backend.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Brick pool"),
+                size: wgpu::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: size.z,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::R8Uint,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            brickmap.pool(),
+        );
+    }
+
+    fn create_bind_group(wgpu_backend: &WGPUBackend, pipeline: &pipeline::VoxelPipeline, buffers: [&wgpu::Buffer; 6], textures: [&wgpu::Texture; 2]) -> wgpu::BindGroup {
+        let [camera_position_buffer, camera_inverted_projection_buffer, camera_inverted_view_buffer, surface_configuration_buffer, brickmap_buffer, palette_buffer] = buffers;
+        let [grid_texture, pool_texture] = textures;
+
+        let grid_view = grid_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let pool_view = pool_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        return wgpu_backend.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: camera_inverted_projection_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: camera_inverted_view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: surface_configuration_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: brickmap_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&grid_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: palette_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(&pool_view),
+                },
+            ],
+        });
+    }
+
+    fn rebind(&mut self, wgpu_backend: &WGPUBackend) {
+        self.bind_group = Self::create_bind_group(
+            wgpu_backend,
+            &self.pipeline,
+            [&self.camera_position_buffer, &self.camera_inverted_projection_buffer, &self.camera_inverted_view_buffer, &self.surface_configuration_buffer, &self.brickmap_buffer, &self.palette_buffer],
+            [&self.grid_texture, &self.pool_texture],
+        );
+    }
+
+    /// Writes the changed cells and bricks into the existing textures, the pool is only uploaded
+    /// whole when it grew a layer.
+    fn upload_changes(&mut self, wgpu_backend: &WGPUBackend) {
+        let changes = self.brickmap.take_changes();
+
+        for cell in changes.cells {
+            let value = self.brickmap.cell(cell);
+
+            wgpu_backend.queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.grid_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: cell.x, y: cell.y, z: cell.z },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                bytemuck::bytes_of(&value),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        if self.brickmap.pool_size().z != self.pool_texture.depth_or_array_layers() {
+            self.pool_texture = Self::create_pool_texture(wgpu_backend, &self.brickmap);
+            self.rebind(wgpu_backend);
+
+            return;
+        }
+
+        for slot in changes.slots {
+            let origin = Brickmap::slot_origin(slot);
+
+            wgpu_backend.queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.pool_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: origin.x, y: origin.y, z: origin.z },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &self.brickmap.slot_voxels(slot),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(BRICK_SIZE),
+                    rows_per_image: Some(BRICK_SIZE),
+                },
+                wgpu::Extent3d {
+                    width: BRICK_SIZE,
+                    height: BRICK_SIZE,
+                    depth_or_array_layers: BRICK_SIZE,
+                },
+            );
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,437 @@
+//! Golden-image regression tests for the render pipelines.
+//!
+//! Each test renders fixed camera poses offscreen on the software fallback adapter and compares the
+//! result with the reference images checked in under `tests/golden`. Failing comparisons write the
+//! actual frame and a diff image to `target/golden`. Run with `VOX_UPDATE_GOLDEN=1` to regenerate
+//! the references after an intended visual change. Without an adapter the tests fail, unless
+//! `VOX_SKIP_GPU_TESTS` is set to skip them.
+
+use std::{
+    cell::Cell,
+    env,
+    f32::consts::FRAC_PI_2,
+    fs::{
+        self,
+        File
+    },
+    mem,
+    path::{
+        Path,
+        PathBuf
+    }
+};
+
+use glam::{
+    Mat4,
+    Vec3
+};
+
+use crate::{
+    build_headless_wgpu_backend,
+    no_adapter,
+    logic::{
+        camera::Camera,
+        Logic
+    },
+    renderer::{
+        capture,
+        software_ray_marcher,
+        Renderer
+    },
+    scene::{
+        evaluate::SceneEvaluator,
+        Scene
+    },
+    voxel::VoxelVolume,
+    RenderTarget,
+    WGPUBackend
+};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 90;
+
+/// Maximum perceptual distance between two pixels, as a fraction of the largest possible YIQ distance.
+const PIXEL_THRESHOLD: f32 = 0.1;
+
+/// Fraction of pixels allowed to exceed `PIXEL_THRESHOLD` before an image is considered different.
+const MAX_DIFFERENT_PIXELS: f32 = 0.002;
+
+struct Pose {
+    name: &'static str,
+    position: Vec3,
+    rotation: Vec3,
+}
+
+const POSES: [Pose; 3] = [
+    Pose {
+        name: "front",
+        position: Vec3::new(0.0, -3.0, 0.0),
+        rotation: Vec3::new(FRAC_PI_2, 0.0, 0.0),
+    },
+    Pose {
+        name: "close",
+        position: Vec3::new(0.5, -1.8, 0.5),
+        rotation: Vec3::new(FRAC_PI_2, 0.0, 0.0),
+    },
+    Pose {
+        name: "above",
+        position: Vec3::new(1.5, -2.5, 2.0),
+        rotation: Vec3::new(1.0, 0.0, 0.5),
+    },
+];
+
+const VOXEL_POSES: [Pose; 2] = [
+    Pose {
+        name: "front",
+        position: Vec3::new(0.0, -5.0, 1.0),
+        rotation: Vec3::new(1.35, 0.0, 0.0),
+    },
+    Pose {
+        name: "corner",
+        position: Vec3::new(3.5, -3.5, 3.0),
+        rotation: Vec3::new(0.9, 0.0, 0.8),
+    },
+];
+
+/// The second pose is far from the origin, where every chunk in view was generated on the way there.
+const WORLD_POSES: [Pose; 2] = [
+    Pose {
+        name: "front",
+        position: Vec3::new(0.0, -3.0, 0.0),
+        rotation: Vec3::new(1.35, 0.0, 0.0),
+    },
+    Pose {
+        name: "far",
+        position: Vec3::new(250.0, -130.0, 1.0),
+        rotation: Vec3::new(1.2, 0.0, 0.8),
+    },
+];
+
+const SHOWCASE_POSES: [Pose; 2] = [
+    Pose {
+        name: "front",
+        position: Vec3::new(0.0, -5.0, 0.5),
+        rotation: Vec3::new(FRAC_PI_2, 0.0, 0.0),
+    },
+    Pose {
+        name: "side",
+        position: Vec3::new(2.5, -3.0, 1.5),
+        rotation: Vec3::new(1.25, 0.0, 0.6),
+    },
+];
+
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+fn golden_directory() -> PathBuf {
+    return Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+}
+
+fn output_directory() -> PathBuf {
+    return Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("golden");
+}
+
+/// Renders one frame of `pipeline` from `pose`, or returns `None` when no adapter is available.
+/// `setup` prepares the logic the renderer is created with, `step` then changes it and updates the
+/// renderer before the frame is drawn.
+fn render(pipeline: &str, pose: &Pose, setup: impl FnOnce(&mut Logic), step: impl FnOnce(&WGPUBackend, &mut Renderer, &mut Logic)) -> Option<Image> {
+    let backend = build_headless_wgpu_backend(WIDTH, HEIGHT, true)?;
+
+    let mut logic = Logic::new();
+    logic.play.pipeline = String::from(pipeline);
+    logic.play.camera.position = pose.position;
+    logic.play.camera.rotation = pose.rotation;
+
+    setup(&mut logic);
+
+    let mut renderer = Renderer::new(&backend, &logic);
+
+    step(&backend, &mut renderer, &mut logic);
+    renderer.render(&backend, &logic);
+
+    let RenderTarget::Offscreen(texture) = &backend.target else {
+        unreachable!("Headless backends always render offscreen");
+    };
+
+    return Some(Image {
+        width: WIDTH,
+        height: HEIGHT,
+        pixels: capture::read_texture(&backend, texture),
+    });
+}
+
+/// Step drawing the logic the renderer was created with.
+fn unchanged(_: &WGPUBackend, _: &mut Renderer, _: &mut Logic) {}
+
+/// Step generating the whole window of the world around the camera and uploading or meshing it a few
+/// chunks per frame, as when flying around.
+fn streamed(backend: &WGPUBackend, renderer: &mut Renderer, logic: &mut Logic) {
+    logic.play.world.load_window(logic.play.camera.position);
+
+    renderer.update(backend, logic);
+
+    while renderer.pending(logic) > 0 {
+        renderer.update(backend, logic);
+    }
+}
+
+/// Like `streamed`, but chunks are meshed as they finish generating, in whatever order the workers
+/// deliver them, so neighbours are often meshed before the chunks next to them load.
+fn streaming(backend: &WGPUBackend, renderer: &mut Renderer, logic: &mut Logic) {
+    renderer.update(backend, logic);
+
+    while logic.play.world.stream(logic.play.camera.position) > 0 || renderer.pending(logic) > 0 {
+        renderer.update(backend, logic);
+    }
+}
+
+fn render_software(scene: &Scene, pose: &Pose) -> Option<Image> {
+    let mut logic = Logic::new();
+    logic.play.camera.position = pose.position;
+    logic.play.camera.rotation = pose.rotation;
+
+    let evaluator = SceneEvaluator::new(scene).expect("Invalid test scene");
+
+    let mut pixels = vec![0u8; (WIDTH * HEIGHT * 4) as usize];
+    software_ray_marcher::march_frame(&logic.play.camera, scene, &evaluator, WIDTH, HEIGHT, &mut pixels);
+
+    return Some(Image {
+        width: WIDTH,
+        height: HEIGHT,
+        pixels,
+    });
+}
+
+fn load_png(path: &Path) -> Option<Image> {
+    let decoder = png::Decoder::new(File::open(path).ok()?);
+    let mut reader = decoder.read_info().expect("Failed to read golden image header");
+
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).expect("Failed to decode golden image");
+
+    assert_eq!(info.color_type, png::ColorType::Rgba, "Golden images must be stored as RGBA8");
+    assert_eq!(info.bit_depth, png::BitDepth::Eight, "Golden images must be stored as RGBA8");
+
+    pixels.truncate(info.buffer_size());
+
+    return Some(Image {
+        width: info.width,
+        height: info.height,
+        pixels,
+    });
+}
+
+fn to_yiq(pixel: &[u8]) -> (f32, f32, f32) {
+    let r = pixel[0] as f32;
+    let g = pixel[1] as f32;
+    let b = pixel[2] as f32;
+
+    let y = r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_2;
+    let i = r * 0.595_978 - g * 0.274_176_1 - b * 0.321_801_9;
+    let q = r * 0.211_470_2 - g * 0.522_617_1 + b * 0.311_146_9;
+
+    return (y, i, q);
+}
+
+/// Perceptual distance between two RGBA8 pixels in YIQ space, normalized to `[0, 1]`.
+fn pixel_distance(a: &[u8], b: &[u8]) -> f32 {
+    let (ya, ia, qa) = to_yiq(a);
+    let (yb, ib, qb) = to_yiq(b);
+
+    let delta = 0.5053 * (ya - yb).powi(2) + 0.299 * (ia - ib).powi(2) + 0.1957 * (qa - qb).powi(2);
+
+    return (delta / 35215.0).sqrt();
+}
+
+/// Compares two images and returns the number of differing pixels along with a diff image in which
+/// differing pixels are red and the rest is a faded copy of `expected`.
+fn compare(expected: &Image, actual: &Image) -> (usize, Image) {
+    let mut different = 0;
+    let mut diff = Vec::<u8>::with_capacity(actual.pixels.len());
+
+    for (a, b) in expected.pixels.chunks_exact(4).zip(actual.pixels.chunks_exact(4)) {
+        if pixel_distance(a, b) > PIXEL_THRESHOLD {
+            different += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let (y, _, _) = to_yiq(a);
+            let faded = (255.0 - 0.1 * (255.0 - y)) as u8;
+            diff.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+
+    return (different, Image {
+        width: actual.width,
+        height: actual.height,
+        pixels: diff,
+    });
+}
+
+/// Renders every pose in `poses` with `render` and compares the frames with the `golden_name` references. References
+/// are rewritten instead when `VOX_UPDATE_GOLDEN` is set and `updatable` is true.
+fn check_golden(golden_name: &str, poses: &[Pose], updatable: bool, render: impl Fn(&Pose) -> Option<Image>) {
+    let mut failures = Vec::<String>::new();
+
+    for pose in poses.iter() {
+        let name = format!("{}_{}", golden_name, pose.name);
+
+        let Some(actual) = render(pose) else {
+            no_adapter(&format!("golden image {}", name));
+            return;
+        };
+
+        let golden_path = golden_directory().join(format!("{}.png", name));
+
+        if updatable && env::var_os("VOX_UPDATE_GOLDEN").is_some() {
+            fs::create_dir_all(golden_directory()).expect("Failed to create golden directory");
+            capture::save_png(&golden_path, actual.width, actual.height, &actual.pixels).expect("Failed to write golden image");
+            continue;
+        }
+
+        let output = output_directory();
+        fs::create_dir_all(&output).expect("Failed to create golden output directory");
+
+        let actual_path = output.join(format!("{}.actual.png", name));
+
+        let Some(expected) = load_png(&golden_path) else {
+            capture::save_png(&actual_path, actual.width, actual.height, &actual.pixels).expect("Failed to write actual image");
+            failures.push(format!("{}: missing reference {}, actual frame written to {}", name, golden_path.display(), actual_path.display()));
+            continue;
+        };
+
+        if expected.width != actual.width || expected.height != actual.height {
+            capture::save_png(&actual_path, actual.width, actual.height, &actual.pixels).expect("Failed to write actual image");
+            failures.push(format!("{}: expected {}x{}, rendered {}x{}", name, expected.width, expected.height, actual.width, actual.height));
+            continue;
+        }
+
+        let (different, diff) = compare(&expected, &actual);
+        let allowed = (MAX_DIFFERENT_PIXELS * (actual.width * actual.height) as f32) as usize;
+
+        if different > allowed {
+            let diff_path = output.join(format!("{}.diff.png", name));
+
+            capture::save_png(&actual_path, actual.width, actual.height, &actual.pixels).expect("Failed to write actual image");
+            capture::save_png(&diff_path, diff.width, diff.height, &diff.pixels).expect("Failed to write diff image");
+
+            failures.push(format!("{}: {} pixels differ (allowed {}), see {}", name, different, allowed, diff_path.display()));
+        }
+    }
+
+    assert!(failures.is_empty(), "Golden image mismatches:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn ray_marcher_matches_golden_images() {
+    check_golden("ray_marcher", &POSES, true, |pose| render("ray-marcher", pose, |_| {}, unchanged));
+}
+
+#[test]
+fn rasterizer_matches_golden_images() {
+    check_golden("rasterizer", &POSES, true, |pose| render("rasterizer", pose, |_| {}, unchanged));
+}
+
+#[test]
+fn rasterizer_uploads_added_objects() {
+    // The meshes only reach the GPU through `Renderer::update`
+    check_golden("rasterizer", &POSES, false, |pose| {
+        let objects = Cell::new(Vec::new());
+
+        return render("rasterizer", pose, |logic| objects.set(mem::take(&mut logic.play.objects)), |backend, renderer, logic| {
+            logic.play.objects = objects.take();
+            renderer.update(backend, logic);
+        });
+    });
+}
+
+#[test]
+fn hybrid_matches_golden_images() {
+    // The cube is scaled until its corners poke through the sphere, so each of the ray-marched and
+    // rasterized surfaces hides the other in places
+    let setup = |logic: &mut Logic| {
+        for object in logic.play.objects.iter_mut() {
+            object.transform = Mat4::from_scale(Vec3::splat(1.5)) * object.transform;
+        }
+    };
+
+    check_golden("hybrid", &POSES, true, |pose| render("hybrid", pose, setup, unchanged));
+}
+
+#[test]
+fn software_ray_marcher_matches_gpu_golden_images() {
+    // The CPU ray marcher is the reference implementation, it has to agree with the shader output
+    check_golden("ray_marcher", &POSES, false, |pose| render_software(&Scene::new(), pose));
+}
+
+#[test]
+fn software_ray_marcher_showcase_matches_gpu_golden_images() {
+    check_golden("ray_marcher_showcase", &SHOWCASE_POSES, false, |pose| render_software(&Scene::showcase(), pose));
+}
+
+#[test]
+fn ray_marcher_showcase_matches_golden_images() {
+    check_golden("ray_marcher_showcase", &SHOWCASE_POSES, true, |pose| render("ray-marcher", pose, |logic| logic.play.scene = Scene::showcase(), unchanged));
+}
+
+#[test]
+fn voxel_ray_marcher_matches_golden_images() {
+    check_golden("voxel_ray_marcher", &VOXEL_POSES, true, |pose| render("voxel", pose, |_| {}, unchanged));
+}
+
+#[test]
+fn octree_ray_marcher_matches_voxel_golden_images() {
+    // The octree only changes how empty space is skipped, it has to draw the dense references
+    check_golden("voxel_ray_marcher", &VOXEL_POSES, false, |pose| render("octree", pose, |_| {}, unchanged));
+}
+
+#[test]
+fn brickmap_ray_marcher_matches_voxel_golden_images() {
+    check_golden("voxel_ray_marcher", &VOXEL_POSES, false, |pose| render("brickmap", pose, |_| {}, unchanged));
+}
+
+#[test]
+fn brickmap_ray_marcher_uploads_edits() {
+    // Starting from an empty volume, every voxel reaches the GPU through the incremental updates
+    let setup = |logic: &mut Logic| logic.play.volume = VoxelVolume::new(logic.play.volume.size());
+
+    check_golden("voxel_ray_marcher", &VOXEL_POSES, false, |pose| render("brickmap", pose, setup, |backend, renderer, logic| {
+        logic.play.volume = VoxelVolume::demo();
+        renderer.update(backend, logic);
+    }));
+}
+
+#[test]
+fn world_ray_marcher_matches_golden_images() {
+    check_golden("world_ray_marcher", &WORLD_POSES, true, |pose| render("world", pose, |_| {}, streamed));
+}
+
+#[test]
+fn world_rasterizer_matches_golden_images() {
+    check_golden("world_rasterizer", &WORLD_POSES, true, |pose| render("world-raster", pose, |_| {}, streamed));
+}
+
+#[test]
+fn world_rasterizer_meshes_chunks_as_they_stream_in() {
+    check_golden("world_rasterizer", &WORLD_POSES, false, |pose| render("world-raster", pose, |_| {}, streaming));
+}
+
+#[test]
+fn world_rasterizer_showcase_matches_golden_images() {
+    check_golden("world_rasterizer_showcase", &WORLD_POSES, true, |pose| render("world-raster", pose, |logic| logic.play.scene = Scene::showcase(), streamed));
+}
+
+#[test]
+fn world_rasterizer_follows_the_camera_and_lights() {
+    // Created with the default camera and scene, the pose and the lights only reach the GPU
+    // through `Renderer::update`
+    check_golden("world_rasterizer_showcase", &WORLD_POSES, false, |pose| render("world-raster", pose, |logic| logic.play.camera = Camera::new(), |backend, renderer, logic| {
+        logic.play.scene = Scene::showcase();
+        logic.play.camera.position = pose.position;
+        logic.play.camera.rotation = pose.rotation;
+
+        streamed(backend, renderer, logic);
+    }));
+}
\ No newline at end of file
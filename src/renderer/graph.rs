@@ -0,0 +1,418 @@
+use std::{
+    collections::{
+        BTreeSet,
+        HashMap
+    },
+    fmt
+};
+
+use crate::{
+    logic::Logic,
+    WGPUBackend
+};
+
+/// Texture presented at the end of the frame, the surface or the offscreen target. It is not owned by
+/// the graph, so passes can draw into it but not read it.
+pub const SURFACE: &str = "surface";
+
+/// Records the draw calls of a pass, given the context the graph runs with.
+pub type Record<C> = for<'a> fn(&'a C, &'a Logic, &mut wgpu::RenderPass<'a>);
+
+#[derive(Debug, PartialEq)]
+pub enum GraphError {
+    /// Two passes with the same name.
+    DuplicatePass(&'static str),
+    /// A pass using a texture that was never added.
+    UnknownTexture { pass: &'static str, texture: &'static str },
+    /// A pass reading the surface, which is only written.
+    SurfaceInput(&'static str),
+    /// A pass drawing depth into the surface, which only holds color.
+    SurfaceDepth(&'static str),
+    /// A pass sampling a texture it also draws into.
+    InputAttachment { pass: &'static str, texture: &'static str },
+    /// Passes depending on each other's outputs, in the order they were added.
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            GraphError::DuplicatePass(pass) => write!(f, "pass {:?} is added twice", pass),
+            GraphError::UnknownTexture { pass, texture } => write!(f, "pass {:?} uses the unknown texture {:?}", pass, texture),
+            GraphError::SurfaceInput(pass) => write!(f, "pass {:?} reads the surface", pass),
+            GraphError::SurfaceDepth(pass) => write!(f, "pass {:?} uses the surface as its depth attachment", pass),
+            GraphError::InputAttachment { pass, texture } => write!(f, "pass {:?} both reads and draws into {:?}", pass, texture),
+            GraphError::Cycle(passes) => write!(f, "passes {:?} depend on each other", passes),
+        };
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+/// A render pass with the textures it samples and the attachments it draws into. Attachments are
+/// cleared when a clear value is given, otherwise they keep what earlier passes drew.
+pub struct Pass<C> {
+    pub name: &'static str,
+    pub inputs: Vec<&'static str>,
+    pub color: Option<(&'static str, Option<wgpu::Color>)>,
+    pub depth: Option<(&'static str, Option<f32>)>,
+    pub record: Record<C>,
+}
+
+impl<C> Pass<C> {
+    pub fn new(name: &'static str, record: Record<C>) -> Self {
+        return Self {
+            name,
+            inputs: Vec::new(),
+            color: None,
+            depth: None,
+            record,
+        };
+    }
+
+    pub fn with_input(mut self, texture: &'static str) -> Self {
+        self.inputs.push(texture);
+
+        return self;
+    }
+
+    pub fn with_color(self, texture: &'static str, clear: Option<wgpu::Color>) -> Self {
+        return Self {
+            color: Some((texture, clear)),
+            ..self
+        };
+    }
+
+    pub fn with_depth(self, texture: &'static str, clear: Option<f32>) -> Self {
+        return Self {
+            depth: Some((texture, clear)),
+            ..self
+        };
+    }
+
+    fn outputs(&self) -> impl Iterator<Item = &'static str> + '_ {
+        return self.color.iter().map(|(texture, _)| *texture).chain(self.depth.iter().map(|(texture, _)| *texture));
+    }
+
+    /// Whether the pass clears `texture` instead of drawing over it.
+    fn clears(&self, texture: &str) -> bool {
+        let color = self.color.is_some_and(|(color, clear)| color == texture && clear.is_some());
+        let depth = self.depth.is_some_and(|(depth, clear)| depth == texture && clear.is_some());
+
+        return color || depth;
+    }
+}
+
+/// Texture owned by the graph, sized to the surface and recreated with it.
+struct Transient {
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+    view: Option<wgpu::TextureView>,
+}
+
+/// Passes run in the order of their dependencies: a pass reading a texture runs after every pass
+/// drawing into it, and a pass clearing an attachment runs before the passes drawing over it. Other
+/// passes drawing into the same texture run in the order they were added. Attachments are only stored
+/// when a later pass uses them.
+pub struct RenderGraph<C> {
+    textures: HashMap<&'static str, Transient>,
+    passes: Vec<Pass<C>>,
+    /// Indices into `passes`, set by `compile`.
+    order: Vec<usize>,
+}
+
+impl<C> RenderGraph<C> {
+    pub fn new() -> Self {
+        return Self {
+            textures: HashMap::new(),
+            passes: Vec::new(),
+            order: Vec::new(),
+        };
+    }
+
+    /// Declares a transient texture, its usage follows from the passes using it.
+    pub fn add_texture(&mut self, name: &'static str, format: wgpu::TextureFormat) {
+        self.textures.insert(name, Transient {
+            format,
+            usage: wgpu::TextureUsages::empty(),
+            view: None,
+        });
+    }
+
+    pub fn add_pass(&mut self, pass: Pass<C>) {
+        self.passes.push(pass);
+    }
+
+    /// Checks the passes and orders them, then derives the usage of every texture. Textures have to
+    /// be created again with `resize` afterwards.
+    pub fn compile(&mut self) -> Result<(), GraphError> {
+        for (index, pass) in self.passes.iter().enumerate() {
+            if self.passes[..index].iter().any(|other| other.name == pass.name) {
+                return Err(GraphError::DuplicatePass(pass.name));
+            }
+
+            if pass.inputs.contains(&SURFACE) {
+                return Err(GraphError::SurfaceInput(pass.name));
+            }
+
+            if pass.depth.is_some_and(|(texture, _)| texture == SURFACE) {
+                return Err(GraphError::SurfaceDepth(pass.name));
+            }
+
+            if let Some(texture) = pass.outputs().find(|output| pass.inputs.contains(output)) {
+                return Err(GraphError::InputAttachment { pass: pass.name, texture });
+            }
+
+            for texture in pass.inputs.iter().copied().chain(pass.outputs()) {
+                if texture != SURFACE && !self.textures.contains_key(texture) {
+                    return Err(GraphError::UnknownTexture { pass: pass.name, texture });
+                }
+            }
+        }
+
+        let mut dependencies = vec![BTreeSet::<usize>::new(); self.passes.len()];
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            for (other, earlier) in self.passes.iter().enumerate() {
+                let reads = pass.inputs.iter().any(|input| earlier.outputs().any(|output| output == *input));
+
+                // A clear wipes whatever was drawn before it, wherever it was added
+                let overwrites = pass.outputs().filter(|output| earlier.outputs().any(|written| written == *output)).any(|output| {
+                    return match (earlier.clears(output), pass.clears(output)) {
+                        (true, false) => true,
+                        (false, true) => false,
+                        _ => other < index,
+                    };
+                });
+
+                if other != index && (reads || overwrites) {
+                    dependencies[index].insert(other);
+                }
+            }
+        }
+
+        // Kahn's algorithm, picking the first pass added among the ready ones so the order is stable
+        let mut order = Vec::with_capacity(self.passes.len());
+        let mut done = vec![false; self.passes.len()];
+
+        while order.len() < self.passes.len() {
+            let ready = (0..self.passes.len()).find(|index| !done[*index] && dependencies[*index].iter().all(|dependency| done[*dependency]));
+
+            let Some(index) = ready else {
+                let cycle = (0..self.passes.len()).filter(|index| !done[*index]).map(|index| self.passes[index].name).collect();
+
+                return Err(GraphError::Cycle(cycle));
+            };
+
+            done[index] = true;
+            order.push(index);
+        }
+
+        for (name, texture) in self.textures.iter_mut() {
+            texture.usage = wgpu::TextureUsages::empty();
+
+            for pass in self.passes.iter() {
+                if pass.inputs.contains(name) {
+                    texture.usage |= wgpu::TextureUsages::TEXTURE_BINDING;
+                }
+
+                if pass.outputs().any(|output| output == *name) {
+                    texture.usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+                }
+            }
+        }
+
+        self.order = order;
+
+        return Ok(());
+    }
+
+    /// Names of the passes in the order they run.
+    pub fn order(&self) -> Vec<&'static str> {
+        return self.order.iter().map(|index| self.passes[*index].name).collect();
+    }
+
+    /// Creates the transient textures at the size of the surface, dropping the previous ones.
+    pub fn resize(&mut self, wgpu_backend: &WGPUBackend) {
+        for (name, texture) in self.textures.iter_mut() {
+            // Unused textures are never created
+            if texture.usage.is_empty() {
+                texture.view = None;
+                continue;
+            }
+
+            let created = wgpu_backend.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(name),
+                size: wgpu::Extent3d {
+                    width: wgpu_backend.config.width,
+                    height: wgpu_backend.config.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: texture.format,
+                usage: texture.usage,
+                view_formats: &[],
+            });
+
+            texture.view = Some(created.create_view(&wgpu::TextureViewDescriptor::default()));
+        }
+    }
+
+    /// View of a transient texture, for passes sampling it. Views change on `resize`, bind groups
+    /// holding one have to be created again after it.
+    pub fn view(&self, name: &str) -> Option<&wgpu::TextureView> {
+        return self.textures.get(name).and_then(|texture| texture.view.as_ref());
+    }
+
+    /// Whether a pass running after the one at `position` in the order uses `texture`.
+    fn used_later(&self, position: usize, texture: &str) -> bool {
+        return self.order[position + 1..].iter().any(|index| {
+            let pass = &self.passes[*index];
+
+            return pass.inputs.contains(&texture) || pass.outputs().any(|output| output == texture);
+        });
+    }
+
+    fn store(&self, position: usize, texture: &str) -> wgpu::StoreOp {
+        return match texture == SURFACE || self.used_later(position, texture) {
+            true => wgpu::StoreOp::Store,
+            false => wgpu::StoreOp::Discard,
+        };
+    }
+
+    fn attachment_view<'a>(&'a self, texture: &str, surface: &'a wgpu::TextureView) -> &'a wgpu::TextureView {
+        if texture == SURFACE {
+            return surface;
+        }
+
+        return self.view(texture).expect("Transient textures are created by resize");
+    }
+
+    /// Records every pass into `encoder`, drawing the surface through `surface`.
+    pub fn execute(&self, context: &C, logic: &Logic, encoder: &mut wgpu::CommandEncoder, surface: &wgpu::TextureView) {
+        for (position, index) in self.order.iter().enumerate() {
+            let pass = &self.passes[*index];
+
+            let color_attachment = pass.color.map(|(texture, clear)| wgpu::RenderPassColorAttachment {
+                view: self.attachment_view(texture, surface),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                    store: self.store(position, texture),
+                },
+            });
+
+            let depth_attachment = pass.depth.map(|(texture, clear)| wgpu::RenderPassDepthStencilAttachment {
+                view: self.attachment_view(texture, surface),
+                depth_ops: Some(wgpu::Operations {
+                    load: clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                    store: self.store(position, texture),
+                }),
+                stencil_ops: None,
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(pass.name),
+                color_attachments: &[color_attachment],
+                depth_stencil_attachment: depth_attachment,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            (pass.record)(context, logic, &mut render_pass);
+        }
+    }
+
+    /// Changes the color a pass clears its color attachment to, for clears that depend on what is drawn.
+    pub fn set_clear_color(&mut self, name: &str, color: wgpu::Color) {
+        for pass in self.passes.iter_mut().filter(|pass| pass.name == name) {
+            if let Some((_, clear)) = pass.color.as_mut() {
+                *clear = Some(color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        GraphError,
+        Pass,
+        RenderGraph,
+        SURFACE
+    };
+
+    fn pass(name: &'static str) -> Pass<()> {
+        return Pass::new(name, |_, _, _| {});
+    }
+
+    #[test]
+    fn passes_run_after_the_passes_drawing_their_inputs() {
+        let mut graph = RenderGraph::<()>::new();
+        graph.add_texture("color", wgpu::TextureFormat::Rgba8Unorm);
+        graph.add_texture("depth", wgpu::TextureFormat::Depth32Float);
+        graph.add_texture("unused", wgpu::TextureFormat::Rgba8Unorm);
+
+        graph.add_pass(pass("overlay").with_color(SURFACE, None));
+        graph.add_pass(pass("post").with_input("color").with_color(SURFACE, Some(wgpu::Color::BLACK)));
+        graph.add_pass(pass("scene").with_color("color", Some(wgpu::Color::BLACK)).with_depth("depth", Some(1.0)));
+        graph.add_pass(pass("meshes").with_color("color", None).with_depth("depth", None));
+
+        graph.compile().unwrap();
+
+        // The post pass clears the surface, so the overlay drawn over it runs after it although it was added first
+        assert_eq!(graph.order(), ["scene", "meshes", "post", "overlay"]);
+
+        let usage = |name: &str| graph.textures[name].usage;
+
+        assert_eq!(usage("color"), wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING);
+        assert_eq!(usage("depth"), wgpu::TextureUsages::RENDER_ATTACHMENT);
+        assert!(usage("unused").is_empty());
+
+        // Depth is only kept for the meshes, and the color for the post pass
+        assert_eq!(graph.store(0, "depth"), wgpu::StoreOp::Store);
+        assert_eq!(graph.store(1, "depth"), wgpu::StoreOp::Discard);
+        assert_eq!(graph.store(1, "color"), wgpu::StoreOp::Store);
+    }
+
+    #[test]
+    fn invalid_graphs_are_rejected() {
+        let mut graph = RenderGraph::<()>::new();
+        graph.add_pass(pass("scene").with_color("color", None));
+
+        assert_eq!(graph.compile(), Err(GraphError::UnknownTexture { pass: "scene", texture: "color" }));
+
+        let mut graph = RenderGraph::<()>::new();
+        graph.add_pass(pass("post").with_input(SURFACE));
+
+        assert_eq!(graph.compile(), Err(GraphError::SurfaceInput("post")));
+
+        let mut graph = RenderGraph::<()>::new();
+        graph.add_pass(pass("scene").with_color(SURFACE, None).with_depth(SURFACE, None));
+
+        assert_eq!(graph.compile(), Err(GraphError::SurfaceDepth("scene")));
+
+        let mut graph = RenderGraph::<()>::new();
+        graph.add_texture("color", wgpu::TextureFormat::Rgba8Unorm);
+        graph.add_pass(pass("blur").with_input("color").with_color("color", None));
+
+        assert_eq!(graph.compile(), Err(GraphError::InputAttachment { pass: "blur", texture: "color" }));
+
+        let mut graph = RenderGraph::<()>::new();
+        graph.add_texture("a", wgpu::TextureFormat::Rgba8Unorm);
+        graph.add_texture("b", wgpu::TextureFormat::Rgba8Unorm);
+        graph.add_pass(pass("first").with_input("b").with_color("a", None));
+        graph.add_pass(pass("second").with_input("a").with_color("b", None));
+        graph.add_pass(pass("present").with_color(SURFACE, None));
+
+        assert_eq!(graph.compile(), Err(GraphError::Cycle(vec!["first", "second"])));
+
+        let mut graph = RenderGraph::<()>::new();
+        graph.add_pass(pass("scene"));
+        graph.add_pass(pass("scene"));
+
+        assert_eq!(graph.compile(), Err(GraphError::DuplicatePass("scene")));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,274 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    WGPUBackend,
+    logic::play::Play,
+    renderer::{
+        pipeline,
+        pipeline::SimpleVertex,
+        registry::Pipeline
+    },
+    voxel::{
+        octree::{
+            OctreeUniform,
+            SparseVoxelOctree,
+            NODE_TEXTURE_WIDTH
+        },
+        PaletteUniform
+    }
+};
+
+/// Renders the volume of `Play` through its sparse voxel octree, rebuilt on the CPU whenever the
+/// volume changes.
+pub struct OctreeRayMarcher {
+    pipeline: pipeline::VoxelPipeline,
+
+    camera_position_buffer: wgpu::Buffer,
+    camera_inverted_projection_buffer: wgpu::Buffer,
+    camera_inverted_view_buffer: wgpu::Buffer,
+    surface_configuration_buffer: wgpu::Buffer,
+    octree_buffer: wgpu::Buffer,
+    palette_buffer: wgpu::Buffer,
+
+    nodes_texture: wgpu::Texture,
+    uploaded_revision: u64,
+
+    bind_group: wgpu::BindGroup,
+
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+}
+
+impl Pipeline for OctreeRayMarcher {
+    fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self {
+        let pipeline = pipeline::VoxelPipeline::new(wgpu_backend, pipeline::VoxelTraversal::Octree);
+
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+        let camera_position_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_position_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_inverted_projection_data = play.camera.get_inverted_projection_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let camera_inverted_projection_ref: &[f32; 16] = camera_inverted_projection_data.as_ref();
+        let camera_inverted_projection_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_inverted_projection_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_inverted_view_data = play.camera.get_inverted_view_matrix();
+        let camera_inverted_view_ref: &[f32; 16] = camera_inverted_view_data.as_ref();
+        let camera_inverted_view_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_inverted_view_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let surface_configuration_data = [wgpu_backend.config.width as f32, wgpu_backend.config.height as f32];
+        let surface_configuration_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(surface_configuration_data.as_ref()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let octree = SparseVoxelOctree::new(&play.volume);
+
+        let octree_data = OctreeUniform::new(&octree);
+        let octree_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&octree_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let palette_data = PaletteUniform::new(&play.volume.palette);
+        let palette_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&palette_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let nodes_texture = Self::create_nodes_texture(wgpu_backend, &octree);
+
+        let bind_group = Self::create_bind_group(
+            wgpu_backend,
+            &pipeline,
+            [&camera_position_buffer, &camera_inverted_projection_buffer, &camera_inverted_view_buffer, &surface_configuration_buffer, &octree_buffer, &palette_buffer],
+            &nodes_texture,
+        );
+
+        let vertices = [
+            SimpleVertex { position: [-1.0, 1.0] },
+            SimpleVertex { position: [-1.0, -1.0] },
+            SimpleVertex { position: [1.0, -1.0] },
+            SimpleVertex { position: [1.0, 1.0] },
+        ];
+
+        let vertex_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let indices: [u16; 6] = [0, 1, 2, 2, 3, 0];
+
+        let index_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        return Self {
+            pipeline,
+
+            camera_position_buffer,
+            camera_inverted_projection_buffer,
+            camera_inverted_view_buffer,
+            surface_configuration_buffer,
+            octree_buffer,
+            palette_buffer,
+
+            nodes_texture,
+            uploaded_revision: play.volume.revision(),
+
+            bind_group,
+
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+        };
+    }
+
+    fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_position_buffer, 0, bytemuck::cast_slice(camera_position_ref));
+
+        let camera_inverted_view_data = play.camera.get_inverted_view_matrix();
+        let camera_inverted_view_ref: &[f32; 16] = camera_inverted_view_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_inverted_view_buffer, 0, bytemuck::cast_slice(camera_inverted_view_ref));
+
+        wgpu_backend.queue.write_buffer(&self.palette_buffer, 0, bytemuck::bytes_of(&PaletteUniform::new(&play.volume.palette)));
+
+        if play.volume.revision() != self.uploaded_revision {
+            let octree = SparseVoxelOctree::new(&play.volume);
+
+            wgpu_backend.queue.write_buffer(&self.octree_buffer, 0, bytemuck::bytes_of(&OctreeUniform::new(&octree)));
+
+            // The node count changes with almost every edit, only grow or shrink the texture by whole rows
+            if octree.node_texture_rows() != self.nodes_texture.height() {
+                self.nodes_texture = Self::create_nodes_texture(wgpu_backend, &octree);
+                self.bind_group = Self::create_bind_group(
+                    wgpu_backend,
+                    &self.pipeline,
+                    [&self.camera_position_buffer, &self.camera_inverted_projection_buffer, &self.camera_inverted_view_buffer, &self.surface_configuration_buffer, &self.octree_buffer, &self.palette_buffer],
+                    &self.nodes_texture,
+                );
+            } else {
+                wgpu_backend.queue.write_texture(
+                    self.nodes_texture.as_image_copy(),
+                    bytemuck::cast_slice(&octree.node_texture_data()),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(NODE_TEXTURE_WIDTH * 4),
+                        rows_per_image: None,
+                    },
+                    self.nodes_texture.size(),
+                );
+            }
+
+            self.uploaded_revision = play.volume.revision();
+        }
+    }
+
+    fn resize(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let surface_configuration_data = [wgpu_backend.config.width as f32, wgpu_backend.config.height as f32];
+        wgpu_backend.queue.write_buffer(&self.surface_configuration_buffer, 0, bytemuck::cast_slice(surface_configuration_data.as_ref()));
+
+        let camera_inverted_projection_data = play.camera.get_inverted_projection_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let camera_inverted_projection_ref: &[f32; 16] = camera_inverted_projection_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_inverted_projection_buffer, 0, bytemuck::cast_slice(camera_inverted_projection_ref));
+    }
+
+    fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        pass.set_pipeline(&self.pipeline.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
+
+impl OctreeRayMarcher {
+    /// Uploads the node buffer as rows of `NODE_TEXTURE_WIDTH` slots, read with `textureLoad` by the
+    /// traversal since the downlevel limits leave no storage buffers to fragment shaders.
+    fn create_nodes_texture(wgpu_backend: &WGPUBackend, octree: &SparseVoxelOctree) -> wgpu::Texture {
+        return wgpu_backend.device.create_texture_with_data(
+            &wgpu_backend.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Octree nodes"),
+                size: wgpu::Extent3d {
+                    width: NODE_TEXTURE_WIDTH,
+                    height: octree.node_texture_rows(),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R32Uint,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&octree.node_texture_data()),
+        );
+    }
+
+    fn create_bind_group(wgpu_backend: &WGPUBackend, pipeline: &pipeline::VoxelPipeline, buffers: [&wgpu::Buffer; 6], nodes_texture: &wgpu::Texture) -> wgpu::BindGroup {
+        let [camera_position_buffer, camera_inverted_projection_buffer, camera_inverted_view_buffer, surface_configuration_buffer, octree_buffer, palette_buffer] = buffers;
+
+        let nodes_view = nodes_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        return wgpu_backend.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: camera_inverted_projection_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: camera_inverted_view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: surface_configuration_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: octree_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&nodes_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: palette_buffer.as_entire_binding(),
+                },
+            ],
+        });
+    }
+}
\ No newline at end of file
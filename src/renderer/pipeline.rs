@@ -8,32 +8,91 @@ use bytemuck::{
     Zeroable
 };
 
+use glam::Mat4;
+
 use wgpu::{
+    util::DeviceExt,
     BindGroupLayout,
     Face,
+    PipelineLayout,
     RenderPipeline
 };
 
-use crate::WGPUBackend;
+use crate::{
+    WGPUBackend,
+    scene::{
+        codegen,
+        light::LightUniform,
+        material::MaterialUniform,
+        SceneInstruction,
+        SceneUniform
+    },
+    voxel::{
+        brickmap::BrickmapUniform,
+        octree::OctreeUniform,
+        world::WorldUniform,
+        PaletteUniform,
+        VolumeUniform
+    }
+};
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct ColorVertex {
     pub position: [f32; 3],
     pub color: [u8; 4],
+    /// Zero for surfaces lit by the ambient light alone.
+    pub normal: [f32; 3],
+}
+
+/// Layout of the model uniform buffer of color.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct ModelUniform {
+    pub model: [f32; 16],
+    /// Inverse transpose of `model`, which transforms normals.
+    pub normal: [f32; 16],
+}
+
+impl ModelUniform {
+    pub fn new(model: Mat4) -> Self {
+        return Self {
+            model: model.to_cols_array(),
+            normal: model.inverse().transpose().to_cols_array(),
+        };
+    }
+}
+
+/// Format of the depth attachments of pipelines drawing actual geometry.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Full screen pipelines that do not write depth still draw in passes with a depth attachment, they
+/// neither test nor write it.
+fn ignored_depth() -> wgpu::DepthStencilState {
+    return wgpu::DepthStencilState {
+        format: DEPTH_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Always,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    };
 }
 
 pub struct ColorPipeline {
+    /// Group 0, the projection and view of the camera shared by every object.
     pub layout: BindGroupLayout,
+    /// Group 1, the model matrix of the object being drawn.
+    pub model_layout: BindGroupLayout,
     pub pipeline: RenderPipeline,
 }
 
 impl ColorPipeline {
+    /// Depth tests against a `DEPTH_FORMAT` attachment, which the pass drawing meshes has to provide.
     pub fn new(wgpu_backend: &WGPUBackend) -> Self {
         let bind_group_layout = wgpu_backend.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("BindGroupLayout for ColorPipeline"),
             entries: &[
-                wgpu::BindGroupLayoutEntry { // Projection * View * Model Matrix
+                wgpu::BindGroupLayoutEntry { // Projection * View Matrix
                     binding: 0,
                     visibility: wgpu::ShaderStages::VERTEX,
                     ty: wgpu::BindingType::Buffer {
@@ -42,18 +101,56 @@ impl ColorPipeline {
                         min_binding_size: wgpu::BufferSize::new(64),
                     },
                     count: None,
+                },
+                wgpu::BindGroupLayoutEntry { // Camera position
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(12),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry { // Lights
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(mem::size_of::<LightUniform>() as u64),
+                    },
+                    count: None,
+                }
+            ],
+        });
+
+        let model_bind_group_layout = wgpu_backend.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Model BindGroupLayout for ColorPipeline"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry { // Model and Normal Matrices
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(mem::size_of::<ModelUniform>() as u64),
+                    },
+                    count: None,
                 }
             ],
         });
 
+        let source = include_str!("shaders/color.wgsl").replace("// @lights", include_str!("shaders/lights.wgsl"));
+
         let shader = wgpu_backend.device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/color.wgsl"))),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
         });
 
         let pipeline_layout = wgpu_backend.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&bind_group_layout],
+            bind_group_layouts: &[&bind_group_layout, &model_bind_group_layout],
             push_constant_ranges: &[],
         });
 
@@ -72,6 +169,11 @@ impl ColorPipeline {
                     format: wgpu::VertexFormat::Unorm8x4,
                     offset: 3 * 4,
                     shader_location: 1,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 4 * 4,
+                    shader_location: 2,
                 }
             ],
         };
@@ -93,16 +195,46 @@ impl ColorPipeline {
                 cull_mode: Some(Face::Back),
                 ..Default::default()
             },
-            depth_stencil: None,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
             multisample: wgpu::MultisampleState::default(),
             multiview: None,
         });
 
         return Self {
             layout: bind_group_layout,
+            model_layout: model_bind_group_layout,
             pipeline: render_pipeline,
         };
     }
+
+    /// Uniform buffer holding a model matrix, bound to group 1.
+    pub fn create_model(&self, wgpu_backend: &WGPUBackend, model: Mat4) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let model_data = ModelUniform::new(model);
+        let model_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&model_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let model_bind_group = wgpu_backend.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.model_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: model_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        return (model_buffer, model_bind_group);
+    }
 }
 
 #[repr(C)]
@@ -114,10 +246,12 @@ pub struct SimpleVertex {
 pub struct RayMarchingPipeline {
     pub layout: BindGroupLayout,
     pub pipeline: RenderPipeline,
+
+    pipeline_layout: PipelineLayout,
 }
 
 impl RayMarchingPipeline {
-    pub fn new(wgpu_backend: &WGPUBackend) -> Self {
+    pub fn new(wgpu_backend: &WGPUBackend, instructions: &[SceneInstruction]) -> Self {
         let bind_group_layout = wgpu_backend.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("BindGroupLayout for RayMarchingPipeline"),
             entries: &[
@@ -160,13 +294,343 @@ impl RayMarchingPipeline {
                         min_binding_size: wgpu::BufferSize::new(8),
                     },
                     count: None,
+                },
+                wgpu::BindGroupLayoutEntry { // Scene instructions
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(mem::size_of::<SceneUniform>() as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry { // Lights
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(mem::size_of::<LightUniform>() as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry { // Materials
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(mem::size_of::<MaterialUniform>() as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry { // Projection * View Matrix
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(64),
+                    },
+                    count: None,
                 }
             ],
         });
 
+        let pipeline_layout = wgpu_backend.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = Self::create_render_pipeline(wgpu_backend, &pipeline_layout, instructions);
+
+        return Self {
+            layout: bind_group_layout,
+            pipeline: render_pipeline,
+
+            pipeline_layout,
+        };
+    }
+
+    /// Rebuilds the render pipeline around a `map` function generated for a new scene structure. The
+    /// bind group layout is kept, so existing bind groups stay valid.
+    pub fn specialize(&mut self, wgpu_backend: &WGPUBackend, instructions: &[SceneInstruction]) {
+        self.pipeline = Self::create_render_pipeline(wgpu_backend, &self.pipeline_layout, instructions);
+    }
+
+    fn create_render_pipeline(wgpu_backend: &WGPUBackend, pipeline_layout: &PipelineLayout, instructions: &[SceneInstruction]) -> RenderPipeline {
+        let scene_source = format!("{}\n\n{}\n\n{}\n\n{}", include_str!("shaders/sdf.wgsl"), codegen::generate_map(instructions), include_str!("shaders/lights.wgsl"), include_str!("shaders/lighting.wgsl"));
+        let source = include_str!("shaders/ray_marching.wgsl").replace("// @scene", &scene_source);
+
         let shader = wgpu_backend.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Specialized ray marching shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
+        });
+
+        let vertex_size = mem::size_of::<SimpleVertex>();
+
+        let buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: vertex_size as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 0,
+                }
+            ],
+        };
+
+        let render_pipeline = wgpu_backend.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/ray_marching.wgsl"))),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[buffer_layout],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu_backend.config.format.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(Face::Back),
+                ..Default::default()
+            },
+            // The depth of each hit, so meshes drawn afterwards in the same pass are hidden behind it
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        return render_pipeline;
+    }
+}
+
+pub struct BlitPipeline {
+    pub layout: BindGroupLayout,
+    pub pipeline: RenderPipeline,
+}
+
+impl BlitPipeline {
+    pub fn new(wgpu_backend: &WGPUBackend) -> Self {
+        let bind_group_layout = wgpu_backend.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("BindGroupLayout for BlitPipeline"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry { // Source image, same size as the surface
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }
+            ],
+        });
+
+        let shader = wgpu_backend.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/blit.wgsl"))),
+        });
+
+        let pipeline_layout = wgpu_backend.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_size = mem::size_of::<SimpleVertex>();
+
+        let buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: vertex_size as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 0,
+                }
+            ],
+        };
+
+        let render_pipeline = wgpu_backend.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[buffer_layout],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu_backend.config.format.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(ignored_depth()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        return Self {
+            layout: bind_group_layout,
+            pipeline: render_pipeline,
+        };
+    }
+}
+
+/// How voxel.wgsl walks the volume, both share its camera, palette and shading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VoxelTraversal {
+    /// DDA over a 3D texture of palette indices.
+    Dense,
+    /// Sparse voxel octree flattened into a 2D texture of nodes.
+    Octree,
+    /// 3D texture of brickmap cells pointing into a 3D atlas of bricks, bound at 7.
+    Brickmap,
+    /// Ring of resident chunks around the camera, a 3D texture flagging the chunks that hold voxels
+    /// and a 3D texture of their voxels, bound at 7.
+    World,
+}
+
+impl VoxelTraversal {
+    /// `VoxelHit` and the `trace_volume` function of the traversal, bound at 4 and 5.
+    pub fn source(self) -> String {
+        let traversal = match self {
+            VoxelTraversal::Dense => include_str!("shaders/voxel_dense.wgsl"),
+            VoxelTraversal::Octree => include_str!("shaders/voxel_octree.wgsl"),
+            VoxelTraversal::Brickmap => include_str!("shaders/voxel_brickmap.wgsl"),
+            VoxelTraversal::World => include_str!("shaders/voxel_world.wgsl"),
+        };
+
+        return format!("{}\n\n{}", include_str!("shaders/voxel_hit.wgsl"), traversal);
+    }
+}
+
+pub struct VoxelPipeline {
+    pub layout: BindGroupLayout,
+    pub pipeline: RenderPipeline,
+}
+
+impl VoxelPipeline {
+    pub fn new(wgpu_backend: &WGPUBackend, traversal: VoxelTraversal) -> Self {
+        let (placement_size, voxels_dimension) = match traversal {
+            VoxelTraversal::Dense => (mem::size_of::<VolumeUniform>(), wgpu::TextureViewDimension::D3),
+            VoxelTraversal::Octree => (mem::size_of::<OctreeUniform>(), wgpu::TextureViewDimension::D2),
+            VoxelTraversal::Brickmap => (mem::size_of::<BrickmapUniform>(), wgpu::TextureViewDimension::D3),
+            VoxelTraversal::World => (mem::size_of::<WorldUniform>(), wgpu::TextureViewDimension::D3),
+        };
+
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry { // Camera position
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(12),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(64),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(64),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(8),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry { // Volume placement
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(placement_size as u64),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry { // Palette indices or octree nodes
+                binding: 5,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Uint,
+                    view_dimension: voxels_dimension,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry { // Palette colors
+                binding: 6,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(mem::size_of::<PaletteUniform>() as u64),
+                },
+                count: None,
+            }
+        ];
+
+        if traversal == VoxelTraversal::Brickmap || traversal == VoxelTraversal::World {
+            entries.push(wgpu::BindGroupLayoutEntry { // Brick pool or chunk voxels
+                binding: 7,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Uint,
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                count: None,
+            });
+        }
+
+        let bind_group_layout = wgpu_backend.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("BindGroupLayout for VoxelPipeline"),
+            entries: &entries,
+        });
+
+        let source = include_str!("shaders/voxel.wgsl").replace("// @traversal", &traversal.source());
+
+        let shader = wgpu_backend.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Voxel ray marching shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
         });
 
         let pipeline_layout = wgpu_backend.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -206,7 +670,7 @@ impl RayMarchingPipeline {
                 cull_mode: Some(Face::Back),
                 ..Default::default()
             },
-            depth_stencil: None,
+            depth_stencil: Some(ignored_depth()),
             multisample: wgpu::MultisampleState::default(),
             multiview: None,
         });
@@ -0,0 +1,218 @@
+//! Compute probes for the CPU/GPU parity tests.
+//!
+//! A probe runs the `probe` compute entry point of a shader once per input element, on the software
+//! fallback adapter, and reads back one `vec4<f32>` per element. Inputs are `array<vec4<f32>>`
+//! storage buffers bound from 0, the output is the storage buffer at `OUTPUT_BINDING`, and the shader
+//! under test keeps its own uniform at 4 and textures at their bindings.
+
+use std::borrow::Cow;
+
+use glam::UVec3;
+
+use wgpu::util::DeviceExt;
+
+/// Binding of the `array<vec4<f32>>` the probe writes its results to.
+pub const OUTPUT_BINDING: u32 = 2;
+pub const UNIFORM_BINDING: u32 = 4;
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// Texture read by the shader under test, always with unsigned integer texels.
+pub struct ProbeTexture<'a> {
+    pub binding: u32,
+    pub size: UVec3,
+    pub dimension: wgpu::TextureDimension,
+    pub format: wgpu::TextureFormat,
+    pub data: &'a [u8],
+}
+
+fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    return wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+}
+
+/// Runs the `probe` entry point of `source` over `inputs`, which all have the same length, with
+/// `uniform` and `textures` bound next to them. Returns `None` when no adapter is available.
+pub fn run(source: &str, inputs: &[&[[f32; 4]]], uniform: &[u8], textures: &[ProbeTexture]) -> Option<Vec<[f32; 4]>> {
+    let count = inputs.first().map_or(0, |input| input.len());
+
+    let instance = wgpu::Instance::default();
+
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        force_fallback_adapter: true,
+        compatible_surface: None,
+    }))?;
+
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+        },
+        None,
+    )).ok()?;
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Probe shader"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+    });
+
+    let mut layout_entries = (0..inputs.len() as u32).map(|binding| storage_entry(binding, true)).collect::<Vec<_>>();
+
+    layout_entries.push(storage_entry(OUTPUT_BINDING, false));
+    layout_entries.push(wgpu::BindGroupLayoutEntry {
+        binding: UNIFORM_BINDING,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    });
+
+    for texture in textures {
+        layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: texture.binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Uint,
+                view_dimension: match texture.dimension {
+                    wgpu::TextureDimension::D3 => wgpu::TextureViewDimension::D3,
+                    _ => wgpu::TextureViewDimension::D2,
+                },
+                multisampled: false,
+            },
+            count: None,
+        });
+    }
+
+    // Explicit layout, the derived one would drop the bindings an entry point happens not to use
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: None,
+        entries: &layout_entries,
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point: "probe",
+    });
+
+    let input_buffers = inputs.iter().map(|input| device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Probe input"),
+        contents: bytemuck::cast_slice(input),
+        usage: wgpu::BufferUsages::STORAGE,
+    })).collect::<Vec<_>>();
+
+    let size = (count * 16) as wgpu::BufferAddress;
+
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Probe output"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: uniform,
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let texture_views = textures.iter().map(|texture| device.create_texture_with_data(
+        &queue,
+        &wgpu::TextureDescriptor {
+            label: Some("Probe texture"),
+            size: wgpu::Extent3d {
+                width: texture.size.x,
+                height: texture.size.y,
+                depth_or_array_layers: texture.size.z,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: texture.dimension,
+            format: texture.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        texture.data,
+    ).create_view(&wgpu::TextureViewDescriptor::default())).collect::<Vec<_>>();
+
+    let mut entries = input_buffers.iter().enumerate().map(|(binding, buffer)| wgpu::BindGroupEntry {
+        binding: binding as u32,
+        resource: buffer.as_entire_binding(),
+    }).collect::<Vec<_>>();
+
+    entries.push(wgpu::BindGroupEntry {
+        binding: OUTPUT_BINDING,
+        resource: output_buffer.as_entire_binding(),
+    });
+    entries.push(wgpu::BindGroupEntry {
+        binding: UNIFORM_BINDING,
+        resource: uniform_buffer.as_entire_binding(),
+    });
+
+    for (texture, view) in textures.iter().zip(texture_views.iter()) {
+        entries.push(wgpu::BindGroupEntry {
+            binding: texture.binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        });
+    }
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &entries,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: None,
+    });
+
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups((count as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+
+    encoder.copy_buffer_to_buffer(&output_buffer, 0, &readback_buffer, 0, size);
+
+    queue.submit(Some(encoder.finish()));
+
+    let slice = readback_buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.expect("Failed to map readback buffer"));
+    device.poll(wgpu::Maintain::Wait);
+
+    let results = bytemuck::cast_slice::<u8, [f32; 4]>(&slice.get_mapped_range()).to_vec();
+
+    return Some(results);
+}
\ No newline at end of file
@@ -3,33 +3,77 @@ use wgpu::util::DeviceExt;
 use crate::{
     WGPUBackend,
     logic::play::Play,
+    mesh::MeshObject,
     renderer::{
         pipeline,
-        pipeline::ColorVertex
+        pipeline::{
+            ColorVertex,
+            ModelUniform
+        },
+        registry::Pipeline
+    },
+    scene::{
+        light::LightUniform,
+        Scene,
+        SceneError
     }
 };
 
+struct ObjectBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+
+    model_buffer: wgpu::Buffer,
+
+    model_bind_group: wgpu::BindGroup,
+}
+
+/// Draws the mesh objects of `Play`, each placed by its own model matrix and lit by the lights of the
+/// scene the ray marcher draws.
 pub struct TestRasterizer {
     pipeline: pipeline::ColorPipeline,
 
-    projection_view_model_buffer: wgpu::Buffer,
+    projection_view_buffer: wgpu::Buffer,
+    camera_position_buffer: wgpu::Buffer,
+    lights_buffer: wgpu::Buffer,
+
+    uploaded_scene: Scene,
+    scene_error: Option<SceneError>,
 
     bind_group: wgpu::BindGroup,
 
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
+    uploaded_objects: Vec<MeshObject>,
+    objects: Vec<ObjectBuffers>,
 }
 
-impl TestRasterizer {
-    pub fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self {
+impl Pipeline for TestRasterizer {
+    fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self {
         let pipeline = pipeline::ColorPipeline::new(wgpu_backend);
 
-        let projection_view_model_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
-        let projection_view_model_ref: &[f32; 16] = projection_view_model_data.as_ref();
-        let projection_view_model_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        let projection_view_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let projection_view_ref: &[f32; 16] = projection_view_data.as_ref();
+        let projection_view_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(projection_view_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+        let camera_position_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(projection_view_model_ref),
+            contents: bytemuck::cast_slice(camera_position_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let lights_data = play.scene.light_uniform();
+        let scene_error = lights_data.err();
+
+        let lights_data = lights_data.unwrap_or_else(|_| LightUniform::new(play.scene.ambient, &play.scene.shadows, &play.scene.occlusion, &[]));
+        let lights_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&lights_data),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -39,58 +83,151 @@ impl TestRasterizer {
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: projection_view_model_buffer.as_entire_binding(),
+                    resource: projection_view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: camera_position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: lights_buffer.as_entire_binding(),
                 },
             ],
         });
 
-        // Back culled green cube
-        let mut vertices = Vec::<ColorVertex>::new();
-        vertices.push(ColorVertex { position: [0.0, 0.0, 0.0], color: [0, 255, 0, 255] });
-        vertices.push(ColorVertex { position: [1.0, 0.0, 0.0], color: [0, 255, 0, 255] });
-        vertices.push(ColorVertex { position: [0.0, 0.0, 1.0], color: [0, 255, 0, 255] });
-        vertices.push(ColorVertex { position: [1.0, 0.0, 1.0], color: [0, 255, 0, 255] });
-
-        let vertex_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let indices: [u16; 6] = [0, 1, 2, 2, 1, 3];
-
-        let index_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&indices),
-            usage: wgpu::BufferUsages::INDEX,
-        });
+        let objects = Self::upload_all(wgpu_backend, &pipeline, &play.objects);
 
         return Self {
             pipeline,
 
-            projection_view_model_buffer,
+            projection_view_buffer,
+            camera_position_buffer,
+            lights_buffer,
+
+            uploaded_scene: play.scene.clone(),
+            scene_error,
 
             bind_group,
 
-            vertex_buffer,
-            index_buffer,
-            num_indices: indices.len() as u32,
+            uploaded_objects: play.objects.clone(),
+            objects,
         };
     }
 
-    pub fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+    fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let projection_view_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let projection_view_ref: &[f32; 16] = projection_view_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.projection_view_buffer, 0, bytemuck::cast_slice(projection_view_ref));
+
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_position_buffer, 0, bytemuck::cast_slice(camera_position_ref));
+
+        if play.scene != self.uploaded_scene {
+            // Invalid lights keep the previous ones
+            match play.scene.light_uniform() {
+                Ok(lights_data) => {
+                    wgpu_backend.queue.write_buffer(&self.lights_buffer, 0, bytemuck::bytes_of(&lights_data));
+                    self.scene_error = None;
+                }
+                Err(error) => self.scene_error = Some(error),
+            }
+
+            self.uploaded_scene = play.scene.clone();
+        }
+
+        if play.objects != self.uploaded_objects {
+            let moved = play.objects.len() == self.uploaded_objects.len() && play.objects.iter().zip(self.uploaded_objects.iter())
+                .all(|(object, uploaded)| object.name == uploaded.name && object.mesh == uploaded.mesh);
+
+            // Objects that only moved keep their meshes on the GPU, added, removed or edited ones are uploaded again
+            if moved {
+                let visible = play.objects.iter().filter(|object| !object.mesh.indices.is_empty());
+
+                for (buffers, object) in self.objects.iter().zip(visible) {
+                    let model_data = ModelUniform::new(object.transform);
+
+                    wgpu_backend.queue.write_buffer(&buffers.model_buffer, 0, bytemuck::bytes_of(&model_data));
+                }
+            } else {
+                self.objects = Self::upload_all(wgpu_backend, &self.pipeline, &play.objects);
+            }
+
+            self.uploaded_objects = play.objects.clone();
+        }
+    }
+
+    fn resize(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let projection_view_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let projection_view_ref: &[f32; 16] = projection_view_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.projection_view_buffer, 0, bytemuck::cast_slice(projection_view_ref));
+    }
+
+    fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
         pass.set_pipeline(&self.pipeline.pipeline);
         pass.set_bind_group(0, &self.bind_group, &[]);
 
-        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        pass.draw_indexed(0..self.num_indices, 0, 0..1);
+        for object in self.objects.iter() {
+            pass.set_bind_group(1, &object.model_bind_group, &[]);
+            pass.set_vertex_buffer(0, object.vertex_buffer.slice(..));
+            pass.set_index_buffer(object.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            pass.draw_indexed(0..object.num_indices, 0, 0..1);
+        }
+    }
+
+    fn triangle_count(&self) -> Option<u32> {
+        return Some(self.objects.iter().map(|object| object.num_indices / 3).sum());
     }
 
-    pub fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
-        let projection_view_model_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
-        let projection_view_model_ref: &[f32; 16] = projection_view_model_data.as_ref();
+    fn scene_error(&self) -> Option<SceneError> {
+        return self.scene_error;
+    }
+}
 
-        wgpu_backend.queue.write_buffer(&self.projection_view_model_buffer, 0, bytemuck::cast_slice(projection_view_model_ref));
+impl TestRasterizer {
+    /// Uploads the objects with something to draw.
+    fn upload_all(wgpu_backend: &WGPUBackend, pipeline: &pipeline::ColorPipeline, objects: &[MeshObject]) -> Vec<ObjectBuffers> {
+        return objects.iter()
+            .filter(|object| !object.mesh.indices.is_empty())
+            .map(|object| Self::upload(wgpu_backend, pipeline, object))
+            .collect();
+    }
+
+    fn upload(wgpu_backend: &WGPUBackend, pipeline: &pipeline::ColorPipeline, object: &MeshObject) -> ObjectBuffers {
+        let vertices = (0..object.mesh.positions.len())
+            .map(|i| ColorVertex {
+                position: object.mesh.positions[i].to_array(),
+                color: object.mesh.colors[i],
+                normal: object.mesh.normals[i].to_array(),
+            })
+            .collect::<Vec<_>>();
+
+        let vertex_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&object.name),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&object.name),
+            contents: bytemuck::cast_slice(&object.mesh.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let (model_buffer, model_bind_group) = pipeline.create_model(wgpu_backend, object.transform);
+
+        return ObjectBuffers {
+            vertex_buffer,
+            index_buffer,
+            num_indices: object.mesh.indices.len() as u32,
+
+            model_buffer,
+
+            model_bind_group,
+        };
     }
 }
\ No newline at end of file
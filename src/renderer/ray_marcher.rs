@@ -1,3 +1,5 @@
+use bytemuck::Zeroable;
+
 use wgpu::util::DeviceExt;
 
 use crate::{
@@ -5,7 +7,17 @@ use crate::{
     logic::play::Play,
     renderer::{
         pipeline,
-        pipeline::SimpleVertex
+        pipeline::SimpleVertex,
+        registry::Pipeline
+    },
+    scene::{
+        codegen,
+        light::LightUniform,
+        material::MaterialUniform,
+        Scene,
+        SceneError,
+        SceneInstruction,
+        SceneUniform
     }
 };
 
@@ -17,6 +29,14 @@ pub struct TestRayMarcher {
     camera_inverted_projection_buffer: wgpu::Buffer,
     camera_inverted_view_buffer: wgpu::Buffer,
     surface_configuration_buffer: wgpu::Buffer,
+    scene_buffer: wgpu::Buffer,
+    lights_buffer: wgpu::Buffer,
+    materials_buffer: wgpu::Buffer,
+    projection_view_buffer: wgpu::Buffer,
+
+    uploaded_scene: Scene,
+    scene_structure: Vec<u32>,
+    scene_error: Option<SceneError>,
 
     bind_group: wgpu::BindGroup,
 
@@ -25,9 +45,14 @@ pub struct TestRayMarcher {
     num_indices: u32,
 }
 
-impl TestRayMarcher {
-    pub fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self {
-        let pipeline = pipeline::RayMarchingPipeline::new(wgpu_backend);
+impl Pipeline for TestRayMarcher {
+    fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self {
+        let scene_data = Self::build_scene(&play.scene);
+        let lights_data = play.scene.light_uniform();
+        let scene_error = scene_data.as_ref().err().or(lights_data.as_ref().err()).copied();
+
+        let (scene_instructions, materials_data) = scene_data.unwrap_or_else(|_| (Vec::new(), MaterialUniform::zeroed()));
+        let pipeline = pipeline::RayMarchingPipeline::new(wgpu_backend, &scene_instructions);
 
         let camera_position_data = play.camera.position;
         let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
@@ -60,6 +85,34 @@ impl TestRayMarcher {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let scene_data = SceneUniform::new(&scene_instructions);
+        let scene_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&scene_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let lights_data = lights_data.unwrap_or_else(|_| LightUniform::new(play.scene.ambient, &play.scene.shadows, &play.scene.occlusion, &[]));
+        let lights_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&lights_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let materials_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&materials_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let projection_view_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let projection_view_ref: &[f32; 16] = projection_view_data.as_ref();
+        let projection_view_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(projection_view_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         let bind_group = wgpu_backend.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &pipeline.layout,
@@ -80,15 +133,32 @@ impl TestRayMarcher {
                     binding: 3,
                     resource: surface_configuration_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: scene_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: lights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: materials_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: projection_view_buffer.as_entire_binding(),
+                },
             ],
         });
 
         // Back culled green cube
-        let mut vertices = Vec::<SimpleVertex>::new();
-        vertices.push(SimpleVertex { position: [-1.0, 1.0] });
-        vertices.push(SimpleVertex { position: [-1.0, -1.0] });
-        vertices.push(SimpleVertex { position: [1.0, -1.0] });
-        vertices.push(SimpleVertex { position: [1.0, 1.0] });
+        let vertices = [
+            SimpleVertex { position: [-1.0, 1.0] },
+            SimpleVertex { position: [-1.0, -1.0] },
+            SimpleVertex { position: [1.0, -1.0] },
+            SimpleVertex { position: [1.0, 1.0] },
+        ];
 
         let vertex_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
@@ -111,6 +181,14 @@ impl TestRayMarcher {
             camera_inverted_projection_buffer,
             camera_inverted_view_buffer,
             surface_configuration_buffer,
+            scene_buffer,
+            lights_buffer,
+            materials_buffer,
+            projection_view_buffer,
+
+            uploaded_scene: play.scene.clone(),
+            scene_structure: codegen::structure(&scene_instructions),
+            scene_error,
 
             bind_group,
 
@@ -120,7 +198,7 @@ impl TestRayMarcher {
         };
     }
 
-    pub fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+    fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
         let camera_position_data = play.camera.position;
         let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
 
@@ -130,9 +208,39 @@ impl TestRayMarcher {
         let camera_inverted_view_ref: &[f32; 16] = camera_inverted_view_data.as_ref();
 
         wgpu_backend.queue.write_buffer(&self.camera_inverted_view_buffer, 0, bytemuck::cast_slice(camera_inverted_view_ref));
+
+        let projection_view_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let projection_view_ref: &[f32; 16] = projection_view_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.projection_view_buffer, 0, bytemuck::cast_slice(projection_view_ref));
+
+        if play.scene != self.uploaded_scene {
+            let scene_data = Self::build_scene(&play.scene);
+            let lights_data = play.scene.light_uniform();
+
+            self.scene_error = scene_data.as_ref().err().or(lights_data.as_ref().err()).copied();
+
+            // An invalid scene keeps the previous one on screen
+            if let Ok((scene_instructions, materials_data)) = scene_data {
+                // Only structural changes need a new shader, parameters live in the uniform buffer
+                if codegen::update_structure(&mut self.scene_structure, &scene_instructions) {
+                    self.pipeline.specialize(wgpu_backend, &scene_instructions);
+                }
+
+                let scene_data = SceneUniform::new(&scene_instructions);
+                wgpu_backend.queue.write_buffer(&self.scene_buffer, 0, bytemuck::bytes_of(&scene_data));
+                wgpu_backend.queue.write_buffer(&self.materials_buffer, 0, bytemuck::bytes_of(&materials_data));
+            }
+
+            if let Ok(lights_data) = lights_data {
+                wgpu_backend.queue.write_buffer(&self.lights_buffer, 0, bytemuck::bytes_of(&lights_data));
+            }
+
+            self.uploaded_scene = play.scene.clone();
+        }
     }
 
-    pub fn process_resize(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+    fn resize(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
         let surface_configuration_data = [wgpu_backend.config.width as f32, wgpu_backend.config.height as f32];
         wgpu_backend.queue.write_buffer(&self.surface_configuration_buffer, 0, bytemuck::cast_slice(surface_configuration_data.as_ref()));
 
@@ -140,9 +248,14 @@ impl TestRayMarcher {
         let camera_inverted_projection_ref: &[f32; 16] = camera_inverted_projection_data.as_ref();
 
         wgpu_backend.queue.write_buffer(&self.camera_inverted_projection_buffer, 0, bytemuck::cast_slice(camera_inverted_projection_ref));
+
+        let projection_view_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let projection_view_ref: &[f32; 16] = projection_view_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.projection_view_buffer, 0, bytemuck::cast_slice(projection_view_ref));
     }
 
-    pub fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+    fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
         pass.set_pipeline(&self.pipeline.pipeline);
         pass.set_bind_group(0, &self.bind_group, &[]);
 
@@ -150,4 +263,15 @@ impl TestRayMarcher {
         pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
         pass.draw_indexed(0..self.num_indices, 0, 0..1);
     }
+
+    fn scene_error(&self) -> Option<SceneError> {
+        return self.scene_error;
+    }
+}
+
+impl TestRayMarcher {
+    /// Flattens the scene and packs its materials, both are uploaded together or not at all.
+    fn build_scene(scene: &Scene) -> Result<(Vec<SceneInstruction>, MaterialUniform), SceneError> {
+        return Ok((scene.instructions()?, scene.material_uniform()?));
+    }
 }
\ No newline at end of file
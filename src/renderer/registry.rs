@@ -0,0 +1,290 @@
+use winit::keyboard::KeyCode;
+
+use crate::{
+    logic::play::Play,
+    scene::SceneError,
+    WGPUBackend
+};
+
+/// Cycles through the registered pipelines, in the order they were registered.
+pub const CYCLE_KEY: KeyCode = KeyCode::Tab;
+
+/// A way of drawing `Play`, selected by name in the registry. Registered pipelines are created the first
+/// time they are selected and resized with the surface from then on, only the selected one, or the
+/// layers it stacks, is updated and rendered.
+pub trait Pipeline {
+    fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self where Self: Sized;
+
+    /// Uploads what changed in `play` since the last frame.
+    fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play);
+
+    /// Recreates what depends on the size of the surface.
+    fn resize(&mut self, wgpu_backend: &WGPUBackend, play: &Play);
+
+    /// Draws into the scene pass, which has a color and a depth attachment.
+    fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>);
+
+    /// Work left before frames show everything, such as chunks waiting for an upload.
+    fn pending(&self) -> usize {
+        return 0;
+    }
+
+    /// Triangles drawn per frame, for pipelines rasterizing meshes.
+    fn triangle_count(&self) -> Option<u32> {
+        return None;
+    }
+
+    /// Background of the pixels the pipeline does not draw.
+    fn clear_color(&self) -> wgpu::Color {
+        return wgpu::Color {
+            r: 0.0,
+            g: 1.0,
+            b: 1.0,
+            a: 1.0,
+        };
+    }
+
+    /// Whether the pipeline draws the chunked world, which then streams around the camera.
+    fn draws_world(&self) -> bool {
+        return false;
+    }
+
+    /// Why the scene of `play` could not be uploaded, the previous valid scene stays on screen until
+    /// it changes again.
+    fn scene_error(&self) -> Option<SceneError> {
+        return None;
+    }
+}
+
+/// Creates a registered pipeline once it is selected.
+type Constructor = fn(&WGPUBackend, &Play) -> Box<dyn Pipeline>;
+
+struct Entry {
+    name: &'static str,
+    key: Option<KeyCode>,
+    /// `None` for entries stacking the pipelines of other entries.
+    constructor: Option<Constructor>,
+    /// Set once the entry is first selected, or by `insert`.
+    pipeline: Option<Box<dyn Pipeline>>,
+    /// Indices of the entries drawn, bottom first, the entry itself for a pipeline.
+    layers: Vec<usize>,
+}
+
+impl Entry {
+    fn is_pipeline(&self) -> bool {
+        return self.constructor.is_some() || self.pipeline.is_some();
+    }
+}
+
+fn construct<P: Pipeline + 'static>(wgpu_backend: &WGPUBackend, play: &Play) -> Box<dyn Pipeline> {
+    return Box::new(P::new(wgpu_backend, play));
+}
+
+pub struct PipelineRegistry {
+    entries: Vec<Entry>,
+}
+
+impl PipelineRegistry {
+    pub fn new() -> Self {
+        return Self {
+            entries: Vec::new(),
+        };
+    }
+
+    /// Registers a pipeline under `name`, selected by `key` when one is given. It is only created by
+    /// `build`, once selected.
+    pub fn register<P: Pipeline + 'static>(&mut self, name: &'static str, key: Option<KeyCode>) {
+        self.push(Entry {
+            name,
+            key,
+            constructor: Some(construct::<P>),
+            pipeline: None,
+            layers: vec![self.entries.len()],
+        });
+    }
+
+    /// Registers a pipeline that is already created.
+    #[cfg(test)]
+    fn insert(&mut self, name: &'static str, key: Option<KeyCode>, pipeline: Box<dyn Pipeline>) {
+        self.push(Entry {
+            name,
+            key,
+            constructor: None,
+            pipeline: Some(pipeline),
+            layers: vec![self.entries.len()],
+        });
+    }
+
+    /// Registers `name` drawing the registered pipelines `layers` in order into the same pass, sharing
+    /// them instead of creating copies.
+    pub fn register_layers(&mut self, name: &'static str, key: Option<KeyCode>, layers: &[&str]) {
+        assert!(!layers.is_empty(), "Pipeline {:?} has no layers", name);
+
+        let layers = layers.iter().map(|layer| {
+            let index = self.entries.iter().position(|entry| entry.name == *layer && entry.is_pipeline());
+
+            return index.unwrap_or_else(|| panic!("Layer {:?} of {:?} is not a registered pipeline", layer, name));
+        }).collect();
+
+        self.push(Entry {
+            name,
+            key,
+            constructor: None,
+            pipeline: None,
+            layers,
+        });
+    }
+
+    fn push(&mut self, entry: Entry) {
+        assert!(self.entries.iter().all(|other| other.name != entry.name), "Pipeline {:?} is registered twice", entry.name);
+
+        self.entries.push(entry);
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        return self.entries.iter().map(|entry| entry.name).collect();
+    }
+
+    /// Creates the pipelines `name` draws that were not selected before. Returns false when `name` is
+    /// not registered.
+    pub fn build(&mut self, name: &str, wgpu_backend: &WGPUBackend, play: &Play) -> bool {
+        let Some(entry) = self.entries.iter().find(|entry| entry.name == name) else {
+            return false;
+        };
+
+        for index in entry.layers.clone() {
+            let layer = &mut self.entries[index];
+
+            if layer.pipeline.is_none() {
+                let constructor = layer.constructor.expect("Layers are pipelines");
+
+                layer.pipeline = Some(constructor(wgpu_backend, play));
+            }
+        }
+
+        return true;
+    }
+
+    /// The pipelines `name` draws, bottom first, once `build` created them.
+    pub fn layers(&self, name: &str) -> Option<impl Iterator<Item = &dyn Pipeline> + '_> {
+        let entry = self.entries.iter().find(|entry| entry.name == name)?;
+
+        return Some(entry.layers.iter().map(|&index| self.entries[index].pipeline.as_deref().expect("Selected pipelines are built")));
+    }
+
+    /// Creates the pipelines `name` draws if needed, then updates them.
+    pub fn update(&mut self, name: &str, wgpu_backend: &WGPUBackend, play: &Play) {
+        if !self.build(name, wgpu_backend, play) {
+            return;
+        }
+
+        let entry = self.entries.iter().find(|entry| entry.name == name).expect("Built entries are registered");
+
+        for index in entry.layers.clone() {
+            self.entries[index].pipeline.as_mut().expect("Built layers are created").update(wgpu_backend, play);
+        }
+    }
+
+    /// Resizes every pipeline created so far, selected or not, each one exactly once.
+    pub fn resize(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        for pipeline in self.entries.iter_mut().filter_map(|entry| entry.pipeline.as_mut()) {
+            pipeline.resize(wgpu_backend, play);
+        }
+    }
+
+    /// Name of the pipeline `key` selects: the one registered with it, or the one after `current` for
+    /// `CYCLE_KEY`.
+    pub fn select(&self, current: &str, key: KeyCode) -> Option<&'static str> {
+        if key == CYCLE_KEY && !self.entries.is_empty() {
+            let next = self.entries.iter().position(|entry| entry.name == current).map_or(0, |index| (index + 1) % self.entries.len());
+
+            return Some(self.entries[next].name);
+        }
+
+        return self.entries.iter().find(|entry| entry.key == Some(key)).map(|entry| entry.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::keyboard::KeyCode;
+
+    use crate::{
+        logic::play::Play,
+        WGPUBackend
+    };
+
+    use super::{
+        Pipeline,
+        PipelineRegistry,
+        CYCLE_KEY
+    };
+
+    struct Empty;
+
+    impl Pipeline for Empty {
+        fn new(_: &WGPUBackend, _: &Play) -> Self {
+            return Self;
+        }
+
+        fn update(&mut self, _: &WGPUBackend, _: &Play) {}
+
+        fn resize(&mut self, _: &WGPUBackend, _: &Play) {}
+
+        fn render<'a>(&'a self, _: &mut wgpu::RenderPass<'a>) {}
+    }
+
+    /// Reports its id as its pending work, to tell the pipelines of layers apart.
+    struct Id(usize);
+
+    impl Pipeline for Id {
+        fn new(_: &WGPUBackend, _: &Play) -> Self {
+            return Self(0);
+        }
+
+        fn update(&mut self, _: &WGPUBackend, _: &Play) {}
+
+        fn resize(&mut self, _: &WGPUBackend, _: &Play) {}
+
+        fn render<'a>(&'a self, _: &mut wgpu::RenderPass<'a>) {}
+
+        fn pending(&self) -> usize {
+            return self.0;
+        }
+    }
+
+    #[test]
+    fn keys_select_and_cycle_through_pipelines() {
+        let mut registry = PipelineRegistry::new();
+        registry.register::<Empty>("first", Some(KeyCode::KeyE));
+        registry.register::<Empty>("second", None);
+        registry.register::<Empty>("third", Some(KeyCode::KeyR));
+
+        assert_eq!(registry.names(), ["first", "second", "third"]);
+
+        assert_eq!(registry.select("first", KeyCode::KeyR), Some("third"));
+        assert_eq!(registry.select("third", KeyCode::KeyE), Some("first"));
+        assert_eq!(registry.select("first", KeyCode::KeyQ), None);
+
+        assert_eq!(registry.select("first", CYCLE_KEY), Some("second"));
+        assert_eq!(registry.select("second", CYCLE_KEY), Some("third"));
+        assert_eq!(registry.select("third", CYCLE_KEY), Some("first"));
+        assert_eq!(registry.select("unknown", CYCLE_KEY), Some("first"));
+    }
+
+    #[test]
+    fn layers_draw_registered_pipelines() {
+        let mut registry = PipelineRegistry::new();
+        registry.insert("bottom", None, Box::new(Id(1)));
+        registry.insert("top", None, Box::new(Id(2)));
+        registry.register_layers("both", Some(KeyCode::KeyH), &["bottom", "top"]);
+
+        assert_eq!(registry.select("top", CYCLE_KEY), Some("both"));
+        assert_eq!(registry.select("bottom", KeyCode::KeyH), Some("both"));
+
+        let ids = |name| registry.layers(name).unwrap().map(|layer| layer.pending()).collect::<Vec<_>>();
+        assert_eq!(ids("both"), [1, 2]);
+        assert_eq!(ids("top"), [2]);
+        assert!(registry.layers("unknown").is_none());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,37 @@
+struct VertexOutput {
+    @builtin(position) out_vertex_pos: vec4<f32>
+}
+
+struct FragmentOutput {
+    @location(0) out_frag_color: vec4<f32>
+}
+
+@group(0)
+@binding(0)
+var source_image: texture_2d<f32>;
+
+@vertex
+fn vs_main(
+
+    @location(0) in_vertex_position: vec2<f32>
+
+) -> VertexOutput {
+    var result: VertexOutput;
+
+    result.out_vertex_pos = vec4<f32> (in_vertex_position.x, in_vertex_position.y, 0.0, 1.0);
+
+    return result;
+}
+
+@fragment
+fn fs_main(
+
+    @builtin(position) in_frag_position: vec4<f32>,
+
+) -> FragmentOutput {
+    var result: FragmentOutput;
+
+    result.out_frag_color = textureLoad(source_image, vec2<i32> (in_frag_position.xy), 0);
+
+    return result;
+}
\ No newline at end of file
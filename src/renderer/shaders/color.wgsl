@@ -1,25 +1,74 @@
 struct VertexOutput {
-    @builtin(position) out_vertex_pos: vec4<f32>
+    @builtin(position) out_vertex_pos: vec4<f32>,
+    @location(0) out_vertex_color: vec4<f32>,
+    @location(1) out_vertex_world_position: vec3<f32>,
+    @location(2) out_vertex_normal: vec3<f32>
 }
 
 struct FragmentOutput {
     @location(0) out_frag_color: vec4<f32>
 }
 
+struct Model {
+    model_matrix: mat4x4<f32>,
+    // Inverse transpose of the model matrix, so normals stay perpendicular under non-uniform scales
+    normal_matrix: mat4x4<f32>,
+}
+
+// Replaced by lights.wgsl
+// @lights
+
+// Meshes have no material of their own, their vertex colors are the albedo of the default material,
+// keep in sync with Material::new in scene/material.rs
+const MESH_ROUGHNESS: f32 = 0.5;
+const MESH_METALLIC: f32 = 0.0;
+
+@group(0)
+@binding(0)
+var<uniform> projection_view_matrix: mat4x4<f32>;
+
 @group(0)
+@binding(1)
+var<uniform> camera_position: vec3<f32>;
+
+@group(0)
+@binding(2)
+var<uniform> lights: Lights;
+
+@group(1)
 @binding(0)
-var<uniform> projection_view_model_matrix: mat4x4<f32>;
+var<uniform> model: Model;
+
+// Ambient light and Blinn-Phong reflection of each light, like shade in lighting.wgsl but without the
+// shadows and occlusion marched through the distance field
+fn shade_mesh (p: vec3<f32>, normal: vec3<f32>, view: vec3<f32>, material: Material) -> vec3<f32> {
+    var color = lights.ambient.xyz * material.albedo;
+
+    for (var i: u32 = 0u; i < lights.light_count; i = i + 1u) {
+        let incident = light_incident(lights.lights[i], p);
+
+        color = color + blinn_phong(normal, incident.to_light, view, material) * incident.radiance;
+    }
+
+    return color + material.emissive;
+}
 
 @vertex
 fn vs_main(
 
     @location(0) in_vertex_position: vec3<f32>,
     @location(1) in_vertex_color: vec4<f32>,
+    @location(2) in_vertex_normal: vec3<f32>,
 
 ) -> VertexOutput {
     var result: VertexOutput;
 
-    result.out_vertex_pos = projection_view_model_matrix * vec4<f32> (in_vertex_position.x, in_vertex_position.y, in_vertex_position.z, 1.0);
+    let world_position = model.model_matrix * vec4<f32> (in_vertex_position.x, in_vertex_position.y, in_vertex_position.z, 1.0);
+
+    result.out_vertex_pos = projection_view_matrix * world_position;
+    result.out_vertex_color = in_vertex_color;
+    result.out_vertex_world_position = world_position.xyz;
+    result.out_vertex_normal = (model.normal_matrix * vec4<f32> (in_vertex_normal, 0.0)).xyz;
 
     return result;
 }
@@ -28,11 +77,23 @@ fn vs_main(
 fn fs_main(
 
     @builtin(position) in_frag_position: vec4<f32>,
+    @location(0) in_frag_color: vec4<f32>,
+    @location(1) in_frag_world_position: vec3<f32>,
+    @location(2) in_frag_normal: vec3<f32>,
 
 ) -> FragmentOutput {
     var result: FragmentOutput;
 
-    result.out_frag_color = vec4<f32> (1.0, 0.0, 0.0, 1.0);
+    var normal = vec3<f32> (0.0);
+
+    if (dot(in_frag_normal, in_frag_normal) > 0.0) {
+        normal = normalize(in_frag_normal);
+    }
+
+    let material = Material (in_frag_color.rgb, MESH_ROUGHNESS, vec3<f32> (0.0), MESH_METALLIC);
+    let view = normalize(camera_position - in_frag_world_position);
+
+    result.out_frag_color = vec4<f32> (shade_mesh(in_frag_world_position, normal, view, material), in_frag_color.a);
 
     return result;
 }
\ No newline at end of file
@@ -0,0 +1,126 @@
+// Keep in sync with scene/material.rs
+const MAX_MATERIALS: u32 = 16u;
+
+// Keep in sync with scene/light.rs
+const SHADOW_START: f32 = 0.02;
+
+// Keep in sync with scene/evaluate.rs
+const NORMAL_EPSILON: f32 = 0.001;
+
+struct Materials {
+    materials: array<Material, MAX_MATERIALS>,
+}
+
+@group(0)
+@binding(5)
+var<uniform> lights: Lights;
+
+@group(0)
+@binding(6)
+var<uniform> materials: Materials;
+
+// Material of a surface sample, blending the two materials of smooth operations
+fn scene_material (sample: SceneSample) -> Material {
+    let a = materials.materials[sample.material];
+    let b = materials.materials[sample.blend_material];
+
+    return Material (
+        mix(a.albedo, b.albedo, sample.blend),
+        mix(a.roughness, b.roughness, sample.blend),
+        mix(a.emissive, b.emissive, sample.blend),
+        mix(a.metallic, b.metallic, sample.blend),
+    );
+}
+
+fn scene_normal (p: vec3<f32>) -> vec3<f32> {
+    let e = vec2<f32> (NORMAL_EPSILON, 0.0);
+
+    let gradient = vec3<f32> (
+        map(p + e.xyy).distance - map(p - e.xyy).distance,
+        map(p + e.yxy).distance - map(p - e.yxy).distance,
+        map(p + e.yyx).distance - map(p - e.yyx).distance,
+    );
+
+    if (dot(gradient, gradient) == 0.0) {
+        return vec3<f32> (0.0);
+    }
+
+    return normalize(gradient);
+}
+
+// Fraction of light reaching origin from direction, the closest miss of the shadow ray relative to the
+// travelled distance widens the penumbra
+fn soft_shadow (origin: vec3<f32>, direction: vec3<f32>, max_distance: f32) -> f32 {
+    var result = 1.0;
+    var t = SHADOW_START;
+
+    for (var i: u32 = 0u; i < lights.shadow_steps; i = i + 1u) {
+        if (t >= max_distance) {
+            break;
+        }
+
+        let h = map(origin + direction * t).distance;
+
+        result = min(result, lights.shadow_hardness * h / t);
+
+        if (result < 0.001) {
+            break;
+        }
+
+        t = t + clamp(h, 0.01, 0.5);
+    }
+
+    return 1.0 - lights.shadow_strength * (1.0 - clamp(result, 0.0, 1.0));
+}
+
+// Fraction of ambient light reaching p, from how much closer the surface gets than the distance travelled
+// along the normal
+fn ambient_occlusion (p: vec3<f32>, normal: vec3<f32>) -> f32 {
+    var occlusion = 0.0;
+    var weight = 1.0;
+
+    for (var i: u32 = 0u; i < lights.occlusion_samples; i = i + 1u) {
+        let h = lights.occlusion_step * f32(i + 1u);
+        let d = map(p + normal * h).distance;
+
+        occlusion = occlusion + (h - d) * weight;
+        weight = weight * 0.75;
+    }
+
+    return clamp(1.0 - lights.occlusion_strength * occlusion, 0.0, 1.0);
+}
+
+// Blinn-Phong shading of the surface point p seen along view, the direction from the surface to the eye,
+// with the shadows and occlusion enabled in the lights
+fn shade (p: vec3<f32>, normal: vec3<f32>, view: vec3<f32>, material: Material) -> vec3<f32> {
+    var occlusion = 1.0;
+
+    if (lights.occlusion_enabled != 0u) {
+        occlusion = ambient_occlusion(p, normal);
+    }
+
+    var color = lights.ambient.xyz * material.albedo * occlusion;
+
+    for (var i: u32 = 0u; i < lights.light_count; i = i + 1u) {
+        let light = lights.lights[i];
+        let incident = light_incident(light, p);
+
+        var radiance = incident.radiance;
+
+        if (dot(normal, incident.to_light) <= 0.0) {
+            continue;
+        }
+
+        if (lights.shadows_enabled != 0u && light.shadows != 0u) {
+            radiance = radiance * soft_shadow(p, incident.to_light, incident.distance);
+        }
+
+        if (light.occlusion != 0u) {
+            radiance = radiance * occlusion;
+        }
+
+        color = color + blinn_phong(normal, incident.to_light, view, material) * radiance;
+    }
+
+    return color + material.emissive;
+}
\ No newline at end of file
@@ -0,0 +1,88 @@
+// Light definitions shared by the ray marcher and the rasterizer, keep in sync with scene/light.rs
+const MAX_LIGHTS: u32 = 8u;
+
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+
+// Reflectance of dielectrics at normal incidence
+const DIELECTRIC_SPECULAR: f32 = 0.04;
+const MIN_ROUGHNESS: f32 = 0.05;
+
+const SHADOW_MAX_DISTANCE: f32 = 20.0;
+
+struct Light {
+    kind: u32,
+    intensity: f32,
+    shadows: u32,
+    occlusion: u32,
+    vector: vec4<f32>,
+    color: vec4<f32>,
+}
+
+struct Lights {
+    light_count: u32,
+    ambient: vec4<f32>,
+    shadows_enabled: u32,
+    shadow_steps: u32,
+    shadow_hardness: f32,
+    shadow_strength: f32,
+    occlusion_enabled: u32,
+    occlusion_samples: u32,
+    occlusion_step: f32,
+    occlusion_strength: f32,
+    lights: array<Light, MAX_LIGHTS>,
+}
+
+struct Material {
+    albedo: vec3<f32>,
+    roughness: f32,
+    emissive: vec3<f32>,
+    metallic: f32,
+}
+
+struct Incident {
+    to_light: vec3<f32>,
+    radiance: vec3<f32>,
+    // How far a shadow ray travels before reaching the light
+    distance: f32,
+}
+
+// Direction from p towards the light and the radiance reaching p
+fn light_incident (light: Light, p: vec3<f32>) -> Incident {
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        return Incident (-light.vector.xyz, light.color.xyz * light.intensity, SHADOW_MAX_DISTANCE);
+    }
+
+    let offset = light.vector.xyz - p;
+
+    // At the light itself there is no direction, like normalize_or_zero on the CPU
+    var to_light = vec3<f32> (0.0);
+
+    if (dot(offset, offset) > 0.0) {
+        to_light = normalize(offset);
+    }
+
+    return Incident (to_light, light.color.xyz * light.intensity / max(dot(offset, offset), 1e-4), length(offset));
+}
+
+// Diffuse and specular reflection of a light coming from to_light, seen along view. Roughness maps to the
+// Blinn-Phong exponent, metals tint their highlights with the albedo
+fn blinn_phong (normal: vec3<f32>, to_light: vec3<f32>, view: vec3<f32>, material: Material) -> vec3<f32> {
+    let roughness = max(material.roughness, MIN_ROUGHNESS);
+    let shininess = max(2.0 / pow(roughness, 4.0) - 2.0, 1.0);
+    let normalization = (shininess + 8.0) / 8.0;
+
+    let diffuse_color = material.albedo * (1.0 - material.metallic);
+    let specular_color = mix(vec3<f32> (DIELECTRIC_SPECULAR), material.albedo, material.metallic);
+
+    let diffuse = max(dot(normal, to_light), 0.0);
+
+    let half_vector = to_light + view;
+    var specular = 0.0;
+
+    if (dot(half_vector, half_vector) > 0.0) {
+        specular = pow(max(dot(normal, normalize(half_vector)), 0.0), shininess) * normalization;
+    }
+
+    return diffuse_color * diffuse + specular_color * specular;
+}
\ No newline at end of file
@@ -3,7 +3,8 @@ struct VertexOutput {
 }
 
 struct FragmentOutput {
-    @location(0) out_frag_color: vec4<f32>
+    @location(0) out_frag_color: vec4<f32>,
+    @builtin(frag_depth) out_frag_depth: f32
 }
 
 @group(0)
@@ -22,6 +23,16 @@ var<uniform> inverted_view_matrix: mat4x4<f32>;
 @binding(3)
 var<uniform> surface_configuration: vec2<f32>;
 
+// Same matrix as the rasterizer, so hits land in the depth buffer where meshes at their distance would
+@group(0)
+@binding(7)
+var<uniform> projection_view_matrix: mat4x4<f32>;
+
+// Replaced by sdf.wgsl, the map function generated from the scene (see scene/codegen.rs), lights.wgsl and lighting.wgsl
+// @scene
+
+const BACKGROUND: vec3<f32> = vec3<f32> (0.55, 0.7, 0.9);
+
 @vertex
 fn vs_main(
 
@@ -35,10 +46,6 @@ fn vs_main(
     return result;
 }
 
-fn map (p: vec3<f32>) -> f32 {
-    return length(p) - 1.0;
-}
-
 @fragment
 fn fs_main(
 
@@ -65,7 +72,7 @@ fn fs_main(
 
     for (var i: i32 = 0; i < 80; i = i + 1) {
         let p: vec3<f32> = camera_position + ray_world * t;
-        let d: f32 = map(p);
+        let d: f32 = map(p).distance;
 
         t = t + d;
 
@@ -74,9 +81,22 @@ fn fs_main(
         }
     }
 
-    let col = vec3<f32> (t * 0.1);
+    var col = BACKGROUND;
+
+    // Misses stay on the far plane the depth buffer is cleared to
+    var depth = 1.0;
+
+    if (t < 100.0) {
+        let p = camera_position + ray_world * t;
+
+        col = shade(p, scene_normal(p), -ray_world, scene_material(map(p)));
+
+        let clip = projection_view_matrix * vec4<f32> (p, 1.0);
+        depth = clamp(clip.z / clip.w, 0.0, 1.0);
+    }
 
     result.out_frag_color = vec4<f32> (col, 1.0);
+    result.out_frag_depth = depth;
 
     return result;
 }
\ No newline at end of file
@@ -0,0 +1,122 @@
+// Keep in sync with scene.rs
+const MAX_INSTRUCTIONS: u32 = 64u;
+
+struct SceneInstruction {
+    opcode: u32,
+    scale: f32,
+    material: u32,
+    padding: u32,
+    parameters: vec4<f32>,
+    inverse_transform: mat4x4<f32>,
+}
+
+struct Scene {
+    instruction_count: u32,
+    instructions: array<SceneInstruction, MAX_INSTRUCTIONS>,
+}
+
+@group(0)
+@binding(4)
+var<uniform> scene: Scene;
+
+// Distance with the material of the closest surface, smooth operations blend two materials and blend is
+// the weight of blend_material. Mirrors SceneSample in scene/evaluate.rs
+struct SceneSample {
+    distance: f32,
+    material: u32,
+    blend_material: u32,
+    blend: f32,
+}
+
+fn scene_sample (distance: f32, material: u32) -> SceneSample {
+    return SceneSample (distance, material, material, 0.0);
+}
+
+// Nested blends only keep the material weighing the most
+fn dominant_material (sample: SceneSample) -> u32 {
+    return select(sample.blend_material, sample.material, sample.blend < 0.5);
+}
+
+fn scene_local_point (index: u32, p: vec3<f32>) -> vec3<f32> {
+    return (scene.instructions[index].inverse_transform * vec4<f32> (p, 1.0)).xyz;
+}
+
+fn sd_sphere (p: vec3<f32>, parameters: vec4<f32>) -> f32 {
+    return length(p) - parameters.x;
+}
+
+fn sd_box (p: vec3<f32>, parameters: vec4<f32>) -> f32 {
+    let q = abs(p) - parameters.xyz;
+
+    return length(max(q, vec3<f32> (0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+}
+
+fn sd_rounded_box (p: vec3<f32>, parameters: vec4<f32>) -> f32 {
+    return sd_box(p, vec4<f32> (parameters.xyz - vec3<f32> (parameters.w), 0.0)) - parameters.w;
+}
+
+fn sd_torus (p: vec3<f32>, parameters: vec4<f32>) -> f32 {
+    let q = vec2<f32> (length(p.xy) - parameters.x, p.z);
+
+    return length(q) - parameters.y;
+}
+
+fn sd_capsule (p: vec3<f32>, parameters: vec4<f32>) -> f32 {
+    let q = vec3<f32> (p.xy, p.z - clamp(p.z, -parameters.x, parameters.x));
+
+    return length(q) - parameters.y;
+}
+
+fn sd_cylinder (p: vec3<f32>, parameters: vec4<f32>) -> f32 {
+    let d = abs(vec2<f32> (length(p.xy), p.z)) - vec2<f32> (parameters.y, parameters.x);
+
+    return min(max(d.x, d.y), 0.0) + length(max(d, vec2<f32> (0.0)));
+}
+
+fn sd_plane (p: vec3<f32>, parameters: vec4<f32>) -> f32 {
+    return dot(p, parameters.xyz) - parameters.w;
+}
+
+fn op_union (a: SceneSample, b: SceneSample, parameters: vec4<f32>) -> SceneSample {
+    if (a.distance < b.distance) {
+        return a;
+    }
+
+    return b;
+}
+
+fn op_subtraction (a: SceneSample, b: SceneSample, parameters: vec4<f32>) -> SceneSample {
+    return SceneSample (max(a.distance, -b.distance), a.material, a.blend_material, a.blend);
+}
+
+fn op_intersection (a: SceneSample, b: SceneSample, parameters: vec4<f32>) -> SceneSample {
+    if (a.distance > b.distance) {
+        return a;
+    }
+
+    return b;
+}
+
+fn op_smooth_union (a: SceneSample, b: SceneSample, parameters: vec4<f32>) -> SceneSample {
+    let k = parameters.x;
+    let h = clamp(0.5 + 0.5 * (b.distance - a.distance) / k, 0.0, 1.0);
+    let distance = mix(b.distance, a.distance, h) - k * h * (1.0 - h);
+
+    return SceneSample (distance, dominant_material(a), dominant_material(b), 1.0 - h);
+}
+
+fn op_smooth_subtraction (a: SceneSample, b: SceneSample, parameters: vec4<f32>) -> SceneSample {
+    let k = parameters.x;
+    let h = clamp(0.5 - 0.5 * (a.distance + b.distance) / k, 0.0, 1.0);
+    let distance = mix(a.distance, -b.distance, h) + k * h * (1.0 - h);
+
+    return SceneSample (distance, a.material, a.blend_material, a.blend);
+}
+
+fn op_smooth_intersection (a: SceneSample, b: SceneSample, parameters: vec4<f32>) -> SceneSample {
+    let k = parameters.x;
+    let h = clamp(0.5 - 0.5 * (b.distance - a.distance) / k, 0.0, 1.0);
+    let distance = mix(b.distance, a.distance, h) + k * h * (1.0 - h);
+
+    return SceneSample (distance, dominant_material(a), dominant_material(b), 1.0 - h);
+}
\ No newline at end of file
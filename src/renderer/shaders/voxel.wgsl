@@ -0,0 +1,86 @@
+struct VertexOutput {
+    @builtin(position) out_vertex_pos: vec4<f32>
+}
+
+struct FragmentOutput {
+    @location(0) out_frag_color: vec4<f32>
+}
+
+struct Palette {
+    colors: array<vec4<f32>, 256>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> camera_position: vec3<f32>;
+
+@group(0)
+@binding(1)
+var<uniform> inverted_projection_matrix: mat4x4<f32>;
+
+@group(0)
+@binding(2)
+var<uniform> inverted_view_matrix: mat4x4<f32>;
+
+@group(0)
+@binding(3)
+var<uniform> surface_configuration: vec2<f32>;
+
+@group(0)
+@binding(6)
+var<uniform> palette: Palette;
+
+// Same sky as ray_marching.wgsl, lit by the direction of the default sun of scene.rs
+const BACKGROUND: vec3<f32> = vec3<f32> (0.55, 0.7, 0.9);
+const SUN_DIRECTION: vec3<f32> = vec3<f32> (-0.3244, 0.4867, -0.8111);
+const AMBIENT: f32 = 0.35;
+
+// Replaced by VoxelTraversal::source, the traversal of the volume representation
+// @traversal
+
+@vertex
+fn vs_main(
+
+    @location(0) in_vertex_position: vec2<f32>
+
+) -> VertexOutput {
+    var result: VertexOutput;
+
+    result.out_vertex_pos = vec4<f32> (in_vertex_position.x, in_vertex_position.y, 0.0, 1.0);
+
+    return result;
+}
+
+@fragment
+fn fs_main(
+
+    @builtin(position) in_frag_position: vec4<f32>,
+
+) -> FragmentOutput {
+    var result: FragmentOutput;
+
+    let x = 2.0 * in_frag_position.x / surface_configuration.x - 1.0;
+    let y = 1.0 - (2.0 * in_frag_position.y) / surface_configuration.y;
+
+    let ray_clip = vec4<f32> (x, y, -1.0, 1.0);
+
+    var ray_eye = inverted_projection_matrix * ray_clip;
+    ray_eye = vec4<f32> (ray_eye.xy, -1.0, 0.0);
+
+    let ray_world = normalize((inverted_view_matrix * ray_eye).xyz);
+
+    let hit = trace_volume(camera_position, ray_world);
+
+    var col = BACKGROUND;
+
+    if (hit.hit) {
+        let albedo = palette.colors[hit.value].xyz;
+        let diffuse = max(dot(hit.normal, -SUN_DIRECTION), 0.0);
+
+        col = albedo * (AMBIENT + (1.0 - AMBIENT) * diffuse);
+    }
+
+    result.out_frag_color = vec4<f32> (col, 1.0);
+
+    return result;
+}
\ No newline at end of file
@@ -0,0 +1,147 @@
+// Keep in sync with brickmap.rs
+struct Brickmap {
+    origin: vec3<f32>,
+    voxel_size: f32,
+    size: vec3<u32>,
+    padding: u32,
+}
+
+const UNIFORM: u32 = 0x80000000u;
+const BRICK_SIZE: i32 = 8;
+const POOL_WIDTH: u32 = 64u;
+
+@group(0)
+@binding(4)
+var<uniform> brickmap: Brickmap;
+
+@group(0)
+@binding(5)
+var grid: texture_3d<u32>;
+
+@group(0)
+@binding(7)
+var pool: texture_3d<u32>;
+
+// Minimum corner of a pool slot in the atlas, in voxels
+fn slot_origin (slot: u32) -> vec3<i32> {
+    return vec3<i32> (vec3<u32> (slot % POOL_WIDTH, (slot / POOL_WIDTH) % POOL_WIDTH, slot / (POOL_WIDTH * POOL_WIDTH))) * BRICK_SIZE;
+}
+
+// Walks the voxels along the ray, leaping over empty bricks, mirrors Brickmap::raycast
+fn trace_volume (origin: vec3<f32>, direction: vec3<f32>) -> VoxelHit {
+    var result: VoxelHit;
+    result.hit = false;
+
+    // Grid space, where voxels are unit cubes starting at zero
+    let o = (origin - brickmap.origin) / brickmap.voxel_size;
+    let d = select(direction, vec3<f32> (1e-8), abs(direction) < vec3<f32> (1e-8));
+    let size = vec3<i32> (brickmap.size);
+
+    let t1 = -o / d;
+    let t2 = (vec3<f32> (size) - o) / d;
+    let t_near = min(t1, t2);
+    let t_far = max(t1, t2);
+
+    let t_enter = max(t_near.x, max(t_near.y, t_near.z));
+    let t_exit = min(t_far.x, min(t_far.y, t_far.z));
+
+    if (t_enter > t_exit || t_exit < 0.0) {
+        return result;
+    }
+
+    let step = sign(d);
+    let t_delta = abs(1.0 / d);
+
+    var t = max(t_enter, 0.0);
+    var voxel = clamp(vec3<i32> (floor(o + d * t)), vec3<i32> (0), size - 1);
+    var normal = entry_normal(t_near, step);
+
+    let max_steps = size.x + size.y + size.z;
+    var steps = 0;
+
+    while (steps < max_steps) {
+        let brick_min = voxel / BRICK_SIZE * BRICK_SIZE;
+        let cell = textureLoad(grid, brick_min / BRICK_SIZE, 0).r;
+
+        if ((cell & UNIFORM) != 0u) {
+            result.hit = true;
+            result.voxel = voxel;
+            result.value = cell & 0xffu;
+            result.normal = normal;
+            result.distance = t * brickmap.voxel_size;
+
+            return result;
+        }
+
+        if (cell == 0u) {
+            let boundary = vec3<f32> (brick_min) + select(vec3<f32> (0.0), vec3<f32> (f32(BRICK_SIZE)), step > vec3<f32> (0.0));
+            let t_max = (boundary - o) / d;
+
+            // The exit axis is stepped exactly, the others are clamped so rounding never leaves the brick sideways
+            var axis = 2;
+
+            if (t_max.x < t_max.y && t_max.x < t_max.z) {
+                axis = 0;
+            } else if (t_max.y < t_max.z) {
+                axis = 1;
+            }
+
+            t = t_max[axis];
+            voxel = clamp(vec3<i32> (floor(o + d * t)), brick_min, brick_min + BRICK_SIZE - 1);
+            voxel[axis] = select(brick_min[axis] - 1, brick_min[axis] + BRICK_SIZE, step[axis] > 0.0);
+
+            normal = vec3<f32> (0.0);
+            normal[axis] = -step[axis];
+
+            steps = steps + 1;
+        } else {
+            let pool_offset = slot_origin(cell) - brick_min;
+
+            let boundary = vec3<f32> (voxel) + max(step, vec3<f32> (0.0));
+            var t_max = (boundary - o) / d;
+
+            while (steps < max_steps) {
+                let value = textureLoad(pool, voxel + pool_offset, 0).r;
+
+                if (value != 0u) {
+                    result.hit = true;
+                    result.voxel = voxel;
+                    result.value = value;
+                    result.normal = normal;
+                    result.distance = t * brickmap.voxel_size;
+
+                    return result;
+                }
+
+                if (t_max.x < t_max.y && t_max.x < t_max.z) {
+                    t = t_max.x;
+                    t_max.x = t_max.x + t_delta.x;
+                    voxel.x = voxel.x + i32(step.x);
+                    normal = vec3<f32> (-step.x, 0.0, 0.0);
+                } else if (t_max.y < t_max.z) {
+                    t = t_max.y;
+                    t_max.y = t_max.y + t_delta.y;
+                    voxel.y = voxel.y + i32(step.y);
+                    normal = vec3<f32> (0.0, -step.y, 0.0);
+                } else {
+                    t = t_max.z;
+                    t_max.z = t_max.z + t_delta.z;
+                    voxel.z = voxel.z + i32(step.z);
+                    normal = vec3<f32> (0.0, 0.0, -step.z);
+                }
+
+                steps = steps + 1;
+
+                if (any(voxel < brick_min) || any(voxel >= brick_min + BRICK_SIZE)) {
+                    break;
+                }
+            }
+        }
+
+        if (any(voxel < vec3<i32> (0)) || any(voxel >= size)) {
+            return result;
+        }
+    }
+
+    return result;
+}
\ No newline at end of file
@@ -0,0 +1,87 @@
+// Keep in sync with voxel.rs
+struct Volume {
+    origin: vec3<f32>,
+    voxel_size: f32,
+    size: vec3<u32>,
+    padding: u32,
+}
+
+@group(0)
+@binding(4)
+var<uniform> volume: Volume;
+
+@group(0)
+@binding(5)
+var voxels: texture_3d<u32>;
+
+// Walks the voxels along the ray with the Amanatides–Woo DDA, mirrors VoxelVolume::raycast
+fn trace_volume (origin: vec3<f32>, direction: vec3<f32>) -> VoxelHit {
+    var result: VoxelHit;
+    result.hit = false;
+
+    // Grid space, where voxels are unit cubes starting at zero
+    let o = (origin - volume.origin) / volume.voxel_size;
+    let d = select(direction, vec3<f32> (1e-8), abs(direction) < vec3<f32> (1e-8));
+    let size = vec3<f32> (volume.size);
+
+    let t1 = -o / d;
+    let t2 = (size - o) / d;
+    let t_near = min(t1, t2);
+    let t_far = max(t1, t2);
+
+    let t_enter = max(t_near.x, max(t_near.y, t_near.z));
+    let t_exit = min(t_far.x, min(t_far.y, t_far.z));
+
+    if (t_enter > t_exit || t_exit < 0.0) {
+        return result;
+    }
+
+    let step = sign(d);
+    let t_delta = abs(1.0 / d);
+
+    var t = max(t_enter, 0.0);
+    var voxel = clamp(vec3<i32> (floor(o + d * t)), vec3<i32> (0), vec3<i32> (volume.size) - 1);
+    var normal = entry_normal(t_near, step);
+
+    let boundary = vec3<f32> (voxel) + max(step, vec3<f32> (0.0));
+    var t_max = (boundary - o) / d;
+
+    let max_steps = volume.size.x + volume.size.y + volume.size.z;
+
+    for (var i: u32 = 0u; i < max_steps; i = i + 1u) {
+        let value = textureLoad(voxels, voxel, 0).r;
+
+        if (value != 0u) {
+            result.hit = true;
+            result.voxel = voxel;
+            result.value = value;
+            result.normal = normal;
+            result.distance = t * volume.voxel_size;
+
+            return result;
+        }
+
+        if (t_max.x < t_max.y && t_max.x < t_max.z) {
+            t = t_max.x;
+            t_max.x = t_max.x + t_delta.x;
+            voxel.x = voxel.x + i32(step.x);
+            normal = vec3<f32> (-step.x, 0.0, 0.0);
+        } else if (t_max.y < t_max.z) {
+            t = t_max.y;
+            t_max.y = t_max.y + t_delta.y;
+            voxel.y = voxel.y + i32(step.y);
+            normal = vec3<f32> (0.0, -step.y, 0.0);
+        } else {
+            t = t_max.z;
+            t_max.z = t_max.z + t_delta.z;
+            voxel.z = voxel.z + i32(step.z);
+            normal = vec3<f32> (0.0, 0.0, -step.z);
+        }
+
+        if (any(voxel < vec3<i32> (0)) || any(voxel >= vec3<i32> (volume.size))) {
+            return result;
+        }
+    }
+
+    return result;
+}
\ No newline at end of file
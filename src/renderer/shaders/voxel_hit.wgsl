@@ -0,0 +1,21 @@
+// First voxel hit along a ray, mirrors VoxelHit in voxel.rs
+struct VoxelHit {
+    hit: bool,
+    voxel: vec3<i32>,
+    value: u32,
+    normal: vec3<f32>,
+    distance: f32,
+}
+
+// Normal of the bounding box face a ray enters through, given its slab entry distances
+fn entry_normal (t_near: vec3<f32>, step: vec3<f32>) -> vec3<f32> {
+    if (t_near.x > t_near.y && t_near.x > t_near.z) {
+        return vec3<f32> (-step.x, 0.0, 0.0);
+    }
+
+    if (t_near.y > t_near.z) {
+        return vec3<f32> (0.0, -step.y, 0.0);
+    }
+
+    return vec3<f32> (0.0, 0.0, -step.z);
+}
\ No newline at end of file
@@ -0,0 +1,111 @@
+// Keep in sync with octree.rs
+struct Octree {
+    origin: vec3<f32>,
+    voxel_size: f32,
+    depth: u32,
+    padding0: u32,
+    padding1: u32,
+    padding2: u32,
+}
+
+const LEAF: u32 = 0x80000000u;
+const NODE_TEXTURE_WIDTH: u32 = 2048u;
+
+@group(0)
+@binding(4)
+var<uniform> octree: Octree;
+
+@group(0)
+@binding(5)
+var nodes: texture_2d<u32>;
+
+fn octree_slot (index: u32) -> u32 {
+    return textureLoad(nodes, vec2<i32> (i32(index % NODE_TEXTURE_WIDTH), i32(index / NODE_TEXTURE_WIDTH)), 0).r;
+}
+
+// Leaps over empty regions of the sparse voxel octree, mirrors SparseVoxelOctree::raycast
+fn trace_volume (origin: vec3<f32>, direction: vec3<f32>) -> VoxelHit {
+    var result: VoxelHit;
+    result.hit = false;
+
+    // Grid space, where voxels are unit cubes starting at zero
+    let o = (origin - octree.origin) / octree.voxel_size;
+    let d = select(direction, vec3<f32> (1e-8), abs(direction) < vec3<f32> (1e-8));
+    let extent = i32(1u << octree.depth);
+
+    let t1 = -o / d;
+    let t2 = (vec3<f32> (f32(extent)) - o) / d;
+    let t_near = min(t1, t2);
+    let t_far = max(t1, t2);
+
+    let t_enter = max(t_near.x, max(t_near.y, t_near.z));
+    let t_exit = min(t_far.x, min(t_far.y, t_far.z));
+
+    if (t_enter > t_exit || t_exit < 0.0) {
+        return result;
+    }
+
+    let step = sign(d);
+
+    var t = max(t_enter, 0.0);
+    var voxel = clamp(vec3<i32> (floor(o + d * t)), vec3<i32> (0), vec3<i32> (extent - 1));
+    var normal = entry_normal(t_near, step);
+
+    for (var i: i32 = 0; i < 3 * extent; i = i + 1) {
+        // Descend to the largest empty or solid region around the voxel
+        var node = 0u;
+        var level = octree.depth;
+        var slot = 0u;
+
+        loop {
+            level = level - 1u;
+
+            let bits = (vec3<u32> (voxel) >> vec3<u32> (level)) & vec3<u32> (1u);
+            slot = octree_slot(node * 8u + bits.x + bits.y * 2u + bits.z * 4u);
+
+            if (slot == 0u || (slot & LEAF) != 0u || level == 0u) {
+                break;
+            }
+
+            node = slot;
+        }
+
+        if ((slot & LEAF) != 0u) {
+            result.hit = true;
+            result.voxel = voxel;
+            result.value = slot & 0xffu;
+            result.normal = normal;
+            result.distance = t * octree.voxel_size;
+
+            return result;
+        }
+
+        let cell_size = i32(1u << level);
+        let cell_min = (voxel >> vec3<u32> (level)) << vec3<u32> (level);
+
+        let boundary = vec3<f32> (cell_min) + select(vec3<f32> (0.0), vec3<f32> (f32(cell_size)), step > vec3<f32> (0.0));
+        let t_max = (boundary - o) / d;
+
+        // The exit axis is stepped exactly, the others are clamped so rounding never leaves the region sideways
+        var axis = 2;
+
+        if (t_max.x < t_max.y && t_max.x < t_max.z) {
+            axis = 0;
+        } else if (t_max.y < t_max.z) {
+            axis = 1;
+        }
+
+        t = t_max[axis];
+        voxel = clamp(vec3<i32> (floor(o + d * t)), cell_min, cell_min + cell_size - 1);
+        voxel[axis] = select(cell_min[axis] - 1, cell_min[axis] + cell_size, step[axis] > 0.0);
+
+        normal = vec3<f32> (0.0);
+        normal[axis] = -step[axis];
+
+        if (any(voxel < vec3<i32> (0)) || any(voxel >= vec3<i32> (extent))) {
+            return result;
+        }
+    }
+
+    return result;
+}
\ No newline at end of file
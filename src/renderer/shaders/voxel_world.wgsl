@@ -0,0 +1,135 @@
+// Keep in sync with world.rs
+struct World {
+    origin: vec3<f32>,
+    voxel_size: f32,
+    window_min: vec3<i32>,
+    padding: u32,
+}
+
+const CHUNK_SIZE: i32 = 32;
+const WINDOW_CHUNKS: i32 = 8;
+
+@group(0)
+@binding(4)
+var<uniform> world: World;
+
+@group(0)
+@binding(5)
+var chunks: texture_3d<u32>;
+
+@group(0)
+@binding(7)
+var voxels: texture_3d<u32>;
+
+// Chunks live in a ring of WINDOW_CHUNKS³ slots, chunk c in slot c mod WINDOW_CHUNKS
+fn wrap (v: vec3<i32>, n: i32) -> vec3<i32> {
+    return ((v % n) + n) % n;
+}
+
+// Walks the voxels of the window around the camera, leaping over empty and not yet uploaded chunks
+fn trace_volume (origin: vec3<f32>, direction: vec3<f32>) -> VoxelHit {
+    var result: VoxelHit;
+    result.hit = false;
+
+    // Window space, where voxels are unit cubes starting at zero
+    let o = (origin - world.origin) / world.voxel_size;
+    let d = select(direction, vec3<f32> (1e-8), abs(direction) < vec3<f32> (1e-8));
+    let size = vec3<i32> (WINDOW_CHUNKS * CHUNK_SIZE);
+    let window_voxel = world.window_min * CHUNK_SIZE;
+
+    let t1 = -o / d;
+    let t2 = (vec3<f32> (size) - o) / d;
+    let t_near = min(t1, t2);
+    let t_far = max(t1, t2);
+
+    let t_enter = max(t_near.x, max(t_near.y, t_near.z));
+    let t_exit = min(t_far.x, min(t_far.y, t_far.z));
+
+    if (t_enter > t_exit || t_exit < 0.0) {
+        return result;
+    }
+
+    let step = sign(d);
+    let t_delta = abs(1.0 / d);
+
+    var t = max(t_enter, 0.0);
+    var voxel = clamp(vec3<i32> (floor(o + d * t)), vec3<i32> (0), size - 1);
+    var normal = entry_normal(t_near, step);
+
+    let max_steps = size.x + size.y + size.z;
+    var steps = 0;
+
+    while (steps < max_steps) {
+        let chunk_min = voxel / CHUNK_SIZE * CHUNK_SIZE;
+        let cell = textureLoad(chunks, wrap(world.window_min + chunk_min / CHUNK_SIZE, WINDOW_CHUNKS), 0).r;
+
+        if (cell == 0u) {
+            let boundary = vec3<f32> (chunk_min) + select(vec3<f32> (0.0), vec3<f32> (f32(CHUNK_SIZE)), step > vec3<f32> (0.0));
+            let t_max = (boundary - o) / d;
+
+            // The exit axis is stepped exactly, the others are clamped so rounding never leaves the chunk sideways
+            var axis = 2;
+
+            if (t_max.x < t_max.y && t_max.x < t_max.z) {
+                axis = 0;
+            } else if (t_max.y < t_max.z) {
+                axis = 1;
+            }
+
+            t = t_max[axis];
+            voxel = clamp(vec3<i32> (floor(o + d * t)), chunk_min, chunk_min + CHUNK_SIZE - 1);
+            voxel[axis] = select(chunk_min[axis] - 1, chunk_min[axis] + CHUNK_SIZE, step[axis] > 0.0);
+
+            normal = vec3<f32> (0.0);
+            normal[axis] = -step[axis];
+
+            steps = steps + 1;
+        } else {
+            let boundary = vec3<f32> (voxel) + max(step, vec3<f32> (0.0));
+            var t_max = (boundary - o) / d;
+
+            while (steps < max_steps) {
+                let value = textureLoad(voxels, wrap(window_voxel + voxel, WINDOW_CHUNKS * CHUNK_SIZE), 0).r;
+
+                if (value != 0u) {
+                    result.hit = true;
+                    result.voxel = voxel;
+                    result.value = value;
+                    result.normal = normal;
+                    result.distance = t * world.voxel_size;
+
+                    return result;
+                }
+
+                if (t_max.x < t_max.y && t_max.x < t_max.z) {
+                    t = t_max.x;
+                    t_max.x = t_max.x + t_delta.x;
+                    voxel.x = voxel.x + i32(step.x);
+                    normal = vec3<f32> (-step.x, 0.0, 0.0);
+                } else if (t_max.y < t_max.z) {
+                    t = t_max.y;
+                    t_max.y = t_max.y + t_delta.y;
+                    voxel.y = voxel.y + i32(step.y);
+                    normal = vec3<f32> (0.0, -step.y, 0.0);
+                } else {
+                    t = t_max.z;
+                    t_max.z = t_max.z + t_delta.z;
+                    voxel.z = voxel.z + i32(step.z);
+                    normal = vec3<f32> (0.0, 0.0, -step.z);
+                }
+
+                steps = steps + 1;
+
+                if (any(voxel < chunk_min) || any(voxel >= chunk_min + CHUNK_SIZE)) {
+                    break;
+                }
+            }
+        }
+
+        if (any(voxel < vec3<i32> (0)) || any(voxel >= size)) {
+            return result;
+        }
+    }
+
+    return result;
+}
\ No newline at end of file
@@ -0,0 +1,268 @@
+use wgpu::util::DeviceExt;
+
+use glam::{
+    Mat4,
+    Vec3,
+    Vec4
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    WGPUBackend,
+    logic::{
+        camera::Camera,
+        play::Play
+    },
+    renderer::{
+        pipeline,
+        pipeline::SimpleVertex,
+        registry::Pipeline
+    },
+    scene::{
+        evaluate::SceneEvaluator,
+        light,
+        material::Material,
+        Scene,
+        SceneError
+    }
+};
+
+// Step limits shared with ray_marching.wgsl
+pub const MAX_STEPS: i32 = 80;
+pub const HIT_DISTANCE: f32 = 0.001;
+pub const MAX_DISTANCE: f32 = 100.0;
+
+pub const BACKGROUND: Vec3 = Vec3::new(0.55, 0.7, 0.9);
+
+/// Marches a single ray and returns the travelled distance, exactly like `fs_main` does.
+pub fn march(scene: &SceneEvaluator, origin: Vec3, direction: Vec3) -> f32 {
+    let mut t = 0f32;
+
+    for _ in 0..MAX_STEPS {
+        let p = origin + direction * t;
+        let d = scene.distance(p);
+
+        t += d;
+
+        if d < HIT_DISTANCE || t > MAX_DISTANCE {
+            break;
+        }
+    }
+
+    return t;
+}
+
+/// Color seen along a ray, the background when it escapes the scene.
+pub fn trace(scene: &Scene, evaluator: &SceneEvaluator, origin: Vec3, direction: Vec3) -> Vec3 {
+    let t = march(evaluator, origin, direction);
+
+    if t >= MAX_DISTANCE {
+        return BACKGROUND;
+    }
+
+    let p = origin + direction * t;
+
+    let material = Material::resolve(&scene.materials, &evaluator.sample(p));
+
+    return light::shade(scene, evaluator, p, evaluator.normal(p), -direction, &material);
+}
+
+/// Computes the world space direction of the ray going through the pixel center at (`x`, `y`).
+pub fn ray_direction(inverted_projection: &Mat4, inverted_view: &Mat4, x: f32, y: f32, width: f32, height: f32) -> Vec3 {
+    let ndc_x = 2.0 * x / width - 1.0;
+    let ndc_y = 1.0 - (2.0 * y) / height;
+
+    let ray_clip = Vec4::new(ndc_x, ndc_y, -1.0, 1.0);
+
+    let ray_eye = *inverted_projection * ray_clip;
+    let ray_eye = Vec4::new(ray_eye.x, ray_eye.y, -1.0, 0.0);
+
+    let ray_world = *inverted_view * ray_eye;
+
+    return ray_world.truncate().normalize();
+}
+
+fn to_unorm(value: f32) -> u8 {
+    return (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+}
+
+/// Ray marches a full frame on the CPU into `pixels`, as tightly packed RGBA8 rows. Scanlines are
+/// distributed over the rayon thread pool.
+pub fn march_frame(camera: &Camera, scene: &Scene, evaluator: &SceneEvaluator, width: u32, height: u32, pixels: &mut [u8]) {
+    let inverted_projection = camera.get_inverted_projection_matrix(width as f32 / height as f32);
+    let inverted_view = camera.get_inverted_view_matrix();
+
+    pixels.par_chunks_exact_mut(width as usize * 4).enumerate().for_each(|(y, row)| {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            // Fragment positions are sampled at pixel centers
+            let direction = ray_direction(&inverted_projection, &inverted_view, x as f32 + 0.5, y as f32 + 0.5, width as f32, height as f32);
+            let color = trace(scene, evaluator, camera.position, direction);
+
+            pixel.copy_from_slice(&[to_unorm(color.x), to_unorm(color.y), to_unorm(color.z), 255]);
+        }
+    });
+}
+
+pub struct SoftwareRayMarcher {
+    pipeline: pipeline::BlitPipeline,
+
+    scene: Scene,
+    evaluator: SceneEvaluator,
+    scene_error: Option<SceneError>,
+
+    pixels: Vec<u8>,
+    texture: wgpu::Texture,
+
+    bind_group: wgpu::BindGroup,
+
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+}
+
+impl Pipeline for SoftwareRayMarcher {
+    fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self {
+        let pipeline = pipeline::BlitPipeline::new(wgpu_backend);
+
+        let (texture, bind_group) = Self::create_frame_texture(wgpu_backend, &pipeline);
+
+        let evaluator = SceneEvaluator::new(&play.scene);
+        let scene_error = evaluator.as_ref().err().copied();
+
+        let evaluator = evaluator.unwrap_or_else(|_| SceneEvaluator::new(&Scene::new()).unwrap());
+
+        let mut pixels = vec![0u8; wgpu_backend.config.width as usize * wgpu_backend.config.height as usize * 4];
+        march_frame(&play.camera, &play.scene, &evaluator, wgpu_backend.config.width, wgpu_backend.config.height, &mut pixels);
+
+        let vertices = [
+            SimpleVertex { position: [-1.0, 1.0] },
+            SimpleVertex { position: [-1.0, -1.0] },
+            SimpleVertex { position: [1.0, -1.0] },
+            SimpleVertex { position: [1.0, 1.0] },
+        ];
+
+        let vertex_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let indices: [u16; 6] = [0, 1, 2, 2, 3, 0];
+
+        let index_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let software_ray_marcher = Self {
+            pipeline,
+
+            scene: play.scene.clone(),
+            evaluator,
+            scene_error,
+
+            pixels,
+            texture,
+
+            bind_group,
+
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+        };
+
+        software_ray_marcher.upload(wgpu_backend);
+
+        return software_ray_marcher;
+    }
+
+    fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        if play.scene != self.scene {
+            // An invalid scene keeps the previous one on screen, like on the GPU
+            match SceneEvaluator::new(&play.scene) {
+                Ok(evaluator) => {
+                    self.evaluator = evaluator;
+                    self.scene_error = None;
+                }
+                Err(error) => self.scene_error = Some(error),
+            }
+
+            self.scene = play.scene.clone();
+        }
+
+        march_frame(&play.camera, &self.scene, &self.evaluator, self.texture.width(), self.texture.height(), &mut self.pixels);
+
+        self.upload(wgpu_backend);
+    }
+
+    /// Only recreates the frame, which `update` marches once the pipeline is shown.
+    fn resize(&mut self, wgpu_backend: &WGPUBackend, _: &Play) {
+        let (texture, bind_group) = Self::create_frame_texture(wgpu_backend, &self.pipeline);
+
+        self.texture = texture;
+        self.bind_group = bind_group;
+        self.pixels = vec![0u8; wgpu_backend.config.width as usize * wgpu_backend.config.height as usize * 4];
+    }
+
+    fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        pass.set_pipeline(&self.pipeline.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+
+    fn scene_error(&self) -> Option<SceneError> {
+        return self.scene_error;
+    }
+}
+
+impl SoftwareRayMarcher {
+    fn create_frame_texture(wgpu_backend: &WGPUBackend, pipeline: &pipeline::BlitPipeline) -> (wgpu::Texture, wgpu::BindGroup) {
+        let texture = wgpu_backend.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Software ray marcher frame"),
+            size: wgpu::Extent3d {
+                width: wgpu_backend.config.width,
+                height: wgpu_backend.config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = wgpu_backend.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+        });
+
+        return (texture, bind_group);
+    }
+
+    fn upload(&self, wgpu_backend: &WGPUBackend) {
+        wgpu_backend.queue.write_texture(
+            self.texture.as_image_copy(),
+            &self.pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.texture.width() * 4),
+                rows_per_image: Some(self.texture.height()),
+            },
+            self.texture.size(),
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    WGPUBackend,
+    logic::play::Play,
+    renderer::{
+        pipeline,
+        pipeline::SimpleVertex,
+        registry::Pipeline
+    },
+    voxel::{
+        PaletteUniform,
+        VolumeUniform,
+        VoxelVolume
+    }
+};
+
+pub struct VoxelRayMarcher {
+    pipeline: pipeline::VoxelPipeline,
+
+    camera_position_buffer: wgpu::Buffer,
+    camera_inverted_projection_buffer: wgpu::Buffer,
+    camera_inverted_view_buffer: wgpu::Buffer,
+    surface_configuration_buffer: wgpu::Buffer,
+    volume_buffer: wgpu::Buffer,
+    palette_buffer: wgpu::Buffer,
+
+    volume_texture: wgpu::Texture,
+    uploaded_revision: u64,
+
+    bind_group: wgpu::BindGroup,
+
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+}
+
+impl Pipeline for VoxelRayMarcher {
+    fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self {
+        let pipeline = pipeline::VoxelPipeline::new(wgpu_backend, pipeline::VoxelTraversal::Dense);
+
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+        let camera_position_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_position_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_inverted_projection_data = play.camera.get_inverted_projection_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let camera_inverted_projection_ref: &[f32; 16] = camera_inverted_projection_data.as_ref();
+        let camera_inverted_projection_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_inverted_projection_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_inverted_view_data = play.camera.get_inverted_view_matrix();
+        let camera_inverted_view_ref: &[f32; 16] = camera_inverted_view_data.as_ref();
+        let camera_inverted_view_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_inverted_view_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let surface_configuration_data = [wgpu_backend.config.width as f32, wgpu_backend.config.height as f32];
+        let surface_configuration_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(surface_configuration_data.as_ref()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let volume_data = VolumeUniform::new(&play.volume);
+        let volume_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&volume_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let palette_data = PaletteUniform::new(&play.volume.palette);
+        let palette_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&palette_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let volume_texture = Self::create_volume_texture(wgpu_backend, &play.volume);
+
+        let bind_group = Self::create_bind_group(
+            wgpu_backend,
+            &pipeline,
+            [&camera_position_buffer, &camera_inverted_projection_buffer, &camera_inverted_view_buffer, &surface_configuration_buffer, &volume_buffer, &palette_buffer],
+            &volume_texture,
+        );
+
+        let vertices = [
+            SimpleVertex { position: [-1.0, 1.0] },
+            SimpleVertex { position: [-1.0, -1.0] },
+            SimpleVertex { position: [1.0, -1.0] },
+            SimpleVertex { position: [1.0, 1.0] },
+        ];
+
+        let vertex_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let indices: [u16; 6] = [0, 1, 2, 2, 3, 0];
+
+        let index_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        return Self {
+            pipeline,
+
+            camera_position_buffer,
+            camera_inverted_projection_buffer,
+            camera_inverted_view_buffer,
+            surface_configuration_buffer,
+            volume_buffer,
+            palette_buffer,
+
+            volume_texture,
+            uploaded_revision: play.volume.revision(),
+
+            bind_group,
+
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+        };
+    }
+
+    fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_position_buffer, 0, bytemuck::cast_slice(camera_position_ref));
+
+        let camera_inverted_view_data = play.camera.get_inverted_view_matrix();
+        let camera_inverted_view_ref: &[f32; 16] = camera_inverted_view_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_inverted_view_buffer, 0, bytemuck::cast_slice(camera_inverted_view_ref));
+
+        // Placement and palette are tiny, only the voxels are worth skipping
+        wgpu_backend.queue.write_buffer(&self.volume_buffer, 0, bytemuck::bytes_of(&VolumeUniform::new(&play.volume)));
+        wgpu_backend.queue.write_buffer(&self.palette_buffer, 0, bytemuck::bytes_of(&PaletteUniform::new(&play.volume.palette)));
+
+        if play.volume.revision() != self.uploaded_revision {
+            let size = play.volume.size();
+
+            if size.x != self.volume_texture.width() || size.y != self.volume_texture.height() || size.z != self.volume_texture.depth_or_array_layers() {
+                self.volume_texture = Self::create_volume_texture(wgpu_backend, &play.volume);
+                self.bind_group = Self::create_bind_group(
+                    wgpu_backend,
+                    &self.pipeline,
+                    [&self.camera_position_buffer, &self.camera_inverted_projection_buffer, &self.camera_inverted_view_buffer, &self.surface_configuration_buffer, &self.volume_buffer, &self.palette_buffer],
+                    &self.volume_texture,
+                );
+            } else {
+                wgpu_backend.queue.write_texture(
+                    self.volume_texture.as_image_copy(),
+                    play.volume.voxels(),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(size.x),
+                        rows_per_image: Some(size.y),
+                    },
+                    self.volume_texture.size(),
+                );
+            }
+
+            self.uploaded_revision = play.volume.revision();
+        }
+    }
+
+    fn resize(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let surface_configuration_data = [wgpu_backend.config.width as f32, wgpu_backend.config.height as f32];
+        wgpu_backend.queue.write_buffer(&self.surface_configuration_buffer, 0, bytemuck::cast_slice(surface_configuration_data.as_ref()));
+
+        let camera_inverted_projection_data = play.camera.get_inverted_projection_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let camera_inverted_projection_ref: &[f32; 16] = camera_inverted_projection_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_inverted_projection_buffer, 0, bytemuck::cast_slice(camera_inverted_projection_ref));
+    }
+
+    fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        pass.set_pipeline(&self.pipeline.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
+
+impl VoxelRayMarcher {
+    /// Uploads the palette indices as a 3D texture, read with `textureLoad` by the DDA.
+    fn create_volume_texture(wgpu_backend: &WGPUBackend, volume: &VoxelVolume) -> wgpu::Texture {
+        let size = volume.size();
+
+        return wgpu_backend.device.create_texture_with_data(
+            &wgpu_backend.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Voxel volume"),
+                size: wgpu::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: size.z,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::R8Uint,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            volume.voxels(),
+        );
+    }
+
+    fn create_bind_group(wgpu_backend: &WGPUBackend, pipeline: &pipeline::VoxelPipeline, buffers: [&wgpu::Buffer; 6], volume_texture: &wgpu::Texture) -> wgpu::BindGroup {
+        let [camera_position_buffer, camera_inverted_projection_buffer, camera_inverted_view_buffer, surface_configuration_buffer, volume_buffer, palette_buffer] = buffers;
+
+        let volume_view = volume_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        return wgpu_backend.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: camera_inverted_projection_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: camera_inverted_view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: surface_configuration_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: volume_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&volume_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: palette_buffer.as_entire_binding(),
+                },
+            ],
+        });
+    }
+}
\ No newline at end of file
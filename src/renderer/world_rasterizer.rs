@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use glam::{
+    IVec3,
+    Mat4,
+    UVec3
+};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    WGPUBackend,
+    logic::play::Play,
+    renderer::{
+        pipeline,
+        registry::Pipeline
+    },
+    scene::{
+        light::LightUniform,
+        Scene,
+        SceneError
+    },
+    voxel::{
+        mesher::mesh_chunk,
+        world::Residency
+    }
+};
+
+/// Neighbours whose faces and occlusion towards a chunk depend on its voxels, through the faces, edges
+/// and corners they share.
+const NEIGHBOURS: [IVec3; 26] = neighbours();
+
+const fn neighbours() -> [IVec3; 26] {
+    let mut offsets = [IVec3::ZERO; 26];
+    let mut count = 0;
+    let mut i = 0;
+
+    while i < 27 {
+        if i != 13 {
+            offsets[count] = IVec3::new(i % 3 - 1, i / 3 % 3 - 1, i / 9 - 1);
+            count += 1;
+        }
+
+        i += 1;
+    }
+
+    return offsets;
+}
+
+struct ChunkBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+}
+
+struct MeshedChunk {
+    coord: IVec3,
+    /// Unless the chunk has no visible face.
+    buffers: Option<ChunkBuffers>,
+    /// Bit `i` is set when `NEIGHBOURS[i]` was loaded as the chunk was meshed, the borders towards the
+    /// others were built as if they were empty.
+    loaded_neighbours: u32,
+}
+
+/// Renders the chunked world of `Play` as greedy meshes through `ColorPipeline`, the rasterized
+/// counterpart of `WorldRayMarcher`, lit by the lights of the scene like the other rasterized meshes.
+/// Chunks entering the window around the camera are meshed a few per frame, into the same ring of
+/// slots the ray marcher uploads them to.
+pub struct WorldRasterizer {
+    pipeline: pipeline::ColorPipeline,
+
+    projection_view_buffer: wgpu::Buffer,
+    camera_position_buffer: wgpu::Buffer,
+    lights_buffer: wgpu::Buffer,
+
+    uploaded_scene: Scene,
+    scene_error: Option<SceneError>,
+
+    bind_group: wgpu::BindGroup,
+    /// Chunk meshes are built in world space.
+    model_bind_group: wgpu::BindGroup,
+
+    residency: Residency,
+    /// Chunk in each slot.
+    meshes: HashMap<UVec3, MeshedChunk>,
+}
+
+impl Pipeline for WorldRasterizer {
+    fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self {
+        let pipeline = pipeline::ColorPipeline::new(wgpu_backend);
+
+        let projection_view_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let projection_view_ref: &[f32; 16] = projection_view_data.as_ref();
+        let projection_view_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(projection_view_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_position_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(play.camera.position.as_ref()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let lights_data = play.scene.light_uniform();
+        let scene_error = lights_data.err();
+
+        let lights_data = lights_data.unwrap_or_else(|_| LightUniform::new(play.scene.ambient, &play.scene.shadows, &play.scene.occlusion, &[]));
+        let lights_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&lights_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = wgpu_backend.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: projection_view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: camera_position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: lights_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let (_, model_bind_group) = pipeline.create_model(wgpu_backend, Mat4::IDENTITY);
+
+        return Self {
+            pipeline,
+
+            projection_view_buffer,
+            camera_position_buffer,
+            lights_buffer,
+
+            uploaded_scene: play.scene.clone(),
+            scene_error,
+
+            bind_group,
+            model_bind_group,
+
+            residency: Residency::new(),
+            meshes: HashMap::new(),
+        };
+    }
+
+    fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let projection_view_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let projection_view_ref: &[f32; 16] = projection_view_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.projection_view_buffer, 0, bytemuck::cast_slice(projection_view_ref));
+
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_position_buffer, 0, bytemuck::cast_slice(camera_position_ref));
+
+        if play.scene != self.uploaded_scene {
+            // Invalid lights keep the previous ones
+            match play.scene.light_uniform() {
+                Ok(lights_data) => {
+                    wgpu_backend.queue.write_buffer(&self.lights_buffer, 0, bytemuck::bytes_of(&lights_data));
+                    self.scene_error = None;
+                }
+                Err(error) => self.scene_error = Some(error),
+            }
+
+            self.uploaded_scene = play.scene.clone();
+        }
+
+        for slot in self.residency.recenter(play.world.chunk_coord(play.camera.position)) {
+            self.meshes.remove(&slot);
+        }
+
+        self.residency.refresh(&play.world);
+
+        for coord in self.residency.next_uploads(&play.world) {
+            let edited = self.meshed(coord).is_some();
+
+            self.build_mesh(wgpu_backend, play, coord);
+
+            // The borders of the meshed neighbours were built against the voxels of the chunk before
+            // an edit, or without them when it was not loaded yet, both can uncover or hide faces
+            for offset in NEIGHBOURS {
+                let neighbour = coord + offset;
+                let seen = 1 << NEIGHBOURS.iter().position(|other| *other == -offset).expect("Neighbours are symmetric");
+
+                if self.meshed(neighbour).is_some_and(|chunk| edited || chunk.loaded_neighbours & seen == 0) {
+                    self.build_mesh(wgpu_backend, play, neighbour);
+                }
+            }
+        }
+    }
+
+    fn resize(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let projection_view_data = play.camera.build_projection_view_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let projection_view_ref: &[f32; 16] = projection_view_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.projection_view_buffer, 0, bytemuck::cast_slice(projection_view_ref));
+    }
+
+    fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        pass.set_pipeline(&self.pipeline.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_bind_group(1, &self.model_bind_group, &[]);
+
+        for buffers in self.meshes.values().filter_map(|chunk| chunk.buffers.as_ref()) {
+            pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+            pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            pass.draw_indexed(0..buffers.num_indices, 0, 0..1);
+        }
+    }
+
+    /// Chunks still waiting to be meshed.
+    fn pending(&self) -> usize {
+        return self.residency.pending();
+    }
+
+    fn triangle_count(&self) -> Option<u32> {
+        return Some(self.meshes.values().filter_map(|chunk| chunk.buffers.as_ref()).map(|buffers| buffers.num_indices / 3).sum());
+    }
+
+    /// Without a sky pass the meshed world clears to the sky of voxel.wgsl.
+    fn clear_color(&self) -> wgpu::Color {
+        return wgpu::Color {
+            r: 0.55,
+            g: 0.7,
+            b: 0.9,
+            a: 1.0,
+        };
+    }
+
+    fn draws_world(&self) -> bool {
+        return true;
+    }
+
+    fn scene_error(&self) -> Option<SceneError> {
+        return self.scene_error;
+    }
+}
+
+impl WorldRasterizer {
+    fn build_mesh(&mut self, wgpu_backend: &WGPUBackend, play: &Play, coord: IVec3) {
+        let mesh = mesh_chunk(&play.world, coord);
+
+        let buffers = (!mesh.is_empty()).then(|| ChunkBuffers {
+            vertex_buffer: wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&mesh.vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+            index_buffer: wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&mesh.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }),
+            num_indices: mesh.indices.len() as u32,
+        });
+
+        let loaded_neighbours = NEIGHBOURS.iter().enumerate()
+            .filter(|(_, offset)| play.world.chunk(coord + **offset).is_some())
+            .fold(0, |mask, (index, _)| mask | 1 << index);
+
+        self.meshes.insert(Residency::slot(coord), MeshedChunk {
+            coord,
+            buffers,
+            loaded_neighbours,
+        });
+    }
+
+    /// The mesh of `coord`, unless its slot is empty or holds another chunk.
+    fn meshed(&self, coord: IVec3) -> Option<&MeshedChunk> {
+        return self.meshes.get(&Residency::slot(coord)).filter(|chunk| chunk.coord == coord);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,310 @@
+use glam::UVec3;
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    WGPUBackend,
+    logic::play::Play,
+    renderer::{
+        pipeline,
+        pipeline::SimpleVertex,
+        registry::Pipeline
+    },
+    voxel::{
+        world::{
+            Residency,
+            WorldUniform,
+            CHUNK_SIZE,
+            WINDOW_CHUNKS
+        },
+        PaletteUniform
+    }
+};
+
+/// Renders the chunked world of `Play`. The chunks of the window around the camera live in a ring of
+/// GPU slots, and chunks entering the window are uploaded a few per frame.
+pub struct WorldRayMarcher {
+    pipeline: pipeline::VoxelPipeline,
+
+    camera_position_buffer: wgpu::Buffer,
+    camera_inverted_projection_buffer: wgpu::Buffer,
+    camera_inverted_view_buffer: wgpu::Buffer,
+    surface_configuration_buffer: wgpu::Buffer,
+    world_buffer: wgpu::Buffer,
+    palette_buffer: wgpu::Buffer,
+
+    residency: Residency,
+    chunks_texture: wgpu::Texture,
+    voxels_texture: wgpu::Texture,
+
+    bind_group: wgpu::BindGroup,
+
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+}
+
+impl Pipeline for WorldRayMarcher {
+    fn new(wgpu_backend: &WGPUBackend, play: &Play) -> Self {
+        let pipeline = pipeline::VoxelPipeline::new(wgpu_backend, pipeline::VoxelTraversal::World);
+
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+        let camera_position_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_position_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_inverted_projection_data = play.camera.get_inverted_projection_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let camera_inverted_projection_ref: &[f32; 16] = camera_inverted_projection_data.as_ref();
+        let camera_inverted_projection_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_inverted_projection_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_inverted_view_data = play.camera.get_inverted_view_matrix();
+        let camera_inverted_view_ref: &[f32; 16] = camera_inverted_view_data.as_ref();
+        let camera_inverted_view_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(camera_inverted_view_ref),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let surface_configuration_data = [wgpu_backend.config.width as f32, wgpu_backend.config.height as f32];
+        let surface_configuration_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(surface_configuration_data.as_ref()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let world_data = WorldUniform::new(&play.world, play.world.chunk_coord(play.camera.position));
+        let world_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&world_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let palette_data = PaletteUniform::new(&play.world.palette);
+        let palette_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&palette_data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // Both start zeroed, with every slot empty until its chunk is uploaded
+        let chunks_texture = Self::create_texture(wgpu_backend, "World chunks", WINDOW_CHUNKS as u32);
+        let voxels_texture = Self::create_texture(wgpu_backend, "World voxels", WINDOW_CHUNKS as u32 * CHUNK_SIZE);
+
+        let chunks_view = chunks_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let voxels_view = voxels_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = wgpu_backend.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: camera_inverted_projection_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: camera_inverted_view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: surface_configuration_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: world_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&chunks_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: palette_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(&voxels_view),
+                },
+            ],
+        });
+
+        let vertices = [
+            SimpleVertex { position: [-1.0, 1.0] },
+            SimpleVertex { position: [-1.0, -1.0] },
+            SimpleVertex { position: [1.0, -1.0] },
+            SimpleVertex { position: [1.0, 1.0] },
+        ];
+
+        let vertex_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let indices: [u16; 6] = [0, 1, 2, 2, 3, 0];
+
+        let index_buffer = wgpu_backend.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        return Self {
+            pipeline,
+
+            camera_position_buffer,
+            camera_inverted_projection_buffer,
+            camera_inverted_view_buffer,
+            surface_configuration_buffer,
+            world_buffer,
+            palette_buffer,
+
+            residency: Residency::new(),
+            chunks_texture,
+            voxels_texture,
+
+            bind_group,
+
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+        };
+    }
+
+    fn update(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let camera_position_data = play.camera.position;
+        let camera_position_ref: &[f32; 3] = camera_position_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_position_buffer, 0, bytemuck::cast_slice(camera_position_ref));
+
+        let camera_inverted_view_data = play.camera.get_inverted_view_matrix();
+        let camera_inverted_view_ref: &[f32; 16] = camera_inverted_view_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_inverted_view_buffer, 0, bytemuck::cast_slice(camera_inverted_view_ref));
+
+        let center = play.world.chunk_coord(play.camera.position);
+
+        // Slots of chunks that left the window read as empty until their replacement arrives
+        for slot in self.residency.recenter(center) {
+            self.write_chunk_flag(wgpu_backend, slot, 0);
+        }
+
+        self.residency.refresh(&play.world);
+
+        for coord in self.residency.next_uploads(&play.world) {
+            let chunk = play.world.chunk(coord).expect("Uploaded chunks are loaded");
+            let slot = Residency::slot(coord);
+
+            if chunk.is_empty() {
+                self.write_chunk_flag(wgpu_backend, slot, 0);
+                continue;
+            }
+
+            wgpu_backend.queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.voxels_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: slot.x * CHUNK_SIZE, y: slot.y * CHUNK_SIZE, z: slot.z * CHUNK_SIZE },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                chunk.voxels(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(CHUNK_SIZE),
+                    rows_per_image: Some(CHUNK_SIZE),
+                },
+                wgpu::Extent3d {
+                    width: CHUNK_SIZE,
+                    height: CHUNK_SIZE,
+                    depth_or_array_layers: CHUNK_SIZE,
+                },
+            );
+
+            self.write_chunk_flag(wgpu_backend, slot, 1);
+        }
+
+        wgpu_backend.queue.write_buffer(&self.world_buffer, 0, bytemuck::bytes_of(&WorldUniform::new(&play.world, center)));
+        wgpu_backend.queue.write_buffer(&self.palette_buffer, 0, bytemuck::bytes_of(&PaletteUniform::new(&play.world.palette)));
+    }
+
+    fn resize(&mut self, wgpu_backend: &WGPUBackend, play: &Play) {
+        let surface_configuration_data = [wgpu_backend.config.width as f32, wgpu_backend.config.height as f32];
+        wgpu_backend.queue.write_buffer(&self.surface_configuration_buffer, 0, bytemuck::cast_slice(surface_configuration_data.as_ref()));
+
+        let camera_inverted_projection_data = play.camera.get_inverted_projection_matrix(wgpu_backend.config.width as f32 / wgpu_backend.config.height as f32);
+        let camera_inverted_projection_ref: &[f32; 16] = camera_inverted_projection_data.as_ref();
+
+        wgpu_backend.queue.write_buffer(&self.camera_inverted_projection_buffer, 0, bytemuck::cast_slice(camera_inverted_projection_ref));
+    }
+
+    fn render<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        pass.set_pipeline(&self.pipeline.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+
+    /// Chunks still waiting for an upload.
+    fn pending(&self) -> usize {
+        return self.residency.pending();
+    }
+
+    fn draws_world(&self) -> bool {
+        return true;
+    }
+}
+
+impl WorldRayMarcher {
+    /// Cube of R8Uint texels, `size` per side.
+    fn create_texture(wgpu_backend: &WGPUBackend, label: &str, size: u32) -> wgpu::Texture {
+        return wgpu_backend.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: size,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::R8Uint,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+    }
+
+    fn write_chunk_flag(&self, wgpu_backend: &WGPUBackend, slot: UVec3, flag: u8) {
+        wgpu_backend.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.chunks_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: slot.x, y: slot.y, z: slot.z },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &[flag],
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: None,
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
\ No newline at end of file
@@ -0,0 +1,601 @@
+use std::fmt;
+
+use bytemuck::{
+    Pod,
+    Zeroable
+};
+
+use glam::{
+    Mat4,
+    Quat,
+    Vec3
+};
+
+pub mod codegen;
+pub mod evaluate;
+pub mod light;
+pub mod material;
+
+use light::{
+    Light,
+    LightData,
+    LightUniform,
+    OcclusionSettings,
+    ShadowSettings,
+    MAX_LIGHTS
+};
+
+use material::{
+    Material,
+    MaterialData,
+    MaterialUniform,
+    MAX_MATERIALS
+};
+
+/// Maximum number of instructions a flattened scene can hold on the GPU.
+pub const MAX_INSTRUCTIONS: usize = 64;
+
+// Opcodes of the scene program, primitives are below OPCODE_UNION
+pub const OPCODE_SPHERE: u32 = 0;
+pub const OPCODE_BOX: u32 = 1;
+pub const OPCODE_ROUNDED_BOX: u32 = 2;
+pub const OPCODE_TORUS: u32 = 3;
+pub const OPCODE_CAPSULE: u32 = 4;
+pub const OPCODE_CYLINDER: u32 = 5;
+pub const OPCODE_PLANE: u32 = 6;
+
+pub const OPCODE_UNION: u32 = 16;
+pub const OPCODE_SUBTRACTION: u32 = 17;
+pub const OPCODE_INTERSECTION: u32 = 18;
+pub const OPCODE_SMOOTH_UNION: u32 = 19;
+pub const OPCODE_SMOOTH_SUBTRACTION: u32 = 20;
+pub const OPCODE_SMOOTH_INTERSECTION: u32 = 21;
+
+/// Signed distance primitives, defined in their local space. Capsules, cylinders and tori are
+/// aligned with the Z axis, which is up in the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primitive {
+    Sphere { radius: f32 },
+    Box { half_extents: Vec3 },
+    RoundedBox { half_extents: Vec3, radius: f32 },
+    Torus { major_radius: f32, minor_radius: f32 },
+    Capsule { half_height: f32, radius: f32 },
+    Cylinder { half_height: f32, radius: f32 },
+    /// Points on the side of `normal` are outside, `offset` is the distance of the plane from the origin.
+    Plane { normal: Vec3, offset: f32 },
+}
+
+impl Primitive {
+    pub fn opcode(&self) -> u32 {
+        return match self {
+            Primitive::Sphere { .. } => OPCODE_SPHERE,
+            Primitive::Box { .. } => OPCODE_BOX,
+            Primitive::RoundedBox { .. } => OPCODE_ROUNDED_BOX,
+            Primitive::Torus { .. } => OPCODE_TORUS,
+            Primitive::Capsule { .. } => OPCODE_CAPSULE,
+            Primitive::Cylinder { .. } => OPCODE_CYLINDER,
+            Primitive::Plane { .. } => OPCODE_PLANE,
+        };
+    }
+
+    pub fn parameters(&self) -> [f32; 4] {
+        return match *self {
+            Primitive::Sphere { radius } => [radius, 0.0, 0.0, 0.0],
+            Primitive::Box { half_extents } => [half_extents.x, half_extents.y, half_extents.z, 0.0],
+            Primitive::RoundedBox { half_extents, radius } => [half_extents.x, half_extents.y, half_extents.z, radius],
+            Primitive::Torus { major_radius, minor_radius } => [major_radius, minor_radius, 0.0, 0.0],
+            Primitive::Capsule { half_height, radius } => [half_height, radius, 0.0, 0.0],
+            Primitive::Cylinder { half_height, radius } => [half_height, radius, 0.0, 0.0],
+            Primitive::Plane { normal, offset } => {
+                let normal = normal.normalize();
+
+                [normal.x, normal.y, normal.z, offset]
+            }
+        };
+    }
+}
+
+/// CSG operators. Subtractions remove the right operand from the left one, smooth variants blend
+/// the operands over a distance of `k`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    Union,
+    Subtraction,
+    Intersection,
+    SmoothUnion { k: f32 },
+    SmoothSubtraction { k: f32 },
+    SmoothIntersection { k: f32 },
+}
+
+impl Operation {
+    pub fn opcode(&self) -> u32 {
+        return match self {
+            Operation::Union => OPCODE_UNION,
+            Operation::Subtraction => OPCODE_SUBTRACTION,
+            Operation::Intersection => OPCODE_INTERSECTION,
+            Operation::SmoothUnion { .. } => OPCODE_SMOOTH_UNION,
+            Operation::SmoothSubtraction { .. } => OPCODE_SMOOTH_SUBTRACTION,
+            Operation::SmoothIntersection { .. } => OPCODE_SMOOTH_INTERSECTION,
+        };
+    }
+
+    pub fn parameters(&self) -> [f32; 4] {
+        return match *self {
+            Operation::Union | Operation::Subtraction | Operation::Intersection => [0.0; 4],
+            Operation::SmoothUnion { k } | Operation::SmoothSubtraction { k } | Operation::SmoothIntersection { k } => [k.max(1e-4), 0.0, 0.0, 0.0],
+        };
+    }
+}
+
+/// Rigid transform with a uniform scale, the only kind of scale that keeps distances exact.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: f32,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: 1.0,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        return Self {
+            translation,
+            ..Self::IDENTITY
+        };
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        return Mat4::from_scale_rotation_translation(Vec3::splat(self.scale), self.rotation, self.translation);
+    }
+
+    /// Applies `self` after `child`, the way a parent transform applies to its children.
+    pub fn then(&self, child: &Transform) -> Transform {
+        return Transform {
+            translation: self.translation + self.rotation * (child.translation * self.scale),
+            rotation: self.rotation * child.rotation,
+            scale: self.scale * child.scale,
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SceneNode {
+    Primitive {
+        primitive: Primitive,
+        /// Index in `Scene::materials`.
+        material: u32,
+    },
+    Operation {
+        operation: Operation,
+        left: Box<SceneNode>,
+        right: Box<SceneNode>,
+    },
+    Transform {
+        transform: Transform,
+        child: Box<SceneNode>,
+    },
+}
+
+impl SceneNode {
+    pub fn primitive(primitive: Primitive) -> Self {
+        return SceneNode::Primitive {
+            primitive,
+            material: 0,
+        };
+    }
+
+    pub fn sphere(radius: f32) -> Self {
+        return Self::primitive(Primitive::Sphere { radius });
+    }
+
+    pub fn cuboid(half_extents: Vec3) -> Self {
+        return Self::primitive(Primitive::Box { half_extents });
+    }
+
+    pub fn rounded_cuboid(half_extents: Vec3, radius: f32) -> Self {
+        return Self::primitive(Primitive::RoundedBox { half_extents, radius });
+    }
+
+    pub fn torus(major_radius: f32, minor_radius: f32) -> Self {
+        return Self::primitive(Primitive::Torus { major_radius, minor_radius });
+    }
+
+    pub fn capsule(half_height: f32, radius: f32) -> Self {
+        return Self::primitive(Primitive::Capsule { half_height, radius });
+    }
+
+    pub fn cylinder(half_height: f32, radius: f32) -> Self {
+        return Self::primitive(Primitive::Cylinder { half_height, radius });
+    }
+
+    pub fn plane(normal: Vec3, offset: f32) -> Self {
+        return Self::primitive(Primitive::Plane { normal, offset });
+    }
+
+    /// Assigns `material` to every primitive of the subtree.
+    pub fn with_material(self, material: u32) -> Self {
+        return match self {
+            SceneNode::Primitive { primitive, .. } => SceneNode::Primitive {
+                primitive,
+                material,
+            },
+            SceneNode::Operation { operation, left, right } => SceneNode::Operation {
+                operation,
+                left: Box::new(left.with_material(material)),
+                right: Box::new(right.with_material(material)),
+            },
+            SceneNode::Transform { transform, child } => SceneNode::Transform {
+                transform,
+                child: Box::new(child.with_material(material)),
+            },
+        };
+    }
+
+    pub fn transformed(self, transform: Transform) -> Self {
+        return SceneNode::Transform {
+            transform,
+            child: Box::new(self),
+        };
+    }
+
+    pub fn translated(self, translation: Vec3) -> Self {
+        return self.transformed(Transform::from_translation(translation));
+    }
+
+    pub fn operation(self, operation: Operation, right: SceneNode) -> Self {
+        return SceneNode::Operation {
+            operation,
+            left: Box::new(self),
+            right: Box::new(right),
+        };
+    }
+
+    pub fn union(self, right: SceneNode) -> Self {
+        return self.operation(Operation::Union, right);
+    }
+
+    pub fn subtraction(self, right: SceneNode) -> Self {
+        return self.operation(Operation::Subtraction, right);
+    }
+
+    pub fn intersection(self, right: SceneNode) -> Self {
+        return self.operation(Operation::Intersection, right);
+    }
+
+    pub fn smooth_union(self, right: SceneNode, k: f32) -> Self {
+        return self.operation(Operation::SmoothUnion { k }, right);
+    }
+
+    pub fn smooth_subtraction(self, right: SceneNode, k: f32) -> Self {
+        return self.operation(Operation::SmoothSubtraction { k }, right);
+    }
+
+    pub fn smooth_intersection(self, right: SceneNode, k: f32) -> Self {
+        return self.operation(Operation::SmoothIntersection { k }, right);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SceneError {
+    TooManyInstructions(usize),
+    TooManyLights(usize),
+    TooManyMaterials(usize),
+    UnknownMaterial(u32),
+    /// A plane whose normal has no direction.
+    InvalidPlaneNormal(Vec3),
+    /// A primitive whose transforms scale it by zero, a negative or a non finite factor.
+    InvalidScale(f32),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            SceneError::TooManyInstructions(count) => write!(f, "scene needs {} instructions, at most {} fit on the GPU", count, MAX_INSTRUCTIONS),
+            SceneError::TooManyLights(count) => write!(f, "scene has {} lights, at most {} fit on the GPU", count, MAX_LIGHTS),
+            SceneError::TooManyMaterials(count) => write!(f, "scene has {} materials, at most {} fit on the GPU", count, MAX_MATERIALS),
+            SceneError::UnknownMaterial(material) => write!(f, "scene references material {} which it does not define", material),
+            SceneError::InvalidPlaneNormal(normal) => write!(f, "scene has a plane with normal {} which has no direction", normal),
+            SceneError::InvalidScale(scale) => write!(f, "scene scales a primitive by {}, scales must be positive", scale),
+        };
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// One step of the postfix scene program. Primitives push a distance, operations pop two and push
+/// the combined one. The program structure is compiled into WGSL by `codegen`, while the parameters
+/// and transforms are read from the uniform buffer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct SceneInstruction {
+    pub opcode: u32,
+    pub scale: f32,
+    /// Index in `Scene::materials` of primitives, unused by operations.
+    pub material: u32,
+    pub _padding: u32,
+    pub parameters: [f32; 4],
+    pub inverse_transform: [f32; 16],
+}
+
+/// Layout of the scene uniform buffer in sdf.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct SceneUniform {
+    pub instruction_count: u32,
+    pub _padding: [u32; 3],
+    pub instructions: [SceneInstruction; MAX_INSTRUCTIONS],
+}
+
+impl SceneUniform {
+    pub fn new(instructions: &[SceneInstruction]) -> Self {
+        let mut uniform = Self::zeroed();
+        uniform.instruction_count = instructions.len() as u32;
+        uniform.instructions[..instructions.len()].copy_from_slice(instructions);
+
+        return uniform;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scene {
+    pub root: SceneNode,
+    pub lights: Vec<Light>,
+    /// Light reaching every surface regardless of the lights.
+    pub ambient: Vec3,
+    pub shadows: ShadowSettings,
+    pub occlusion: OcclusionSettings,
+    pub materials: Vec<Material>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        return Self {
+            root: SceneNode::sphere(1.0),
+            lights: Self::default_lights(),
+            ambient: Vec3::splat(0.1),
+            shadows: ShadowSettings::new(),
+            occlusion: OcclusionSettings::new(),
+            materials: vec![Material::new()],
+        };
+    }
+
+    /// A warm sun with a cool fill light from the opposite side.
+    fn default_lights() -> Vec<Light> {
+        return vec![
+            Light::directional(Vec3::new(-0.4, 0.6, -1.0), Vec3::new(1.0, 0.95, 0.85), 1.0),
+            Light::directional(Vec3::new(0.6, -0.3, -0.2), Vec3::new(0.6, 0.7, 1.0), 0.25),
+        ];
+    }
+
+    /// A small scene exercising every primitive and operator, used by the headless mode and the tests.
+    pub fn showcase() -> Self {
+        let hollow_box = SceneNode::rounded_cuboid(Vec3::splat(0.6), 0.1)
+            .subtraction(SceneNode::sphere(0.75))
+            .with_material(1)
+            .translated(Vec3::new(-1.8, 0.0, 0.0));
+
+        let blob = SceneNode::sphere(0.5).with_material(2)
+            .smooth_union(SceneNode::capsule(0.5, 0.25).with_material(3).translated(Vec3::new(0.0, 0.0, 0.4)), 0.3);
+
+        let ring = SceneNode::torus(0.6, 0.15)
+            .smooth_subtraction(SceneNode::cuboid(Vec3::new(1.0, 0.2, 1.0)), 0.05)
+            .with_material(4)
+            .transformed(Transform {
+                translation: Vec3::new(1.8, 0.0, 0.0),
+                rotation: Quat::from_rotation_x(std::f32::consts::FRAC_PI_2),
+                scale: 1.0,
+            });
+
+        let lens = SceneNode::sphere(0.7).translated(Vec3::new(0.3, 0.0, 0.0))
+            .intersection(SceneNode::sphere(0.7).translated(Vec3::new(-0.3, 0.0, 0.0)))
+            .smooth_intersection(SceneNode::cylinder(1.0, 0.35), 0.1)
+            .with_material(5)
+            .translated(Vec3::new(0.0, 0.0, 1.4));
+
+        let floor = SceneNode::plane(Vec3::Z, -1.0);
+
+        let mut lights = Self::default_lights();
+        lights.push(Light::point(Vec3::new(0.0, -1.5, 1.0), Vec3::new(1.0, 0.5, 0.2), 1.5));
+
+        return Self {
+            root: hollow_box.union(blob).union(ring).union(lens).union(floor),
+            lights,
+            ambient: Vec3::splat(0.1),
+            shadows: ShadowSettings::new(),
+            occlusion: OcclusionSettings::new(),
+            materials: vec![
+                Material::new(),
+                Material { albedo: Vec3::new(0.8, 0.15, 0.1), roughness: 0.3, ..Material::new() },
+                Material { albedo: Vec3::new(0.1, 0.3, 0.9), ..Material::new() },
+                Material { albedo: Vec3::new(0.95, 0.8, 0.2), roughness: 0.7, ..Material::new() },
+                Material { albedo: Vec3::new(1.0, 0.78, 0.34), roughness: 0.25, metallic: 1.0, ..Material::new() },
+                Material { albedo: Vec3::new(0.2, 0.6, 0.3), emissive: Vec3::new(0.1, 0.5, 0.25), ..Material::new() },
+            ],
+        };
+    }
+
+    /// Flattens the scene tree into the postfix program uploaded to the GPU, with transforms
+    /// composed down to the primitives, which must have a valid scale and plane normal.
+    pub fn instructions(&self) -> Result<Vec<SceneInstruction>, SceneError> {
+        let mut instructions = Vec::<SceneInstruction>::new();
+
+        Self::flatten(&self.root, &Transform::IDENTITY, &mut instructions)?;
+
+        if instructions.len() > MAX_INSTRUCTIONS {
+            return Err(SceneError::TooManyInstructions(instructions.len()));
+        }
+
+        return Ok(instructions);
+    }
+
+    /// Packs the materials into the layout of the materials uniform buffer, checking that every
+    /// primitive references one of them.
+    pub fn material_uniform(&self) -> Result<MaterialUniform, SceneError> {
+        if self.materials.len() > MAX_MATERIALS {
+            return Err(SceneError::TooManyMaterials(self.materials.len()));
+        }
+
+        for instruction in self.instructions()?.iter() {
+            if instruction.opcode < OPCODE_UNION && instruction.material as usize >= self.materials.len() {
+                return Err(SceneError::UnknownMaterial(instruction.material));
+            }
+        }
+
+        let materials = self.materials.iter().map(MaterialData::new).collect::<Vec<_>>();
+
+        return Ok(MaterialUniform::new(&materials));
+    }
+
+    /// Packs the lights into the layout of the lights uniform buffer.
+    pub fn light_uniform(&self) -> Result<LightUniform, SceneError> {
+        if self.lights.len() > MAX_LIGHTS {
+            return Err(SceneError::TooManyLights(self.lights.len()));
+        }
+
+        let lights = self.lights.iter().map(LightData::new).collect::<Vec<_>>();
+
+        return Ok(LightUniform::new(self.ambient, &self.shadows, &self.occlusion, &lights));
+    }
+
+    fn flatten(node: &SceneNode, transform: &Transform, instructions: &mut Vec<SceneInstruction>) -> Result<(), SceneError> {
+        match node {
+            SceneNode::Primitive { primitive, material } => {
+                // Both would turn every distance of the scene into NaN on the GPU
+                if !(transform.scale.is_finite() && transform.scale > 0.0) {
+                    return Err(SceneError::InvalidScale(transform.scale));
+                }
+
+                if let Primitive::Plane { normal, .. } = primitive {
+                    if normal.try_normalize().is_none() {
+                        return Err(SceneError::InvalidPlaneNormal(*normal));
+                    }
+                }
+
+                let inverse_transform = transform.matrix().inverse();
+
+                instructions.push(SceneInstruction {
+                    opcode: primitive.opcode(),
+                    scale: transform.scale,
+                    material: *material,
+                    _padding: 0,
+                    parameters: primitive.parameters(),
+                    inverse_transform: inverse_transform.to_cols_array(),
+                });
+            }
+            SceneNode::Operation { operation, left, right } => {
+                Self::flatten(left, transform, instructions)?;
+                Self::flatten(right, transform, instructions)?;
+
+                instructions.push(SceneInstruction {
+                    opcode: operation.opcode(),
+                    scale: 1.0,
+                    material: 0,
+                    _padding: 0,
+                    parameters: operation.parameters(),
+                    inverse_transform: Mat4::IDENTITY.to_cols_array(),
+                });
+            }
+            SceneNode::Transform { transform: child_transform, child } => {
+                Self::flatten(child, &transform.then(child_transform), instructions)?;
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{
+        Mat4,
+        Quat,
+        Vec3,
+        Vec4
+    };
+
+    use super::{
+        Scene,
+        SceneError,
+        SceneNode,
+        Transform,
+        MAX_INSTRUCTIONS,
+        OPCODE_BOX,
+        OPCODE_PLANE,
+        OPCODE_SMOOTH_UNION,
+        OPCODE_SPHERE,
+        OPCODE_UNION
+    };
+
+    fn scene(root: SceneNode) -> Scene {
+        return Scene {
+            root,
+            ..Scene::new()
+        };
+    }
+
+    #[test]
+    fn trees_flatten_to_postfix_programs() {
+        let root = SceneNode::sphere(1.0)
+            .smooth_union(SceneNode::cuboid(Vec3::ONE).with_material(2), 0.5)
+            .union(SceneNode::plane(Vec3::new(0.0, 0.0, 2.0), -1.0));
+
+        let instructions = scene(root).instructions().unwrap();
+        let opcodes = instructions.iter().map(|instruction| instruction.opcode).collect::<Vec<_>>();
+
+        assert_eq!(opcodes, [OPCODE_SPHERE, OPCODE_BOX, OPCODE_SMOOTH_UNION, OPCODE_PLANE, OPCODE_UNION]);
+        assert_eq!(instructions[1].material, 2);
+        assert_eq!(instructions[2].parameters[0], 0.5);
+        assert_eq!(instructions[3].parameters, [0.0, 0.0, 1.0, -1.0], "Plane normals are normalized");
+    }
+
+    #[test]
+    fn transforms_compose_down_to_the_primitives() {
+        let transform = Transform {
+            translation: Vec3::new(1.0, 0.0, 0.0),
+            rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            scale: 2.0,
+        };
+
+        let root = SceneNode::sphere(1.0).translated(Vec3::new(0.0, 1.0, 0.0)).transformed(transform);
+        let instructions = scene(root).instructions().unwrap();
+
+        // The sphere ends up at (1, 0, 0) + rotated (0, 2, 0), its inverse transform brings it back
+        let inverse = Mat4::from_cols_array(&instructions[0].inverse_transform);
+        let center = inverse * Vec4::new(-1.0, 0.0, 0.0, 1.0);
+
+        assert_eq!(instructions[0].scale, 2.0);
+        assert!(center.truncate().length() < 1e-5, "{}", center);
+    }
+
+    #[test]
+    fn degenerate_primitives_are_rejected() {
+        let flat = scene(SceneNode::sphere(1.0).union(SceneNode::plane(Vec3::ZERO, 0.0)));
+        assert_eq!(flat.instructions(), Err(SceneError::InvalidPlaneNormal(Vec3::ZERO)));
+
+        let shrunk = Transform {
+            scale: 0.0,
+            ..Transform::IDENTITY
+        };
+
+        let collapsed = scene(SceneNode::sphere(1.0).transformed(shrunk));
+        assert_eq!(collapsed.instructions(), Err(SceneError::InvalidScale(0.0)));
+        assert_eq!(collapsed.material_uniform().err(), Some(SceneError::InvalidScale(0.0)));
+    }
+
+    #[test]
+    fn oversize_scenes_are_rejected() {
+        let mut root = SceneNode::sphere(1.0);
+
+        for _ in 0..MAX_INSTRUCTIONS / 2 {
+            root = root.union(SceneNode::sphere(1.0));
+        }
+
+        assert_eq!(scene(root).instructions(), Err(SceneError::TooManyInstructions(MAX_INSTRUCTIONS + 1)));
+        assert!(Scene::showcase().instructions().is_ok());
+    }
+}
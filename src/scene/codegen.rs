@@ -0,0 +1,166 @@
+use std::fmt::Write;
+
+use crate::scene::{
+    SceneInstruction,
+    OPCODE_BOX,
+    OPCODE_CAPSULE,
+    OPCODE_CYLINDER,
+    OPCODE_INTERSECTION,
+    OPCODE_PLANE,
+    OPCODE_ROUNDED_BOX,
+    OPCODE_SMOOTH_INTERSECTION,
+    OPCODE_SMOOTH_SUBTRACTION,
+    OPCODE_SMOOTH_UNION,
+    OPCODE_SPHERE,
+    OPCODE_SUBTRACTION,
+    OPCODE_TORUS,
+    OPCODE_UNION
+};
+
+/// Name of the sdf.wgsl function implementing `opcode`.
+fn function_name(opcode: u32) -> &'static str {
+    return match opcode {
+        OPCODE_SPHERE => "sd_sphere",
+        OPCODE_BOX => "sd_box",
+        OPCODE_ROUNDED_BOX => "sd_rounded_box",
+        OPCODE_TORUS => "sd_torus",
+        OPCODE_CAPSULE => "sd_capsule",
+        OPCODE_CYLINDER => "sd_cylinder",
+        OPCODE_PLANE => "sd_plane",
+        OPCODE_UNION => "op_union",
+        OPCODE_SUBTRACTION => "op_subtraction",
+        OPCODE_INTERSECTION => "op_intersection",
+        OPCODE_SMOOTH_UNION => "op_smooth_union",
+        OPCODE_SMOOTH_SUBTRACTION => "op_smooth_subtraction",
+        OPCODE_SMOOTH_INTERSECTION => "op_smooth_intersection",
+        _ => unreachable!("Unknown scene opcode {}", opcode),
+    };
+}
+
+/// The part of a scene that is baked into the generated shader. Two scenes with the same structure
+/// share a pipeline and only differ by the contents of the scene uniform buffer.
+pub fn structure(instructions: &[SceneInstruction]) -> Vec<u32> {
+    return instructions.iter().map(|instruction| instruction.opcode).collect();
+}
+
+/// Replaces `structure` with the one of `instructions` when they differ, returning whether the shader
+/// generated for the old structure has to be rebuilt.
+pub fn update_structure(structure: &mut Vec<u32>, instructions: &[SceneInstruction]) -> bool {
+    let next = self::structure(instructions);
+
+    if next == *structure {
+        return false;
+    }
+
+    *structure = next;
+
+    return true;
+}
+
+/// Generates a WGSL `map` function evaluating the scene program with straight-line code, returning the
+/// distance and material as a `SceneSample`. Parameters, materials and transforms are still read from
+/// the scene uniform buffer, by their instruction index.
+pub fn generate_map(instructions: &[SceneInstruction]) -> String {
+    let mut source = String::from("fn map (p: vec3<f32>) -> SceneSample {\n");
+    let mut stack = Vec::<usize>::new();
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        let function = function_name(instruction.opcode);
+        let parameters = format!("scene.instructions[{}u].parameters", index);
+
+        if instruction.opcode < OPCODE_UNION {
+            writeln!(source, "    let s{} = scene_sample({}(scene_local_point({}u, p), {}) * scene.instructions[{}u].scale, scene.instructions[{}u].material);", index, function, index, parameters, index, index).unwrap();
+        } else {
+            let right = stack.pop().expect("Scene program pops an empty stack");
+            let left = stack.pop().expect("Scene program pops an empty stack");
+
+            writeln!(source, "    let s{} = {}(s{}, s{}, {});", index, function, left, right, parameters).unwrap();
+        }
+
+        stack.push(index);
+    }
+
+    match stack.last() {
+        Some(result) => writeln!(source, "    return s{};", result).unwrap(),
+        None => writeln!(source, "    return scene_sample(1e10, 0u);").unwrap(),
+    }
+
+    source.push('}');
+
+    return source;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::scene::{
+        Scene,
+        SceneNode
+    };
+
+    use super::{
+        generate_map,
+        structure,
+        update_structure
+    };
+
+    fn scene(root: SceneNode) -> Scene {
+        return Scene {
+            root,
+            ..Scene::new()
+        };
+    }
+
+    #[test]
+    fn primitives_read_their_instruction() {
+        let instructions = scene(SceneNode::sphere(1.0)).instructions().unwrap();
+
+        assert_eq!(generate_map(&instructions), "\
+fn map (p: vec3<f32>) -> SceneSample {
+    let s0 = scene_sample(sd_sphere(scene_local_point(0u, p), scene.instructions[0u].parameters) * scene.instructions[0u].scale, scene.instructions[0u].material);
+    return s0;
+}");
+    }
+
+    #[test]
+    fn operations_combine_the_top_of_the_stack() {
+        let root = SceneNode::sphere(1.0)
+            .smooth_union(SceneNode::cuboid(Vec3::ONE), 0.2)
+            .subtraction(SceneNode::torus(1.0, 0.25).translated(Vec3::Z));
+
+        let source = generate_map(&scene(root).instructions().unwrap());
+        let lines = source.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 8);
+        assert!(lines[1].starts_with("    let s0 = scene_sample(sd_sphere(scene_local_point(0u, p)"), "{}", lines[1]);
+        assert!(lines[2].starts_with("    let s1 = scene_sample(sd_box(scene_local_point(1u, p)"), "{}", lines[2]);
+        assert_eq!(lines[3], "    let s2 = op_smooth_union(s0, s1, scene.instructions[2u].parameters);");
+        assert!(lines[4].starts_with("    let s3 = scene_sample(sd_torus(scene_local_point(3u, p)"), "{}", lines[4]);
+        assert_eq!(lines[5], "    let s4 = op_subtraction(s2, s3, scene.instructions[4u].parameters);");
+        assert_eq!(lines[6], "    return s4;");
+    }
+
+    #[test]
+    fn empty_programs_miss_everywhere() {
+        assert_eq!(generate_map(&[]), "fn map (p: vec3<f32>) -> SceneSample {\n    return scene_sample(1e10, 0u);\n}");
+    }
+
+    #[test]
+    fn only_structural_changes_rebuild() {
+        let original = scene(SceneNode::sphere(1.0).union(SceneNode::cuboid(Vec3::ONE)));
+        let mut built = structure(&original.instructions().unwrap());
+
+        // Sizes, materials and transforms live in the uniform buffer
+        let moved = scene(SceneNode::sphere(2.0).with_material(1).union(SceneNode::cuboid(Vec3::splat(0.5)).translated(Vec3::X)));
+
+        assert!(!update_structure(&mut built, &moved.instructions().unwrap()));
+
+        let swapped = scene(SceneNode::sphere(1.0).subtraction(SceneNode::cuboid(Vec3::ONE)));
+
+        assert!(update_structure(&mut built, &swapped.instructions().unwrap()));
+        assert_eq!(built, structure(&swapped.instructions().unwrap()));
+        assert!(!update_structure(&mut built, &swapped.instructions().unwrap()));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,352 @@
+use std::cell::RefCell;
+
+use glam::{
+    Mat4,
+    Vec2,
+    Vec3,
+    Vec3Swizzles,
+    Vec4,
+    Vec4Swizzles
+};
+
+use crate::scene::{
+    Scene,
+    SceneError,
+    SceneInstruction,
+    OPCODE_BOX,
+    OPCODE_CAPSULE,
+    OPCODE_CYLINDER,
+    OPCODE_INTERSECTION,
+    OPCODE_PLANE,
+    OPCODE_ROUNDED_BOX,
+    OPCODE_SMOOTH_INTERSECTION,
+    OPCODE_SMOOTH_SUBTRACTION,
+    OPCODE_SMOOTH_UNION,
+    OPCODE_SPHERE,
+    OPCODE_SUBTRACTION,
+    OPCODE_TORUS,
+    OPCODE_UNION
+};
+
+/// Offset of the distance samples used to estimate normals, shared with lighting.wgsl.
+pub const NORMAL_EPSILON: f32 = 0.001;
+
+// CPU counterparts of the functions in sdf.wgsl, they have to stay numerically identical
+
+pub fn sd_sphere(p: Vec3, parameters: Vec4) -> f32 {
+    return p.length() - parameters.x;
+}
+
+pub fn sd_box(p: Vec3, parameters: Vec4) -> f32 {
+    let q = p.abs() - parameters.xyz();
+
+    return q.max(Vec3::ZERO).length() + q.x.max(q.y.max(q.z)).min(0.0);
+}
+
+pub fn sd_rounded_box(p: Vec3, parameters: Vec4) -> f32 {
+    return sd_box(p, (parameters.xyz() - Vec3::splat(parameters.w)).extend(0.0)) - parameters.w;
+}
+
+pub fn sd_torus(p: Vec3, parameters: Vec4) -> f32 {
+    let q = Vec2::new(p.xy().length() - parameters.x, p.z);
+
+    return q.length() - parameters.y;
+}
+
+pub fn sd_capsule(p: Vec3, parameters: Vec4) -> f32 {
+    let q = Vec3::new(p.x, p.y, p.z - p.z.clamp(-parameters.x, parameters.x));
+
+    return q.length() - parameters.y;
+}
+
+pub fn sd_cylinder(p: Vec3, parameters: Vec4) -> f32 {
+    let d = Vec2::new(p.xy().length(), p.z).abs() - Vec2::new(parameters.y, parameters.x);
+
+    return d.x.max(d.y).min(0.0) + d.max(Vec2::ZERO).length();
+}
+
+pub fn sd_plane(p: Vec3, parameters: Vec4) -> f32 {
+    return p.dot(parameters.xyz()) - parameters.w;
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    return a * (1.0 - t) + b * t;
+}
+
+/// Distance to the scene with the material of the closest surface. Smooth operations blend two
+/// materials, `blend` is the weight of `blend_material`. Mirrors `SceneSample` in sdf.wgsl.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SceneSample {
+    pub distance: f32,
+    pub material: u32,
+    pub blend_material: u32,
+    pub blend: f32,
+}
+
+impl SceneSample {
+    pub fn new(distance: f32, material: u32) -> Self {
+        return Self {
+            distance,
+            material,
+            blend_material: material,
+            blend: 0.0,
+        };
+    }
+
+    /// The material weighing the most, nested blends only keep that one.
+    pub fn dominant_material(&self) -> u32 {
+        return if self.blend < 0.5 { self.material } else { self.blend_material };
+    }
+
+    fn blended(distance: f32, a: &SceneSample, b: &SceneSample, blend: f32) -> Self {
+        return Self {
+            distance,
+            material: a.dominant_material(),
+            blend_material: b.dominant_material(),
+            blend,
+        };
+    }
+}
+
+pub fn op_union(a: SceneSample, b: SceneSample, _parameters: Vec4) -> SceneSample {
+    return if a.distance < b.distance { a } else { b };
+}
+
+pub fn op_subtraction(a: SceneSample, b: SceneSample, _parameters: Vec4) -> SceneSample {
+    return SceneSample { distance: a.distance.max(-b.distance), ..a };
+}
+
+pub fn op_intersection(a: SceneSample, b: SceneSample, _parameters: Vec4) -> SceneSample {
+    return if a.distance > b.distance { a } else { b };
+}
+
+pub fn op_smooth_union(a: SceneSample, b: SceneSample, parameters: Vec4) -> SceneSample {
+    let k = parameters.x;
+    let h = (0.5 + 0.5 * (b.distance - a.distance) / k).clamp(0.0, 1.0);
+
+    return SceneSample::blended(mix(b.distance, a.distance, h) - k * h * (1.0 - h), &a, &b, 1.0 - h);
+}
+
+pub fn op_smooth_subtraction(a: SceneSample, b: SceneSample, parameters: Vec4) -> SceneSample {
+    let k = parameters.x;
+    let h = (0.5 - 0.5 * (a.distance + b.distance) / k).clamp(0.0, 1.0);
+
+    return SceneSample { distance: mix(a.distance, -b.distance, h) + k * h * (1.0 - h), ..a };
+}
+
+pub fn op_smooth_intersection(a: SceneSample, b: SceneSample, parameters: Vec4) -> SceneSample {
+    let k = parameters.x;
+    let h = (0.5 - 0.5 * (b.distance - a.distance) / k).clamp(0.0, 1.0);
+
+    return SceneSample::blended(mix(b.distance, a.distance, h) + k * h * (1.0 - h), &a, &b, 1.0 - h);
+}
+
+thread_local! {
+    /// Stack of the postfix program, reused by every sample taken on a thread. Evaluators are shared
+    /// between the threads of the CPU ray marcher, so they cannot own it.
+    static STACK: RefCell<Vec<SceneSample>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Evaluates a scene on the CPU, running the same postfix program the shader is generated from.
+#[derive(Clone, Debug)]
+pub struct SceneEvaluator {
+    instructions: Vec<SceneInstruction>,
+}
+
+impl SceneEvaluator {
+    pub fn new(scene: &Scene) -> Result<Self, SceneError> {
+        return Ok(Self {
+            instructions: scene.instructions()?,
+        });
+    }
+
+    pub fn distance(&self, p: Vec3) -> f32 {
+        return self.sample(p).distance;
+    }
+
+    /// Distance and material at `p`, like the generated `map` function.
+    pub fn sample(&self, p: Vec3) -> SceneSample {
+        return STACK.with_borrow_mut(|stack| self.run(p, stack));
+    }
+
+    fn run(&self, p: Vec3, stack: &mut Vec<SceneSample>) -> SceneSample {
+        stack.clear();
+
+        for instruction in self.instructions.iter() {
+            let parameters = Vec4::from_array(instruction.parameters);
+
+            if instruction.opcode < OPCODE_UNION {
+                let inverse_transform = Mat4::from_cols_array(&instruction.inverse_transform);
+                let local_p = (inverse_transform * p.extend(1.0)).xyz();
+
+                let distance = match instruction.opcode {
+                    OPCODE_SPHERE => sd_sphere(local_p, parameters),
+                    OPCODE_BOX => sd_box(local_p, parameters),
+                    OPCODE_ROUNDED_BOX => sd_rounded_box(local_p, parameters),
+                    OPCODE_TORUS => sd_torus(local_p, parameters),
+                    OPCODE_CAPSULE => sd_capsule(local_p, parameters),
+                    OPCODE_CYLINDER => sd_cylinder(local_p, parameters),
+                    OPCODE_PLANE => sd_plane(local_p, parameters),
+                    _ => unreachable!("Unknown primitive opcode {}", instruction.opcode),
+                };
+
+                stack.push(SceneSample::new(distance * instruction.scale, instruction.material));
+            } else {
+                let b = stack.pop().expect("Scene program pops an empty stack");
+                let a = stack.pop().expect("Scene program pops an empty stack");
+
+                let sample = match instruction.opcode {
+                    OPCODE_UNION => op_union(a, b, parameters),
+                    OPCODE_SUBTRACTION => op_subtraction(a, b, parameters),
+                    OPCODE_INTERSECTION => op_intersection(a, b, parameters),
+                    OPCODE_SMOOTH_UNION => op_smooth_union(a, b, parameters),
+                    OPCODE_SMOOTH_SUBTRACTION => op_smooth_subtraction(a, b, parameters),
+                    OPCODE_SMOOTH_INTERSECTION => op_smooth_intersection(a, b, parameters),
+                    _ => unreachable!("Unknown operation opcode {}", instruction.opcode),
+                };
+
+                stack.push(sample);
+            }
+        }
+
+        return stack.pop().unwrap_or(SceneSample::new(1e10, 0));
+    }
+
+    /// Surface normal at `p` from the central differences of the distance field, like `scene_normal`
+    /// in lighting.wgsl.
+    pub fn normal(&self, p: Vec3) -> Vec3 {
+        let e = Vec3::new(NORMAL_EPSILON, 0.0, 0.0);
+
+        let gradient = Vec3::new(
+            self.distance(p + e.xyy()) - self.distance(p - e.xyy()),
+            self.distance(p + e.yxy()) - self.distance(p - e.yxy()),
+            self.distance(p + e.yyx()) - self.distance(p - e.yyx()),
+        );
+
+        return gradient.normalize_or_zero();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{
+        Quat,
+        Vec3
+    };
+
+    use crate::{
+        no_adapter,
+        renderer::probe,
+        scene::{
+            codegen,
+            material::Material,
+            Scene,
+            SceneNode,
+            SceneUniform,
+            Transform
+        }
+    };
+
+    use super::SceneEvaluator;
+
+    const POINT_COUNT: usize = 4096;
+    const TOLERANCE: f32 = 1e-4;
+
+    const PROBE_SHADER: &str = r#"
+@group(0)
+@binding(0)
+var<storage, read> points: array<vec4<f32>>;
+
+@group(0)
+@binding(2)
+var<storage, read_write> samples: array<vec4<f32>>;
+
+@compute
+@workgroup_size(64)
+fn probe(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= arrayLength(&points)) {
+        return;
+    }
+
+    let sample = map(points[id.x].xyz);
+    samples[id.x] = vec4<f32>(sample.distance, f32(sample.material), f32(sample.blend_material), sample.blend);
+}
+"#;
+
+    fn transformed_scene() -> Scene {
+        let rotation = Quat::from_euler(glam::EulerRot::XYZ, 0.3, -0.7, 1.1);
+
+        let body = SceneNode::rounded_cuboid(Vec3::new(0.8, 0.5, 0.3), 0.1)
+            .smooth_union(SceneNode::cylinder(0.9, 0.2).with_material(1).transformed(Transform { translation: Vec3::new(0.4, 0.0, 0.2), rotation, scale: 0.7 }), 0.25)
+            .smooth_intersection(SceneNode::sphere(1.1).with_material(2), 0.15);
+
+        let torus = SceneNode::torus(0.7, 0.2).with_material(3)
+            .subtraction(SceneNode::capsule(0.6, 0.3).translated(Vec3::new(0.7, 0.0, 0.0)))
+            .transformed(Transform { translation: Vec3::new(-1.0, 1.2, 0.4), rotation: rotation.inverse(), scale: 1.6 });
+
+        let floor = SceneNode::plane(Vec3::new(0.1, 0.2, 1.0), -1.5);
+
+        return Scene {
+            root: body.union(torus).union(floor).transformed(Transform { translation: Vec3::new(0.2, -0.1, 0.3), rotation: Quat::from_rotation_z(0.4), scale: 1.3 }),
+            materials: vec![Material::new(); 4],
+            ..Scene::new()
+        };
+    }
+
+    /// Deterministic points spread over the [-3, 3] cube.
+    fn sample_points() -> Vec<[f32; 4]> {
+        let mut state = 0x2545f491u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+
+            return (state as f32 / u32::MAX as f32) * 6.0 - 3.0;
+        };
+
+        return (0..POINT_COUNT).map(|_| [next(), next(), next(), 1.0]).collect();
+    }
+
+    /// Evaluates `scene` at every point with a compute shader, or returns `None` when no adapter is
+    /// available.
+    fn evaluate_on_gpu(scene: &Scene, points: &[[f32; 4]]) -> Option<Vec<[f32; 4]>> {
+        let instructions = scene.instructions().expect("Invalid test scene");
+        let source = format!("{}\n\n{}\n{}", include_str!("../renderer/shaders/sdf.wgsl"), codegen::generate_map(&instructions), PROBE_SHADER);
+
+        return probe::run(&source, &[points], bytemuck::bytes_of(&SceneUniform::new(&instructions)), &[]);
+    }
+
+    fn check_parity(scene: &Scene) {
+        let points = sample_points();
+
+        let Some(gpu_samples) = evaluate_on_gpu(scene, &points) else {
+            no_adapter("SDF parity test");
+            return;
+        };
+
+        let evaluator = SceneEvaluator::new(scene).expect("Invalid test scene");
+
+        for (point, gpu_sample) in points.iter().zip(gpu_samples.iter()) {
+            let p = Vec3::new(point[0], point[1], point[2]);
+            let cpu_sample = evaluator.sample(p);
+
+            assert!(
+                (cpu_sample.distance - gpu_sample[0]).abs() <= TOLERANCE * (1.0 + cpu_sample.distance.abs()),
+                "CPU and GPU disagree at {}: {} against {}", p, cpu_sample.distance, gpu_sample[0]
+            );
+
+            assert_eq!((cpu_sample.material, cpu_sample.blend_material), (gpu_sample[1] as u32, gpu_sample[2] as u32), "CPU and GPU materials disagree at {}", p);
+            assert!((cpu_sample.blend - gpu_sample[3]).abs() <= TOLERANCE, "CPU and GPU blends disagree at {}: {} against {}", p, cpu_sample.blend, gpu_sample[3]);
+        }
+    }
+
+    #[test]
+    fn showcase_matches_gpu() {
+        check_parity(&Scene::showcase());
+    }
+
+    #[test]
+    fn transformed_scene_matches_gpu() {
+        check_parity(&transformed_scene());
+    }
+}
@@ -0,0 +1,384 @@
+use bytemuck::{
+    Pod,
+    Zeroable
+};
+
+use glam::Vec3;
+
+use crate::scene::{
+    evaluate::SceneEvaluator,
+    material::Material,
+    Scene
+};
+
+/// Maximum number of lights a scene can hold on the GPU.
+pub const MAX_LIGHTS: usize = 8;
+
+// Reflectance of dielectrics at normal incidence and lowest roughness, keep in sync with lights.wgsl
+pub const DIELECTRIC_SPECULAR: f32 = 0.04;
+pub const MIN_ROUGHNESS: f32 = 0.05;
+
+// Shadow rays start slightly off the surface and stop this far from directional lights, keep in sync
+// with lighting.wgsl and lights.wgsl
+pub const SHADOW_START: f32 = 0.02;
+pub const SHADOW_MAX_DISTANCE: f32 = 20.0;
+
+// Light kinds, keep in sync with lights.wgsl
+pub const LIGHT_DIRECTIONAL: u32 = 0;
+pub const LIGHT_POINT: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Infinitely far light, `direction` is the way the light travels.
+    Directional { direction: Vec3 },
+    /// Light radiating from `position`, falling off with the squared distance.
+    Point { position: Vec3 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Whether the scene casts shadows from this light, when shadows are enabled.
+    pub shadows: bool,
+    /// Whether ambient occlusion also darkens the light, when it is enabled. It always applies to the
+    /// ambient term.
+    pub occlusion: bool,
+}
+
+impl Light {
+    /// A zero `direction` gives a light that reaches nothing, rather than NaNs in the whole uniform.
+    pub fn directional(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        return Self {
+            kind: LightKind::Directional { direction: direction.normalize_or_zero() },
+            color,
+            intensity,
+            shadows: true,
+            occlusion: false,
+        };
+    }
+
+    pub fn point(position: Vec3, color: Vec3, intensity: f32) -> Self {
+        return Self {
+            kind: LightKind::Point { position },
+            color,
+            intensity,
+            shadows: true,
+            occlusion: false,
+        };
+    }
+
+    pub fn with_shadows(self, shadows: bool) -> Self {
+        return Self {
+            shadows,
+            ..self
+        };
+    }
+
+    pub fn with_occlusion(self, occlusion: bool) -> Self {
+        return Self {
+            occlusion,
+            ..self
+        };
+    }
+
+    /// Direction from `p` towards the light and the radiance reaching `p`.
+    pub fn incident(&self, p: Vec3) -> (Vec3, Vec3) {
+        return match self.kind {
+            LightKind::Directional { direction } => (-direction, self.color * self.intensity),
+            LightKind::Point { position } => {
+                let to_light = position - p;
+                let distance_squared = to_light.length_squared().max(1e-4);
+
+                (to_light.normalize_or_zero(), self.color * self.intensity / distance_squared)
+            }
+        };
+    }
+
+    /// How far a shadow ray from `p` travels before reaching the light.
+    pub fn shadow_distance(&self, p: Vec3) -> f32 {
+        return match self.kind {
+            LightKind::Directional { .. } => SHADOW_MAX_DISTANCE,
+            LightKind::Point { position } => position.distance(p),
+        };
+    }
+}
+
+/// Penumbra shadows, marched from the surface towards each light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Step budget of a shadow ray.
+    pub steps: u32,
+    /// Sharpness of the penumbra, higher values give harder shadows.
+    pub hardness: f32,
+    /// How much light a fully shadowed point loses, from 0 to 1.
+    pub strength: f32,
+}
+
+impl ShadowSettings {
+    pub fn new() -> Self {
+        return Self {
+            enabled: true,
+            steps: 48,
+            hardness: 8.0,
+            strength: 1.0,
+        };
+    }
+}
+
+/// Ambient occlusion estimated from distance samples along the surface normal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OcclusionSettings {
+    pub enabled: bool,
+    /// Number of distance samples.
+    pub samples: u32,
+    /// Spacing of the samples along the normal.
+    pub step: f32,
+    /// Scale of the occlusion, higher values give darker creases.
+    pub strength: f32,
+}
+
+impl OcclusionSettings {
+    pub fn new() -> Self {
+        return Self {
+            enabled: true,
+            samples: 5,
+            step: 0.08,
+            strength: 2.0,
+        };
+    }
+}
+
+/// Fraction of light reaching `origin` from `direction`, using the closest miss of the shadow ray
+/// relative to the travelled distance to widen the penumbra. CPU counterpart of `soft_shadow` in
+/// lighting.wgsl.
+pub fn soft_shadow(evaluator: &SceneEvaluator, settings: &ShadowSettings, origin: Vec3, direction: Vec3, max_distance: f32) -> f32 {
+    let mut result = 1f32;
+    let mut t = SHADOW_START;
+
+    for _ in 0..settings.steps {
+        if t >= max_distance {
+            break;
+        }
+
+        let h = evaluator.distance(origin + direction * t);
+
+        result = result.min(settings.hardness * h / t);
+
+        if result < 0.001 {
+            break;
+        }
+
+        t += h.clamp(0.01, 0.5);
+    }
+
+    return 1.0 - settings.strength * (1.0 - result.clamp(0.0, 1.0));
+}
+
+/// Fraction of ambient light reaching `p`, from how much closer the surface gets than the distance
+/// travelled along `normal`. CPU counterpart of `ambient_occlusion` in lighting.wgsl.
+pub fn ambient_occlusion(evaluator: &SceneEvaluator, settings: &OcclusionSettings, p: Vec3, normal: Vec3) -> f32 {
+    let mut occlusion = 0f32;
+    let mut weight = 1f32;
+
+    for i in 0..settings.samples {
+        let h = settings.step * (i + 1) as f32;
+        let d = evaluator.distance(p + normal * h);
+
+        occlusion += (h - d) * weight;
+        weight *= 0.75;
+    }
+
+    return (1.0 - settings.strength * occlusion).clamp(0.0, 1.0);
+}
+
+/// Blinn-Phong shading of the surface point `p` seen along `view`, the direction from the surface to
+/// the eye, with the shadows and occlusion enabled in `scene`. Roughness maps to the Blinn-Phong
+/// exponent, metals tint their highlights with the albedo. CPU counterpart of `shade` in
+/// lighting.wgsl.
+pub fn shade(scene: &Scene, evaluator: &SceneEvaluator, p: Vec3, normal: Vec3, view: Vec3, material: &Material) -> Vec3 {
+    let roughness = material.roughness.max(MIN_ROUGHNESS);
+    let shininess = (2.0 / roughness.powf(4.0) - 2.0).max(1.0);
+    let normalization = (shininess + 8.0) / 8.0;
+
+    let diffuse_color = material.albedo * (1.0 - material.metallic);
+    let specular_color = Vec3::splat(DIELECTRIC_SPECULAR).lerp(material.albedo, material.metallic);
+
+    let occlusion = if scene.occlusion.enabled {
+        ambient_occlusion(evaluator, &scene.occlusion, p, normal)
+    } else {
+        1.0
+    };
+
+    let mut color = scene.ambient * material.albedo * occlusion;
+
+    for light in scene.lights.iter() {
+        let (to_light, mut radiance) = light.incident(p);
+
+        let diffuse = normal.dot(to_light).max(0.0);
+
+        if diffuse <= 0.0 {
+            continue;
+        }
+
+        if scene.shadows.enabled && light.shadows {
+            radiance *= soft_shadow(evaluator, &scene.shadows, p, to_light, light.shadow_distance(p));
+        }
+
+        if light.occlusion {
+            radiance *= occlusion;
+        }
+
+        let half = (to_light + view).normalize_or_zero();
+        let specular = normal.dot(half).max(0.0).powf(shininess) * normalization;
+
+        color += (diffuse_color * diffuse + specular_color * specular) * radiance;
+    }
+
+    return color + material.emissive;
+}
+
+/// Layout of a light in the lights uniform buffer of lights.wgsl. `vector` holds the direction of
+/// directional lights and the position of point lights.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct LightData {
+    pub kind: u32,
+    pub intensity: f32,
+    pub shadows: u32,
+    pub occlusion: u32,
+    pub vector: [f32; 4],
+    pub color: [f32; 4],
+}
+
+impl LightData {
+    pub fn new(light: &Light) -> Self {
+        let (kind, vector) = match light.kind {
+            LightKind::Directional { direction } => (LIGHT_DIRECTIONAL, direction),
+            LightKind::Point { position } => (LIGHT_POINT, position),
+        };
+
+        return Self {
+            kind,
+            intensity: light.intensity,
+            shadows: light.shadows as u32,
+            occlusion: light.occlusion as u32,
+            vector: vector.extend(0.0).to_array(),
+            color: light.color.extend(1.0).to_array(),
+        };
+    }
+}
+
+/// Layout of the lights uniform buffer in lights.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct LightUniform {
+    pub light_count: u32,
+    pub _padding: [u32; 3],
+    pub ambient: [f32; 4],
+    pub shadows_enabled: u32,
+    pub shadow_steps: u32,
+    pub shadow_hardness: f32,
+    pub shadow_strength: f32,
+    pub occlusion_enabled: u32,
+    pub occlusion_samples: u32,
+    pub occlusion_step: f32,
+    pub occlusion_strength: f32,
+    pub lights: [LightData; MAX_LIGHTS],
+}
+
+impl LightUniform {
+    pub fn new(ambient: Vec3, shadows: &ShadowSettings, occlusion: &OcclusionSettings, lights: &[LightData]) -> Self {
+        let mut uniform = Self::zeroed();
+        uniform.light_count = lights.len() as u32;
+        uniform.ambient = ambient.extend(1.0).to_array();
+        uniform.shadows_enabled = shadows.enabled as u32;
+        uniform.shadow_steps = shadows.steps;
+        uniform.shadow_hardness = shadows.hardness;
+        uniform.shadow_strength = shadows.strength;
+        uniform.occlusion_enabled = occlusion.enabled as u32;
+        uniform.occlusion_samples = occlusion.samples;
+        uniform.occlusion_step = occlusion.step;
+        uniform.occlusion_strength = occlusion.strength;
+        uniform.lights[..lights.len()].copy_from_slice(lights);
+
+        return uniform;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use glam::Vec3;
+
+    use crate::scene::{
+        Scene,
+        SceneError
+    };
+
+    use super::{
+        Light,
+        LightData,
+        LightUniform,
+        LIGHT_DIRECTIONAL,
+        LIGHT_POINT,
+        MAX_LIGHTS
+    };
+
+    #[test]
+    fn zero_directions_give_dark_lights() {
+        let light = Light::directional(Vec3::ZERO, Vec3::ONE, 1.0);
+        let data = LightData::new(&light);
+
+        assert_eq!(data.vector, [0.0; 4]);
+        assert!(light.incident(Vec3::ONE).0.is_finite());
+
+        let bulb = Light::point(Vec3::ONE, Vec3::ONE, 1.0);
+        assert_eq!(bulb.incident(Vec3::ONE).0, Vec3::ZERO, "No direction at the light itself");
+    }
+
+    #[test]
+    fn lights_pack_into_the_wgsl_layout() {
+        // Sizes of `Light` and `Lights` in lights.wgsl
+        assert_eq!(size_of::<LightData>(), 48);
+        assert_eq!(size_of::<LightUniform>(), 64 + MAX_LIGHTS * 48);
+
+        let sun = LightData::new(&Light::directional(Vec3::new(0.0, 0.0, -2.0), Vec3::new(1.0, 0.5, 0.25), 2.0).with_occlusion(true));
+
+        assert_eq!(sun, LightData {
+            kind: LIGHT_DIRECTIONAL,
+            intensity: 2.0,
+            shadows: 1,
+            occlusion: 1,
+            vector: [0.0, 0.0, -1.0, 0.0],
+            color: [1.0, 0.5, 0.25, 1.0],
+        });
+
+        let lamp = LightData::new(&Light::point(Vec3::new(1.0, 2.0, 3.0), Vec3::ONE, 0.5).with_shadows(false));
+
+        assert_eq!((lamp.kind, lamp.shadows, lamp.vector), (LIGHT_POINT, 0, [1.0, 2.0, 3.0, 0.0]));
+    }
+
+    #[test]
+    fn scenes_pack_their_lights_and_settings() {
+        let mut scene = Scene::showcase();
+        scene.shadows.steps = 12;
+        scene.occlusion.enabled = false;
+
+        let uniform = scene.light_uniform().unwrap();
+
+        assert_eq!(uniform.light_count, 3);
+        assert_eq!(uniform.ambient, [0.1, 0.1, 0.1, 1.0]);
+        assert_eq!((uniform.shadows_enabled, uniform.shadow_steps), (1, 12));
+        assert_eq!(uniform.occlusion_enabled, 0);
+        assert_eq!(uniform.lights[2], LightData::new(&scene.lights[2]));
+        assert_eq!(uniform.lights[3], LightData { kind: 0, intensity: 0.0, shadows: 0, occlusion: 0, vector: [0.0; 4], color: [0.0; 4] });
+
+        scene.lights = vec![Light::point(Vec3::ZERO, Vec3::ONE, 1.0); MAX_LIGHTS + 1];
+
+        assert!(matches!(scene.light_uniform(), Err(SceneError::TooManyLights(count)) if count == MAX_LIGHTS + 1));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,152 @@
+use bytemuck::{
+    Pod,
+    Zeroable
+};
+
+use glam::Vec3;
+
+use crate::scene::evaluate::SceneSample;
+
+/// Maximum number of materials a scene can hold on the GPU.
+pub const MAX_MATERIALS: usize = 16;
+
+/// Surface response of the primitives referencing it by index in `Scene::materials`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    pub albedo: Vec3,
+    /// From 0, mirror-like, to 1, fully diffuse highlights.
+    pub roughness: f32,
+    /// From 0, dielectric, to 1, metal tinting its reflections with the albedo.
+    pub metallic: f32,
+    /// Light emitted by the surface, added after shading.
+    pub emissive: Vec3,
+}
+
+impl Material {
+    pub fn new() -> Self {
+        return Self {
+            albedo: Vec3::splat(0.8),
+            roughness: 0.5,
+            metallic: 0.0,
+            emissive: Vec3::ZERO,
+        };
+    }
+
+    pub fn mix(&self, other: &Material, t: f32) -> Material {
+        return Material {
+            albedo: self.albedo.lerp(other.albedo, t),
+            roughness: self.roughness + (other.roughness - self.roughness) * t,
+            metallic: self.metallic + (other.metallic - self.metallic) * t,
+            emissive: self.emissive.lerp(other.emissive, t),
+        };
+    }
+
+    /// Material of a surface sample, blending the two materials of smooth operations. Unknown indices
+    /// fall back to the default material.
+    pub fn resolve(materials: &[Material], sample: &SceneSample) -> Material {
+        let get = |index: u32| materials.get(index as usize).copied().unwrap_or_else(Material::new);
+
+        return get(sample.material).mix(&get(sample.blend_material), sample.blend);
+    }
+}
+
+/// Layout of a material in the materials uniform buffer of lighting.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct MaterialData {
+    /// Albedo, then roughness.
+    pub albedo_roughness: [f32; 4],
+    /// Emissive color, then metallic.
+    pub emissive_metallic: [f32; 4],
+}
+
+impl MaterialData {
+    pub fn new(material: &Material) -> Self {
+        return Self {
+            albedo_roughness: material.albedo.extend(material.roughness).to_array(),
+            emissive_metallic: material.emissive.extend(material.metallic).to_array(),
+        };
+    }
+}
+
+/// Layout of the materials uniform buffer in lighting.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct MaterialUniform {
+    pub materials: [MaterialData; MAX_MATERIALS],
+}
+
+impl MaterialUniform {
+    pub fn new(materials: &[MaterialData]) -> Self {
+        let mut uniform = Self::zeroed();
+        uniform.materials[..materials.len()].copy_from_slice(materials);
+
+        return uniform;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use glam::Vec3;
+
+    use crate::scene::{
+        evaluate::SceneSample,
+        Scene,
+        SceneError,
+        SceneNode
+    };
+
+    use super::{
+        Material,
+        MaterialData,
+        MaterialUniform,
+        MAX_MATERIALS
+    };
+
+    #[test]
+    fn materials_pack_into_the_wgsl_layout() {
+        // Sizes of `Material` and `Materials` in lighting.wgsl
+        assert_eq!(size_of::<MaterialData>(), 32);
+        assert_eq!(size_of::<MaterialUniform>(), MAX_MATERIALS * 32);
+
+        let gold = Material { albedo: Vec3::new(1.0, 0.75, 0.25), roughness: 0.25, metallic: 1.0, emissive: Vec3::new(0.5, 0.0, 0.0) };
+
+        assert_eq!(MaterialData::new(&gold), MaterialData {
+            albedo_roughness: [1.0, 0.75, 0.25, 0.25],
+            emissive_metallic: [0.5, 0.0, 0.0, 1.0],
+        });
+    }
+
+    #[test]
+    fn scenes_check_their_material_references() {
+        let mut scene = Scene::showcase();
+        let uniform = scene.material_uniform().unwrap();
+
+        assert_eq!(uniform.materials[4], MaterialData::new(&scene.materials[4]));
+        assert_eq!(uniform.materials[scene.materials.len()], MaterialData { albedo_roughness: [0.0; 4], emissive_metallic: [0.0; 4] });
+
+        scene.root = SceneNode::sphere(1.0).with_material(6);
+
+        assert!(matches!(scene.material_uniform(), Err(SceneError::UnknownMaterial(6))));
+
+        scene.materials = vec![Material::new(); MAX_MATERIALS + 1];
+
+        assert!(matches!(scene.material_uniform(), Err(SceneError::TooManyMaterials(count)) if count == MAX_MATERIALS + 1));
+    }
+
+    #[test]
+    fn blended_samples_mix_their_materials() {
+        let materials = [Material::new(), Material { albedo: Vec3::ZERO, roughness: 1.0, ..Material::new() }];
+        let sample = SceneSample { distance: 0.0, material: 0, blend_material: 1, blend: 0.25 };
+
+        let mixed = Material::resolve(&materials, &sample);
+
+        assert!((mixed.albedo - Vec3::splat(0.6)).length() < 1e-6);
+        assert!((mixed.roughness - 0.625).abs() < 1e-6);
+
+        // Unknown indices read as the default material
+        assert_eq!(Material::resolve(&materials, &SceneSample::new(0.0, 9)), Material::new());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,534 @@
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering
+};
+
+use bytemuck::{
+    Pod,
+    Zeroable
+};
+
+use glam::{
+    IVec3,
+    UVec3,
+    Vec3
+};
+
+pub mod brickmap;
+pub mod magicavoxel;
+pub mod mesher;
+pub mod octree;
+pub mod save;
+pub mod terrain;
+pub mod world;
+
+/// Palette index of empty voxels.
+pub const EMPTY: u8 = 0;
+
+// Palette indices of the materials used by the generated volumes
+pub const GRASS: u8 = 1;
+pub const DIRT: u8 = 2;
+pub const STONE: u8 = 3;
+pub const SAND: u8 = 4;
+pub const WATER: u8 = 5;
+pub const WOOD: u8 = 6;
+pub const LEAVES: u8 = 7;
+pub const SNOW: u8 = 8;
+
+/// Largest number of voxels a volume holds, 1 GiB of palette indices. It also keeps voxel indices
+/// within `u32`.
+pub const MAX_VOLUME_VOXELS: usize = 1 << 30;
+
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(1);
+
+/// A fresh revision number, never handed out twice during a run.
+fn next_revision() -> u64 {
+    return NEXT_REVISION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// RGBA colors of the 256 palette indices, index 0 is never drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub colors: [[u8; 4]; 256],
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        let mut colors = [[0u8; 4]; 256];
+
+        colors[GRASS as usize] = [86, 152, 60, 255];
+        colors[DIRT as usize] = [121, 85, 58, 255];
+        colors[STONE as usize] = [128, 128, 132, 255];
+        colors[SAND as usize] = [219, 201, 142, 255];
+        colors[WATER as usize] = [52, 104, 186, 255];
+        colors[WOOD as usize] = [102, 72, 40, 255];
+        colors[LEAVES as usize] = [58, 118, 46, 255];
+        colors[SNOW as usize] = [236, 240, 244, 255];
+
+        // The remaining indices get a hue ramp so arbitrary volumes stay readable
+        for (index, color) in colors.iter_mut().enumerate().skip(SNOW as usize + 1) {
+            let hue = (index as f32 / 256.0) * 6.0;
+            let channel = |offset: f32| ((((hue + offset) % 6.0) - 3.0).abs() - 1.0).clamp(0.0, 1.0);
+
+            *color = [(channel(0.0) * 255.0) as u8, (channel(4.0) * 255.0) as u8, (channel(2.0) * 255.0) as u8, 255];
+        }
+
+        return Self {
+            colors,
+        };
+    }
+}
+
+/// First voxel hit by a ray, with the normal of the face it entered through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoxelHit {
+    pub voxel: UVec3,
+    pub value: u8,
+    pub normal: IVec3,
+    /// World space distance along the ray.
+    pub distance: f32,
+}
+
+/// Dense grid of palette indices, placed in the world with its minimum corner at `origin`.
+#[derive(Clone, Debug)]
+pub struct VoxelVolume {
+    size: UVec3,
+    voxels: Vec<u8>,
+
+    pub palette: Palette,
+    pub origin: Vec3,
+    pub voxel_size: f32,
+
+    revision: u64,
+}
+
+/// Volumes are equal when they hold the same voxels and colors, wherever they are placed and however
+/// they were edited to get there.
+impl PartialEq for VoxelVolume {
+    fn eq(&self, other: &Self) -> bool {
+        return self.size == other.size && self.palette == other.palette && self.voxels == other.voxels;
+    }
+}
+
+impl VoxelVolume {
+    /// Panics when the volume would hold more than `MAX_VOLUME_VOXELS`, see `try_new` for sizes read
+    /// from untrusted input.
+    pub fn new(size: UVec3) -> Self {
+        return Self::try_new(size).unwrap_or_else(|| panic!("Volume of {} voxels holds more than {} voxels", size, MAX_VOLUME_VOXELS));
+    }
+
+    /// An empty volume, or `None` when it would hold more than `MAX_VOLUME_VOXELS`.
+    pub fn try_new(size: UVec3) -> Option<Self> {
+        return Some(Self {
+            size,
+            voxels: vec![EMPTY; Self::voxel_count(size)?],
+
+            palette: Palette::new(),
+            origin: Vec3::ZERO,
+            voxel_size: 1.0,
+
+            revision: next_revision(),
+        });
+    }
+
+    /// Number of voxels in a volume of `size`, or `None` above `MAX_VOLUME_VOXELS`.
+    pub fn voxel_count(size: UVec3) -> Option<usize> {
+        return (size.x as usize).checked_mul(size.y as usize)?.checked_mul(size.z as usize).filter(|&count| count <= MAX_VOLUME_VOXELS);
+    }
+
+    /// A small landscape with a hill, a pond, a stone arch and a tree, used by the headless mode and
+    /// the tests.
+    pub fn demo() -> Self {
+        let size = UVec3::splat(32);
+        let mut volume = Self::new(size);
+
+        volume.voxel_size = 0.125;
+        volume.origin = Vec3::new(-2.0, -1.0, -2.0);
+
+        for x in 0..size.x {
+            for y in 0..size.y {
+                let (fx, fy) = (x as f32, y as f32);
+                let height = (6.0 + 3.0 * (fx * 0.2).sin() * (fy * 0.15).cos() + 4.0 * (-((fx - 22.0).powi(2) + (fy - 22.0).powi(2)) / 40.0).exp()) as u32;
+
+                for z in 0..height.min(size.z) {
+                    let value = if z + 1 == height { GRASS } else if z + 3 >= height { DIRT } else { STONE };
+
+                    volume.set(UVec3::new(x, y, z), value);
+                }
+
+                // Flood the low ground
+                for z in height..5 {
+                    volume.set(UVec3::new(x, y, z), WATER);
+                }
+            }
+        }
+
+        // Stone arch
+        for x in 4..14 {
+            for z in 8..16 {
+                let (dx, dz) = (x as f32 - 8.5, z as f32 - 8.0);
+                let radius = (dx * dx + dz * dz).sqrt();
+
+                if (3.0..5.0).contains(&radius) {
+                    for y in 10..13 {
+                        volume.set(UVec3::new(x, y, z), STONE);
+                    }
+                }
+            }
+        }
+
+        // Tree on the hill
+        for z in 10..17 {
+            volume.set(UVec3::new(22, 22, z), WOOD);
+        }
+
+        for x in 19..26u32 {
+            for y in 19..26u32 {
+                for z in 15..21u32 {
+                    let offset = Vec3::new(x as f32 - 22.0, y as f32 - 22.0, z as f32 - 17.5);
+
+                    if offset.length() < 3.2 && volume.get(UVec3::new(x, y, z)) == EMPTY {
+                        volume.set(UVec3::new(x, y, z), LEAVES);
+                    }
+                }
+            }
+        }
+
+        return volume;
+    }
+
+    pub fn size(&self) -> UVec3 {
+        return self.size;
+    }
+
+    /// Voxels in X, then Y, then Z order, the layout of the 3D texture.
+    pub fn voxels(&self) -> &[u8] {
+        return &self.voxels;
+    }
+
+    /// Changes whenever the voxels do, renderers compare it to skip re-uploading unchanged volumes.
+    pub fn revision(&self) -> u64 {
+        return self.revision;
+    }
+
+    pub fn contains(&self, voxel: IVec3) -> bool {
+        return voxel.cmpge(IVec3::ZERO).all() && voxel.cmplt(self.size.as_ivec3()).all();
+    }
+
+    fn index(&self, voxel: UVec3) -> usize {
+        return (voxel.x + self.size.x * (voxel.y + self.size.y * voxel.z)) as usize;
+    }
+
+    pub fn get(&self, voxel: UVec3) -> u8 {
+        return self.voxels[self.index(voxel)];
+    }
+
+    pub fn set(&mut self, voxel: UVec3, value: u8) {
+        let index = self.index(voxel);
+
+        self.voxels[index] = value;
+        self.revision = next_revision();
+    }
+
+    /// Walks the voxels along the ray with the Amanatides–Woo DDA and returns the first non empty
+    /// one. CPU counterpart of `trace_volume` in voxel.wgsl.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_steps: u32) -> Option<VoxelHit> {
+        // Grid space, where voxels are unit cubes starting at zero
+        let o = (origin - self.origin) / self.voxel_size;
+        let d = Vec3::select(direction.abs().cmplt(Vec3::splat(1e-8)), Vec3::splat(1e-8), direction);
+        let size = self.size.as_vec3();
+
+        let t1 = -o / d;
+        let t2 = (size - o) / d;
+        let t_near = t1.min(t2);
+        let t_far = t1.max(t2);
+
+        let t_enter = t_near.max_element();
+        let t_exit = t_far.min_element();
+
+        if t_enter > t_exit || t_exit < 0.0 {
+            return None;
+        }
+
+        let step = d.signum().as_ivec3();
+        let t_delta = d.recip().abs();
+
+        let mut t = t_enter.max(0.0);
+        let mut voxel = (o + d * t).floor().as_ivec3().clamp(IVec3::ZERO, self.size.as_ivec3() - 1);
+        let mut normal = entry_normal(t_near, step);
+
+        let boundary = (voxel + step.max(IVec3::ZERO)).as_vec3();
+        let mut t_max = (boundary - o) / d;
+
+        for _ in 0..max_steps {
+            let value = self.get(voxel.as_uvec3());
+
+            if value != EMPTY {
+                return Some(VoxelHit {
+                    voxel: voxel.as_uvec3(),
+                    value,
+                    normal,
+                    distance: t * self.voxel_size,
+                });
+            }
+
+            let axis = if t_max.x < t_max.y && t_max.x < t_max.z { 0 } else if t_max.y < t_max.z { 1 } else { 2 };
+
+            t = t_max[axis];
+            t_max[axis] += t_delta[axis];
+            voxel[axis] += step[axis];
+
+            normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+
+            if !self.contains(voxel) {
+                return None;
+            }
+        }
+
+        return None;
+    }
+}
+
+/// Normal of the bounding box face a ray enters through, given its slab entry distances.
+fn entry_normal(t_near: Vec3, step: IVec3) -> IVec3 {
+    let axis = if t_near.x > t_near.y && t_near.x > t_near.z { 0 } else if t_near.y > t_near.z { 1 } else { 2 };
+
+    let mut normal = IVec3::ZERO;
+    normal[axis] = -step[axis];
+
+    return normal;
+}
+
+/// Layout of the volume uniform buffer in voxel.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct VolumeUniform {
+    pub origin: [f32; 3],
+    pub voxel_size: f32,
+    pub size: [u32; 3],
+    pub _padding: u32,
+}
+
+impl VolumeUniform {
+    pub fn new(volume: &VoxelVolume) -> Self {
+        return Self {
+            origin: volume.origin.to_array(),
+            voxel_size: volume.voxel_size,
+            size: volume.size.to_array(),
+            _padding: 0,
+        };
+    }
+}
+
+/// Layout of the palette uniform buffer in voxel.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct PaletteUniform {
+    pub colors: [[f32; 4]; 256],
+}
+
+impl PaletteUniform {
+    pub fn new(palette: &Palette) -> Self {
+        let mut uniform = Self::zeroed();
+
+        for (color, source) in uniform.colors.iter_mut().zip(palette.colors.iter()) {
+            *color = source.map(|channel| channel as f32 / 255.0);
+        }
+
+        return uniform;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{
+        IVec3,
+        UVec3,
+        Vec3
+    };
+
+    use crate::{
+        no_adapter,
+        renderer::{
+            pipeline::VoxelTraversal,
+            probe::{
+                self,
+                ProbeTexture
+            }
+        }
+    };
+
+    use super::{
+        VolumeUniform,
+        VoxelHit,
+        VoxelVolume,
+        MAX_VOLUME_VOXELS,
+        STONE
+    };
+
+    /// The probe writes the hit voxel and palette index of each ray, -1 for misses.
+    const RAY_COUNT: usize = 4096;
+
+    const PROBE_SHADER: &str = r#"
+@group(0)
+@binding(0)
+var<storage, read> origins: array<vec4<f32>>;
+
+@group(0)
+@binding(1)
+var<storage, read> directions: array<vec4<f32>>;
+
+@group(0)
+@binding(2)
+var<storage, read_write> hits: array<vec4<f32>>;
+
+@compute
+@workgroup_size(64)
+fn probe(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= arrayLength(&origins)) {
+        return;
+    }
+
+    let hit = trace_volume(origins[id.x].xyz, directions[id.x].xyz);
+
+    if (!hit.hit) {
+        hits[id.x] = vec4<f32>(-1.0);
+        return;
+    }
+
+    hits[id.x] = vec4<f32>(vec3<f32>(hit.voxel), f32(hit.value));
+}
+"#;
+
+    /// Deterministic rays from around the volume, aimed at random points inside it.
+    pub(super) fn sample_rays(volume: &VoxelVolume) -> (Vec<[f32; 4]>, Vec<[f32; 4]>) {
+        let mut state = 0x9e3779b9u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+
+            return state as f32 / u32::MAX as f32;
+        };
+
+        let extent = volume.size().as_vec3() * volume.voxel_size;
+        let center = volume.origin + extent * 0.5;
+
+        let mut origins = Vec::with_capacity(RAY_COUNT);
+        let mut directions = Vec::with_capacity(RAY_COUNT);
+
+        for _ in 0..RAY_COUNT {
+            let origin = center + Vec3::new(next() - 0.5, next() - 0.5, next() - 0.5) * extent * 3.0;
+            let target = volume.origin + Vec3::new(next(), next(), next()) * extent;
+            let direction = (target - origin).normalize();
+
+            origins.push(origin.extend(1.0).to_array());
+            directions.push(direction.extend(0.0).to_array());
+        }
+
+        return (origins, directions);
+    }
+
+    /// Checks that `raycast` and the GPU traversal hit the same voxels for the sample rays of `volume`.
+    pub(super) fn check_gpu_parity(volume: &VoxelVolume, traversal: VoxelTraversal, uniform: &[u8], textures: &[ProbeTexture], raycast: impl Fn(Vec3, Vec3) -> Option<VoxelHit>) {
+        let (origins, directions) = sample_rays(volume);
+
+        let source = format!("{}\n{}", traversal.source(), PROBE_SHADER);
+
+        let Some(gpu_hits) = probe::run(&source, &[&origins, &directions], uniform, textures) else {
+            no_adapter("voxel parity test");
+            return;
+        };
+
+        let mut hit_count = 0;
+
+        for ((origin, direction), gpu_hit) in origins.iter().zip(directions.iter()).zip(gpu_hits.iter()) {
+            let (origin, direction) = (Vec3::from_slice(origin), Vec3::from_slice(direction));
+
+            let cpu_hit = raycast(origin, direction).map(|hit| [hit.voxel.x as f32, hit.voxel.y as f32, hit.voxel.z as f32, hit.value as f32]);
+
+            hit_count += cpu_hit.is_some() as usize;
+
+            assert_eq!(cpu_hit.unwrap_or([-1.0; 4]), *gpu_hit, "CPU and GPU disagree for ray {} {}", origin, direction);
+        }
+
+        assert!(hit_count > RAY_COUNT / 2, "Rays should mostly hit the volume");
+    }
+
+    #[test]
+    fn raycast_hits_first_voxel_on_the_way() {
+        let mut volume = VoxelVolume::new(UVec3::splat(8));
+        volume.set(UVec3::new(5, 2, 3), STONE);
+        volume.set(UVec3::new(7, 2, 3), STONE);
+
+        let hit = volume.raycast(Vec3::new(-2.0, 2.5, 3.5), Vec3::X, 64).expect("Ray should hit");
+
+        assert_eq!(hit.voxel, UVec3::new(5, 2, 3));
+        assert_eq!(hit.normal, IVec3::new(-1, 0, 0));
+        assert!((hit.distance - 7.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn raycast_misses_empty_space() {
+        let mut volume = VoxelVolume::new(UVec3::splat(8));
+        volume.set(UVec3::new(5, 2, 3), STONE);
+
+        assert!(volume.raycast(Vec3::new(-2.0, 6.5, 3.5), Vec3::X, 64).is_none());
+        assert!(volume.raycast(Vec3::new(-2.0, 2.5, 3.5), -Vec3::X, 64).is_none());
+    }
+
+    #[test]
+    fn raycast_steps_diagonally_inside_the_volume() {
+        let mut volume = VoxelVolume::new(UVec3::splat(8));
+        volume.set(UVec3::new(6, 6, 6), STONE);
+        volume.origin = Vec3::splat(-1.0);
+        volume.voxel_size = 0.25;
+
+        let hit = volume.raycast(Vec3::splat(-0.9), Vec3::ONE.normalize(), 64).expect("Ray should hit");
+
+        assert_eq!(hit.voxel, UVec3::new(6, 6, 6));
+    }
+
+    #[test]
+    fn oversize_volumes_are_rejected() {
+        assert_eq!(VoxelVolume::voxel_count(UVec3::new(4, 5, 6)), Some(120));
+        assert_eq!(VoxelVolume::voxel_count(UVec3::new(1024, 1024, 1024)), Some(MAX_VOLUME_VOXELS));
+
+        // 2^33 voxels, which wraps to 0 in u32
+        assert!(VoxelVolume::try_new(UVec3::new(1 << 11, 1 << 11, 1 << 11)).is_none());
+        assert!(VoxelVolume::try_new(UVec3::splat(u32::MAX)).is_none());
+    }
+
+    #[test]
+    fn volumes_compare_their_voxels_only() {
+        let mut volume = VoxelVolume::new(UVec3::splat(4));
+        let mut edited = VoxelVolume::new(UVec3::splat(4));
+        edited.set(UVec3::ONE, STONE);
+
+        assert_ne!(volume.revision(), edited.revision());
+        assert_ne!(volume, edited);
+
+        volume.set(UVec3::ONE, STONE);
+        assert_eq!(volume, edited);
+
+        volume.palette.colors[STONE as usize] = [0, 0, 0, 255];
+        assert_ne!(volume, edited);
+        assert_ne!(VoxelVolume::new(UVec3::new(4, 4, 2)), VoxelVolume::new(UVec3::new(4, 2, 4)));
+    }
+
+    #[test]
+    fn raycast_matches_gpu() {
+        let volume = VoxelVolume::demo();
+
+        let voxels = ProbeTexture {
+            binding: 5,
+            size: volume.size(),
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::R8Uint,
+            data: volume.voxels(),
+        };
+
+        check_gpu_parity(&volume, VoxelTraversal::Dense, bytemuck::bytes_of(&VolumeUniform::new(&volume)), &[voxels], |origin, direction| volume.raycast(origin, direction, 96));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,642 @@
+use bytemuck::{
+    Pod,
+    Zeroable
+};
+
+use glam::{
+    IVec3,
+    UVec3,
+    Vec3
+};
+
+use crate::voxel::{
+    entry_normal,
+    Palette,
+    VoxelHit,
+    VoxelVolume,
+    EMPTY
+};
+
+/// Voxels per side of a brick.
+pub const BRICK_SIZE: u32 = 8;
+const BRICK_VOXELS: usize = (BRICK_SIZE * BRICK_SIZE * BRICK_SIZE) as usize;
+
+/// Set on grid cells whose brick is filled with a single palette index, held in the low byte, so
+/// solid ground takes no room in the pool.
+pub const UNIFORM: u32 = 0x8000_0000;
+
+/// Bricks per side of a pool layer, the pool grows by whole layers of `POOL_WIDTH²` bricks. Keep in
+/// sync with voxel_brickmap.wgsl.
+pub const POOL_WIDTH: u32 = 64;
+
+/// GPU memory the grid and the brick pool may take together.
+pub const MEMORY_BUDGET: u64 = 256 << 20;
+
+/// Grid cells and pool slots changed since the last upload.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BrickmapChanges {
+    pub cells: Vec<UVec3>,
+    pub slots: Vec<u32>,
+}
+
+/// Two level voxel volume: a coarse grid of cells pointing into a pool of 8³ bricks.
+///
+/// A cell is 0 when its brick is empty, `UNIFORM | value` when it is filled with one palette index,
+/// or the pool slot holding its voxels. Slot 0 is never handed out. The pool is kept in the layout of
+/// the 3D atlas texture it is uploaded to, slot `s` sitting at brick `(s % W, s / W % W, s / W²)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Brickmap {
+    size: UVec3,
+    grid_size: UVec3,
+    grid: Vec<u32>,
+
+    pool: Vec<u8>,
+    pool_layers: u32,
+    brick_counts: Vec<u16>,
+    free_slots: Vec<u32>,
+    next_slot: u32,
+
+    dirty_cells: Vec<bool>,
+    dirty_slots: Vec<bool>,
+    changes: BrickmapChanges,
+
+    pub palette: Palette,
+    pub origin: Vec3,
+    pub voxel_size: f32,
+}
+
+impl Brickmap {
+    pub fn new(size: UVec3) -> Self {
+        let grid_size = (size + BRICK_SIZE - 1) / BRICK_SIZE;
+        let cell_count = (grid_size.x * grid_size.y * grid_size.z) as usize;
+
+        let mut brickmap = Self {
+            size,
+            grid_size,
+            grid: vec![0; cell_count],
+
+            pool: Vec::new(),
+            pool_layers: 0,
+            brick_counts: Vec::new(),
+            free_slots: Vec::new(),
+            next_slot: 1,
+
+            dirty_cells: vec![false; cell_count],
+            dirty_slots: Vec::new(),
+            changes: BrickmapChanges::default(),
+
+            palette: Palette::new(),
+            origin: Vec3::ZERO,
+            voxel_size: 1.0,
+        };
+
+        brickmap.add_pool_layer();
+
+        return brickmap;
+    }
+
+    pub fn from_volume(volume: &VoxelVolume) -> Self {
+        let mut brickmap = Self::new(volume.size());
+        brickmap.sync_with(volume);
+
+        return brickmap;
+    }
+
+    pub fn size(&self) -> UVec3 {
+        return self.size;
+    }
+
+    /// Bricks per side of the grid.
+    pub fn grid_size(&self) -> UVec3 {
+        return self.grid_size;
+    }
+
+    /// Cells in X, then Y, then Z order, the layout of the grid texture.
+    pub fn grid(&self) -> &[u32] {
+        return &self.grid;
+    }
+
+    pub fn cell(&self, brick: UVec3) -> u32 {
+        return self.grid[self.cell_index(brick)];
+    }
+
+    /// Size of the pool atlas in voxels.
+    pub fn pool_size(&self) -> UVec3 {
+        return UVec3::new(POOL_WIDTH, POOL_WIDTH, self.pool_layers) * BRICK_SIZE;
+    }
+
+    /// Voxels of the pool atlas in X, then Y, then Z order.
+    pub fn pool(&self) -> &[u8] {
+        return &self.pool;
+    }
+
+    /// Bricks currently holding voxels.
+    pub fn brick_count(&self) -> usize {
+        return (self.next_slot - 1) as usize - self.free_slots.len();
+    }
+
+    /// Bytes taken on the GPU by the grid and the whole pool capacity.
+    pub fn gpu_memory(&self) -> u64 {
+        return self.grid.len() as u64 * 4 + self.pool.len() as u64;
+    }
+
+    /// Minimum corner of a pool slot in the atlas, in voxels.
+    pub fn slot_origin(slot: u32) -> UVec3 {
+        return UVec3::new(slot % POOL_WIDTH, (slot / POOL_WIDTH) % POOL_WIDTH, slot / (POOL_WIDTH * POOL_WIDTH)) * BRICK_SIZE;
+    }
+
+    /// The 8³ voxels of a pool slot in X, then Y, then Z order, ready for a texture upload.
+    pub fn slot_voxels(&self, slot: u32) -> Vec<u8> {
+        let origin = Self::slot_origin(slot);
+        let mut voxels = Vec::with_capacity(BRICK_VOXELS);
+
+        for z in 0..BRICK_SIZE {
+            for y in 0..BRICK_SIZE {
+                let start = self.atlas_index(origin + UVec3::new(0, y, z));
+
+                voxels.extend_from_slice(&self.pool[start..start + BRICK_SIZE as usize]);
+            }
+        }
+
+        return voxels;
+    }
+
+    /// Hands the changes since the last call over to the renderer uploading them.
+    pub fn take_changes(&mut self) -> BrickmapChanges {
+        for cell in self.changes.cells.iter() {
+            let index = self.cell_index(*cell);
+            self.dirty_cells[index] = false;
+        }
+
+        for slot in self.changes.slots.iter() {
+            self.dirty_slots[*slot as usize] = false;
+        }
+
+        return std::mem::take(&mut self.changes);
+    }
+
+    fn cell_index(&self, cell: UVec3) -> usize {
+        return (cell.x + self.grid_size.x * (cell.y + self.grid_size.y * cell.z)) as usize;
+    }
+
+    fn atlas_index(&self, voxel: UVec3) -> usize {
+        let side = POOL_WIDTH * BRICK_SIZE;
+
+        return (voxel.x + side * (voxel.y + side * voxel.z)) as usize;
+    }
+
+    fn add_pool_layer(&mut self) {
+        let layer_bricks = (POOL_WIDTH * POOL_WIDTH) as usize;
+
+        self.pool_layers += 1;
+        self.pool.resize(self.pool.len() + layer_bricks * BRICK_VOXELS, EMPTY);
+        self.brick_counts.resize(self.brick_counts.len() + layer_bricks, 0);
+        self.dirty_slots.resize(self.dirty_slots.len() + layer_bricks, false);
+
+        assert!(self.gpu_memory() <= MEMORY_BUDGET, "Brickmap of {} voxels exceeds the GPU memory budget", self.size);
+    }
+
+    fn mark_cell(&mut self, cell: UVec3) {
+        let index = self.cell_index(cell);
+
+        if !self.dirty_cells[index] {
+            self.dirty_cells[index] = true;
+            self.changes.cells.push(cell);
+        }
+    }
+
+    fn mark_slot(&mut self, slot: u32) {
+        if !self.dirty_slots[slot as usize] {
+            self.dirty_slots[slot as usize] = true;
+            self.changes.slots.push(slot);
+        }
+    }
+
+    /// A free pool slot filled with `value`, growing the pool when every slot is taken.
+    fn allocate(&mut self, value: u8) -> u32 {
+        let slot = match self.free_slots.pop() {
+            Some(slot) => slot,
+            None => {
+                if self.next_slot as usize == self.brick_counts.len() {
+                    self.add_pool_layer();
+                }
+
+                self.next_slot += 1;
+                self.next_slot - 1
+            },
+        };
+
+        let origin = Self::slot_origin(slot);
+
+        for z in 0..BRICK_SIZE {
+            for y in 0..BRICK_SIZE {
+                let start = self.atlas_index(origin + UVec3::new(0, y, z));
+
+                self.pool[start..start + BRICK_SIZE as usize].fill(value);
+            }
+        }
+
+        self.brick_counts[slot as usize] = if value == EMPTY { 0 } else { BRICK_VOXELS as u16 };
+        self.mark_slot(slot);
+
+        return slot;
+    }
+
+    fn release(&mut self, slot: u32) {
+        self.brick_counts[slot as usize] = 0;
+        self.free_slots.push(slot);
+    }
+
+    pub fn get(&self, voxel: UVec3) -> u8 {
+        let cell = self.grid[self.cell_index(voxel / BRICK_SIZE)];
+
+        if cell == 0 || cell & UNIFORM != 0 {
+            return (cell & 0xff) as u8;
+        }
+
+        return self.pool[self.atlas_index(Self::slot_origin(cell) + voxel % BRICK_SIZE)];
+    }
+
+    pub fn set(&mut self, voxel: UVec3, value: u8) {
+        let brick = voxel / BRICK_SIZE;
+        let cell_index = self.cell_index(brick);
+        let cell = self.grid[cell_index];
+
+        // Empty and uniform bricks only get a slot once they stop being uniform
+        let slot = if cell == 0 || cell & UNIFORM != 0 {
+            if (cell & 0xff) as u8 == value {
+                return;
+            }
+
+            let slot = self.allocate((cell & 0xff) as u8);
+            self.grid[cell_index] = slot;
+            self.mark_cell(brick);
+
+            slot
+        } else {
+            cell
+        };
+
+        let index = self.atlas_index(Self::slot_origin(slot) + voxel % BRICK_SIZE);
+        let previous = self.pool[index];
+
+        if previous == value {
+            return;
+        }
+
+        self.pool[index] = value;
+        self.mark_slot(slot);
+
+        if previous == EMPTY {
+            self.brick_counts[slot as usize] += 1;
+        } else if value == EMPTY {
+            self.brick_counts[slot as usize] -= 1;
+        }
+
+        let count = self.brick_counts[slot as usize] as usize;
+
+        if count == 0 {
+            self.release(slot);
+            self.grid[cell_index] = 0;
+            self.mark_cell(brick);
+        } else if count == BRICK_VOXELS && self.slot_voxels(slot).iter().all(|&voxel| voxel == value) {
+            self.release(slot);
+            self.grid[cell_index] = UNIFORM | value as u32;
+            self.mark_cell(brick);
+        }
+    }
+
+    /// Fills the voxels from `min` up to `max` excluded, bricks covered entirely become uniform
+    /// without touching the pool.
+    pub fn fill(&mut self, min: UVec3, max: UVec3, value: u8) {
+        let max = max.min(self.size);
+
+        for z in min.z / BRICK_SIZE..max.z.div_ceil(BRICK_SIZE) {
+            for y in min.y / BRICK_SIZE..max.y.div_ceil(BRICK_SIZE) {
+                for x in min.x / BRICK_SIZE..max.x.div_ceil(BRICK_SIZE) {
+                    let brick = UVec3::new(x, y, z);
+                    let brick_min = brick * BRICK_SIZE;
+                    let brick_max = brick_min + BRICK_SIZE;
+
+                    if brick_min.cmpge(min).all() && brick_max.cmple(max).all() {
+                        let cell_index = self.cell_index(brick);
+                        let cell = self.grid[cell_index];
+
+                        if cell != 0 && cell & UNIFORM == 0 {
+                            self.release(cell);
+                        }
+
+                        self.grid[cell_index] = if value == EMPTY { 0 } else { UNIFORM | value as u32 };
+                        self.mark_cell(brick);
+
+                        continue;
+                    }
+
+                    let from = brick_min.max(min);
+                    let to = brick_max.min(max);
+
+                    for vz in from.z..to.z {
+                        for vy in from.y..to.y {
+                            for vx in from.x..to.x {
+                                self.set(UVec3::new(vx, vy, vz), value);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Brings the voxels, palette and placement in line with `volume`, of the same size, recording
+    /// only the bricks that actually changed.
+    pub fn sync_with(&mut self, volume: &VoxelVolume) {
+        assert_eq!(self.size, volume.size(), "Brickmap and volume sizes differ");
+
+        let size = volume.size();
+
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let voxel = UVec3::new(x, y, z);
+                    self.set(voxel, volume.get(voxel));
+                }
+            }
+        }
+
+        self.palette = volume.palette.clone();
+        self.origin = volume.origin;
+        self.voxel_size = volume.voxel_size;
+    }
+
+    /// Walks the voxels along the ray like `VoxelVolume::raycast`, leaping over empty bricks in a
+    /// single step. CPU counterpart of `trace_volume` in voxel_brickmap.wgsl.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<VoxelHit> {
+        // Grid space, where voxels are unit cubes starting at zero
+        let o = (origin - self.origin) / self.voxel_size;
+        let d = Vec3::select(direction.abs().cmplt(Vec3::splat(1e-8)), Vec3::splat(1e-8), direction);
+        let size = self.size.as_ivec3();
+
+        let t1 = -o / d;
+        let t2 = (size.as_vec3() - o) / d;
+        let t_near = t1.min(t2);
+        let t_far = t1.max(t2);
+
+        let t_enter = t_near.max_element();
+        let t_exit = t_far.min_element();
+
+        if t_enter > t_exit || t_exit < 0.0 {
+            return None;
+        }
+
+        let step = d.signum().as_ivec3();
+        let t_delta = d.recip().abs();
+        let brick_size = BRICK_SIZE as i32;
+
+        let mut t = t_enter.max(0.0);
+        let mut voxel = (o + d * t).floor().as_ivec3().clamp(IVec3::ZERO, size - 1);
+        let mut normal = entry_normal(t_near, step);
+
+        let max_steps = size.x + size.y + size.z;
+        let mut steps = 0;
+
+        while steps < max_steps {
+            let brick_min = voxel / brick_size * brick_size;
+            let cell = self.grid[self.cell_index(brick_min.as_uvec3() / BRICK_SIZE)];
+
+            if cell & UNIFORM != 0 {
+                return Some(VoxelHit {
+                    voxel: voxel.as_uvec3(),
+                    value: (cell & 0xff) as u8,
+                    normal,
+                    distance: t * self.voxel_size,
+                });
+            }
+
+            if cell == 0 {
+                let boundary = brick_min.as_vec3() + Vec3::select(step.cmpgt(IVec3::ZERO), Vec3::splat(BRICK_SIZE as f32), Vec3::ZERO);
+                let t_max = (boundary - o) / d;
+
+                let axis = if t_max.x < t_max.y && t_max.x < t_max.z { 0 } else if t_max.y < t_max.z { 1 } else { 2 };
+
+                // The exit axis is stepped exactly, the others are clamped so rounding never leaves the brick sideways
+                t = t_max[axis];
+                voxel = (o + d * t).floor().as_ivec3().clamp(brick_min, brick_min + brick_size - 1);
+                voxel[axis] = if step[axis] > 0 { brick_min[axis] + brick_size } else { brick_min[axis] - 1 };
+
+                normal = IVec3::ZERO;
+                normal[axis] = -step[axis];
+
+                steps += 1;
+            } else {
+                let pool_offset = Self::slot_origin(cell).as_ivec3() - brick_min;
+
+                let boundary = (voxel + step.max(IVec3::ZERO)).as_vec3();
+                let mut t_max = (boundary - o) / d;
+
+                while steps < max_steps {
+                    let value = self.pool[self.atlas_index((voxel + pool_offset).as_uvec3())];
+
+                    if value != EMPTY {
+                        return Some(VoxelHit {
+                            voxel: voxel.as_uvec3(),
+                            value,
+                            normal,
+                            distance: t * self.voxel_size,
+                        });
+                    }
+
+                    let axis = if t_max.x < t_max.y && t_max.x < t_max.z { 0 } else if t_max.y < t_max.z { 1 } else { 2 };
+
+                    t = t_max[axis];
+                    t_max[axis] += t_delta[axis];
+                    voxel[axis] += step[axis];
+
+                    normal = IVec3::ZERO;
+                    normal[axis] = -step[axis];
+
+                    steps += 1;
+
+                    if voxel.cmplt(brick_min).any() || voxel.cmpge(brick_min + brick_size).any() {
+                        break;
+                    }
+                }
+            }
+
+            if voxel.cmplt(IVec3::ZERO).any() || voxel.cmpge(size).any() {
+                return None;
+            }
+        }
+
+        return None;
+    }
+}
+
+/// Layout of the brickmap uniform buffer in voxel_brickmap.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct BrickmapUniform {
+    pub origin: [f32; 3],
+    pub voxel_size: f32,
+    pub size: [u32; 3],
+    pub _padding: u32,
+}
+
+impl BrickmapUniform {
+    pub fn new(brickmap: &Brickmap) -> Self {
+        return Self {
+            origin: brickmap.origin.to_array(),
+            voxel_size: brickmap.voxel_size,
+            size: brickmap.size.to_array(),
+            _padding: 0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{
+        UVec3,
+        Vec3
+    };
+
+    use crate::{
+        renderer::{
+            pipeline::VoxelTraversal,
+            probe::ProbeTexture
+        },
+        voxel::{
+            tests::{
+                check_gpu_parity,
+                sample_rays
+            },
+            VoxelVolume,
+            GRASS,
+            STONE
+        }
+    };
+
+    use super::{
+        Brickmap,
+        BrickmapUniform,
+        MEMORY_BUDGET,
+        UNIFORM
+    };
+
+    #[test]
+    fn brickmap_matches_volume() {
+        let volume = VoxelVolume::demo();
+        let brickmap = Brickmap::from_volume(&volume);
+
+        assert_eq!(brickmap.grid_size(), UVec3::splat(4));
+        assert!(brickmap.brick_count() < 64, "Empty bricks should stay out of the pool");
+
+        for x in 0..32 {
+            for y in 0..32 {
+                for z in 0..32 {
+                    let voxel = UVec3::new(x, y, z);
+
+                    assert_eq!(brickmap.get(voxel), volume.get(voxel), "Brickmap and volume disagree at {}", voxel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn edits_only_change_their_bricks() {
+        let mut brickmap = Brickmap::new(UVec3::splat(16));
+        brickmap.take_changes();
+
+        brickmap.set(UVec3::new(9, 1, 2), STONE);
+        brickmap.set(UVec3::new(10, 1, 2), STONE);
+
+        let changes = brickmap.take_changes();
+        assert_eq!(changes.cells, vec![UVec3::new(1, 0, 0)]);
+        assert_eq!(changes.slots.len(), 1);
+        assert_eq!(brickmap.brick_count(), 1);
+
+        brickmap.set(UVec3::new(9, 1, 2), GRASS);
+
+        let changes = brickmap.take_changes();
+        assert!(changes.cells.is_empty());
+        assert_eq!(changes.slots.len(), 1);
+
+        // Emptied bricks go back to the pool
+        brickmap.set(UVec3::new(9, 1, 2), 0);
+        brickmap.set(UVec3::new(10, 1, 2), 0);
+
+        assert_eq!(brickmap.cell(UVec3::new(1, 0, 0)), 0);
+        assert_eq!(brickmap.brick_count(), 0);
+
+        // And so do bricks filled with a single value
+        brickmap.fill(UVec3::ZERO, UVec3::new(8, 8, 7), STONE);
+        assert_eq!(brickmap.brick_count(), 1);
+
+        brickmap.fill(UVec3::new(0, 0, 7), UVec3::splat(8), STONE);
+        assert_eq!(brickmap.cell(UVec3::ZERO), UNIFORM | STONE as u32);
+        assert_eq!(brickmap.brick_count(), 0);
+    }
+
+    #[test]
+    fn large_world_fits_memory_budget() {
+        let size = UVec3::new(4096, 4096, 256);
+        let mut brickmap = Brickmap::new(size);
+
+        brickmap.fill(UVec3::ZERO, UVec3::new(size.x, size.y, 40), STONE);
+
+        // Rolling hills make for a surface crossing bricks everywhere
+        let height = |x: u32, y: u32| 40 + (12.0 + 10.0 * (x as f32 * 0.01).sin() * (y as f32 * 0.013).cos()) as u32;
+
+        for x in 0..size.x {
+            for y in 0..size.y {
+                brickmap.set(UVec3::new(x, y, height(x, y)), GRASS);
+            }
+        }
+
+        assert!(brickmap.gpu_memory() <= MEMORY_BUDGET);
+
+        let hit = brickmap.raycast(Vec3::new(1234.5, 2345.5, 300.0), -Vec3::Z).expect("Ray should hit the ground");
+
+        assert_eq!(hit.voxel, UVec3::new(1234, 2345, height(1234, 2345)));
+        assert_eq!(hit.value, GRASS);
+    }
+
+    #[test]
+    fn raycast_matches_dense_traversal() {
+        let volume = VoxelVolume::demo();
+        let brickmap = Brickmap::from_volume(&volume);
+        let (origins, directions) = sample_rays(&volume);
+
+        for (origin, direction) in origins.iter().zip(directions.iter()) {
+            let (origin, direction) = (Vec3::from_slice(origin), Vec3::from_slice(direction));
+
+            let dense = volume.raycast(origin, direction, 96).map(|hit| (hit.voxel, hit.value));
+            let bricks = brickmap.raycast(origin, direction).map(|hit| (hit.voxel, hit.value));
+
+            assert_eq!(bricks, dense, "Brickmap and dense traversal disagree for ray {} {}", origin, direction);
+        }
+    }
+
+    #[test]
+    fn raycast_matches_gpu() {
+        let volume = VoxelVolume::demo();
+        let brickmap = Brickmap::from_volume(&volume);
+
+        let grid = ProbeTexture {
+            binding: 5,
+            size: brickmap.grid_size(),
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::R32Uint,
+            data: bytemuck::cast_slice(brickmap.grid()),
+        };
+
+        let pool = ProbeTexture {
+            binding: 7,
+            size: brickmap.pool_size(),
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::R8Uint,
+            data: brickmap.pool(),
+        };
+
+        check_gpu_parity(&volume, VoxelTraversal::Brickmap, bytemuck::bytes_of(&BrickmapUniform::new(&brickmap)), &[grid, pool], |origin, direction| brickmap.raycast(origin, direction));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,1072 @@
+use std::{
+    collections::BTreeMap,
+    fmt
+};
+
+use glam::{
+    I64Vec3,
+    IVec3,
+    UVec3
+};
+
+use crate::voxel::{
+    Palette,
+    VoxelVolume,
+    EMPTY
+};
+
+/// Four character identifier of a chunk.
+pub type ChunkId = [u8; 4];
+
+/// Largest model side MagicaVoxel accepts.
+pub const MAX_MODEL_SIZE: u32 = 256;
+
+/// Node ids of a scene graph, the root transform is always node 0.
+pub type NodeId = u32;
+
+/// Furthest the transforms of a scene graph may move a model along an axis, which keeps every
+/// placement within `i32`.
+pub const MAX_SCENE_DISTANCE: i64 = 1 << 24;
+
+/// Rotation byte of the identity matrix, the first row picks X and the second Y.
+pub const IDENTITY_ROTATION: u8 = 0b0000_0100;
+
+/// File format version written by `VoxFile::write`, the one of MagicaVoxel 0.99.
+const VERSION: i32 = 200;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VoxError {
+    /// The file does not start with the `VOX ` magic.
+    NotVox,
+    /// A chunk extends past the end of its parent or of the file.
+    Truncated { chunk: ChunkId, offset: usize },
+    /// A chunk is complete but its content makes no sense.
+    Malformed { chunk: ChunkId, offset: usize, reason: &'static str },
+    /// The scene graph draws no model, so there is no volume to build.
+    EmptyScene,
+    /// The models are spread over more voxels than a volume holds, `extent` is the size of their
+    /// bounds.
+    TooLarge { extent: I64Vec3 },
+}
+
+impl fmt::Display for VoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            VoxError::NotVox => write!(f, "not a MagicaVoxel file"),
+            VoxError::Truncated { chunk, offset } => write!(f, "{} chunk at byte {} is truncated", String::from_utf8_lossy(chunk), offset),
+            VoxError::Malformed { chunk, offset, reason } => write!(f, "{} chunk at byte {}: {}", String::from_utf8_lossy(chunk), offset, reason),
+            VoxError::EmptyScene => write!(f, "scene draws no models"),
+            VoxError::TooLarge { extent } => write!(f, "scene spans {}x{}x{} voxels, more than a volume holds", extent.x, extent.y, extent.z),
+        };
+    }
+}
+
+impl std::error::Error for VoxError {}
+
+/// Node of the scene graph placing the models.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VoxNode {
+    /// Places its child, `rotation` packs a signed permutation matrix the way MagicaVoxel does.
+    Transform { name: Option<String>, child: NodeId, layer: i32, rotation: u8, translation: IVec3 },
+    Group { children: Vec<NodeId> },
+    /// Draws a model. Animated shapes list one model per frame, only the first one is used.
+    Shape { models: Vec<u32> },
+}
+
+/// Model placed in the scene, its voxel `v` lands at `rotation * (v - size / 2) + translation`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoxInstance {
+    pub model: usize,
+    /// Rows of the rotation matrix.
+    pub rotation: [IVec3; 3],
+    pub translation: IVec3,
+}
+
+impl VoxInstance {
+    fn apply(&self, point: IVec3) -> IVec3 {
+        return IVec3::new(self.rotation[0].dot(point), self.rotation[1].dot(point), self.rotation[2].dot(point)) + self.translation;
+    }
+
+    /// Scene position of a voxel of a model of the given size.
+    pub fn place(&self, voxel: UVec3, size: UVec3) -> IVec3 {
+        return self.apply(voxel.as_ivec3() - (size / 2).as_ivec3());
+    }
+
+    /// Applies `self` after `child`.
+    fn then(&self, child: &VoxInstance) -> VoxInstance {
+        let columns = [IVec3::X, IVec3::Y, IVec3::Z].map(|axis| {
+            let column = IVec3::new(child.rotation[0].dot(axis), child.rotation[1].dot(axis), child.rotation[2].dot(axis));
+
+            return IVec3::new(self.rotation[0].dot(column), self.rotation[1].dot(column), self.rotation[2].dot(column));
+        });
+
+        return VoxInstance {
+            model: child.model,
+            rotation: [
+                IVec3::new(columns[0].x, columns[1].x, columns[2].x),
+                IVec3::new(columns[0].y, columns[1].y, columns[2].y),
+                IVec3::new(columns[0].z, columns[1].z, columns[2].z),
+            ],
+            translation: self.apply(child.translation),
+        };
+    }
+}
+
+/// Decodes the rotation byte of a transform: bits 0-1 and 2-3 hold the column of the non zero entry
+/// of the first and second rows, bits 4, 5 and 6 make the entries of the three rows negative.
+/// Returns `None` when both rows point at the same column.
+pub fn decode_rotation(rotation: u8) -> Option<[IVec3; 3]> {
+    let first = (rotation & 3) as usize;
+    let second = ((rotation >> 2) & 3) as usize;
+
+    if first > 2 || second > 2 || first == second {
+        return None;
+    }
+
+    let third = 3 - first - second;
+    let axes = [IVec3::X, IVec3::Y, IVec3::Z];
+    let sign = |bit: u8| if rotation & (1 << bit) != 0 { -1 } else { 1 };
+
+    return Some([axes[first] * sign(4), axes[second] * sign(5), axes[third] * sign(6)]);
+}
+
+/// Contents of a `.vox` file: its models, the palette they share and the scene graph placing them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxFile {
+    /// One volume per SIZE and XYZI pair, in file order, all carrying `palette`.
+    pub models: Vec<VoxelVolume>,
+    /// Files without an RGBA chunk keep the engine palette.
+    pub palette: Palette,
+    /// Empty for files older than the scene graph, which draw every model at the origin.
+    pub nodes: BTreeMap<NodeId, VoxNode>,
+}
+
+impl VoxFile {
+    /// Splits a volume into models of at most `MAX_MODEL_SIZE³` voxels, each placed by its own
+    /// transform under a single group so the scene puts volume voxel `v` at scene position `v`.
+    pub fn from_volume(volume: &VoxelVolume) -> Self {
+        let size = volume.size();
+        let counts = (size + MAX_MODEL_SIZE - 1) / MAX_MODEL_SIZE;
+
+        let mut models = Vec::new();
+        let mut nodes = BTreeMap::new();
+        let mut children = Vec::new();
+
+        for z in 0..counts.z {
+            for y in 0..counts.y {
+                for x in 0..counts.x {
+                    let min = UVec3::new(x, y, z) * MAX_MODEL_SIZE;
+                    let model_size = (size - min).min(UVec3::splat(MAX_MODEL_SIZE));
+
+                    let mut model = VoxelVolume::new(model_size);
+                    model.palette = volume.palette.clone();
+
+                    for model_z in 0..model_size.z {
+                        for model_y in 0..model_size.y {
+                            let row = volume.index(min + UVec3::new(0, model_y, model_z));
+                            let model_row = model.index(UVec3::new(0, model_y, model_z));
+
+                            model.voxels[model_row..model_row + model_size.x as usize].copy_from_slice(&volume.voxels[row..row + model_size.x as usize]);
+                        }
+                    }
+
+                    let transform = 2 + 2 * models.len() as NodeId;
+
+                    nodes.insert(transform, VoxNode::Transform {
+                        name: None,
+                        child: transform + 1,
+                        layer: 0,
+                        rotation: IDENTITY_ROTATION,
+                        translation: (min + model_size / 2).as_ivec3(),
+                    });
+
+                    nodes.insert(transform + 1, VoxNode::Shape {
+                        models: vec![models.len() as u32],
+                    });
+
+                    children.push(transform);
+                    models.push(model);
+                }
+            }
+        }
+
+        nodes.insert(0, VoxNode::Transform {
+            name: None,
+            child: 1,
+            layer: -1,
+            rotation: IDENTITY_ROTATION,
+            translation: IVec3::ZERO,
+        });
+
+        nodes.insert(1, VoxNode::Group {
+            children,
+        });
+
+        return Self {
+            models,
+            palette: volume.palette.clone(),
+            nodes,
+        };
+    }
+
+    /// Encodes the file as MagicaVoxel writes it, with a single layer holding every model.
+    pub fn write(&self) -> Vec<u8> {
+        let mut children = Vec::new();
+
+        for model in self.models.iter() {
+            let size = model.size();
+            write_chunk(&mut children, b"SIZE", &[size.x, size.y, size.z].map(u32::to_le_bytes).concat());
+
+            let mut content = Vec::new();
+            let mut count = 0u32;
+
+            for z in 0..size.z {
+                for y in 0..size.y {
+                    for x in 0..size.x {
+                        let value = model.get(UVec3::new(x, y, z));
+
+                        if value != EMPTY {
+                            content.extend_from_slice(&[x as u8, y as u8, z as u8, value]);
+                            count += 1;
+                        }
+                    }
+                }
+            }
+
+            write_chunk(&mut children, b"XYZI", &[count.to_le_bytes().to_vec(), content].concat());
+        }
+
+        for (id, node) in self.nodes.iter() {
+            let mut content = id.to_le_bytes().to_vec();
+
+            match node {
+                VoxNode::Transform { name, child, layer, rotation, translation } => {
+                    let name: Vec<(&str, &str)> = name.iter().map(|name| ("_name", name.as_str())).collect();
+                    write_dict(&mut content, &name);
+
+                    content.extend_from_slice(&child.to_le_bytes());
+                    content.extend_from_slice(&(-1i32).to_le_bytes());
+                    content.extend_from_slice(&layer.to_le_bytes());
+                    content.extend_from_slice(&1i32.to_le_bytes());
+
+                    let rotation = rotation.to_string();
+                    let translation = format!("{} {} {}", translation.x, translation.y, translation.z);
+                    let mut frame = vec![("_t", translation.as_str())];
+
+                    if rotation != IDENTITY_ROTATION.to_string() {
+                        frame.push(("_r", rotation.as_str()));
+                    }
+
+                    write_dict(&mut content, &frame);
+                    write_chunk(&mut children, b"nTRN", &content);
+                }
+                VoxNode::Group { children: group } => {
+                    write_dict(&mut content, &[]);
+
+                    content.extend_from_slice(&(group.len() as u32).to_le_bytes());
+
+                    for child in group.iter() {
+                        content.extend_from_slice(&child.to_le_bytes());
+                    }
+
+                    write_chunk(&mut children, b"nGRP", &content);
+                }
+                VoxNode::Shape { models } => {
+                    write_dict(&mut content, &[]);
+
+                    content.extend_from_slice(&(models.len() as u32).to_le_bytes());
+
+                    for model in models.iter() {
+                        content.extend_from_slice(&model.to_le_bytes());
+                        write_dict(&mut content, &[]);
+                    }
+
+                    write_chunk(&mut children, b"nSHP", &content);
+                }
+            }
+        }
+
+        if !self.nodes.is_empty() {
+            let mut layer = 0i32.to_le_bytes().to_vec();
+            write_dict(&mut layer, &[]);
+            layer.extend_from_slice(&(-1i32).to_le_bytes());
+
+            write_chunk(&mut children, b"LAYR", &layer);
+        }
+
+        // Entry i colors index i + 1, the last entry is unused
+        let rgba: Vec<u8> = (1..=256).flat_map(|index| self.palette.colors[index % 256]).collect();
+        write_chunk(&mut children, b"RGBA", &rgba);
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(b"MAIN");
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&children);
+
+        return bytes;
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, VoxError> {
+        if bytes.len() < 8 || &bytes[0..4] != b"VOX " {
+            return Err(VoxError::NotVox);
+        }
+
+        // The version, 150 or 200, changes nothing for the chunks read here
+        let (main, main_end) = read_chunk_header(bytes, 8, bytes.len())?;
+
+        if main.id != *b"MAIN" {
+            return Err(VoxError::Malformed { chunk: main.id, offset: 8, reason: "expected the MAIN chunk" });
+        }
+
+        let mut models = Vec::new();
+        let mut palette = Palette::new();
+        let mut nodes = BTreeMap::new();
+        let mut node_offsets = BTreeMap::new();
+        let mut size = None;
+
+        let mut position = main.children;
+
+        while position < main_end {
+            let (chunk, end) = read_chunk_header(bytes, position, main_end)?;
+            let mut reader = Reader {
+                bytes: &bytes[chunk.content..chunk.children],
+                position: 0,
+                chunk: chunk.id,
+                offset: position,
+            };
+
+            match &chunk.id {
+                b"SIZE" => {
+                    let model_size = UVec3::new(reader.u32()?, reader.u32()?, reader.u32()?);
+
+                    if model_size.cmpeq(UVec3::ZERO).any() || model_size.cmpgt(UVec3::splat(MAX_MODEL_SIZE)).any() {
+                        return Err(reader.malformed("model size outside 1 to 256"));
+                    }
+
+                    size = Some(model_size);
+                }
+                b"XYZI" => {
+                    let Some(model_size) = size.take() else {
+                        return Err(reader.malformed("XYZI chunk without a SIZE chunk before it"));
+                    };
+
+                    let mut model = VoxelVolume::new(model_size);
+                    let count = reader.u32()?;
+
+                    for _ in 0..count {
+                        let voxel = reader.take(4)?;
+                        let position = UVec3::new(voxel[0] as u32, voxel[1] as u32, voxel[2] as u32);
+
+                        if position.cmpge(model_size).any() {
+                            return Err(reader.malformed("voxel outside its model"));
+                        }
+
+                        if voxel[3] == EMPTY {
+                            return Err(reader.malformed("voxel with color index 0"));
+                        }
+
+                        model.set(position, voxel[3]);
+                    }
+
+                    models.push(model);
+                }
+                b"RGBA" => {
+                    let colors = reader.take(256 * 4)?;
+
+                    // Entry i colors index i + 1, index 0 is empty
+                    palette.colors[0] = [0; 4];
+
+                    for (index, color) in colors.chunks_exact(4).take(255).enumerate() {
+                        palette.colors[index + 1] = [color[0], color[1], color[2], color[3]];
+                    }
+                }
+                b"nTRN" | b"nGRP" | b"nSHP" => {
+                    let id = reader.node_id()?;
+                    let attributes = reader.dict()?;
+
+                    let node = match &chunk.id {
+                        b"nTRN" => {
+                            let child = reader.node_id()?;
+                            let _reserved = reader.i32()?;
+                            let layer = reader.i32()?;
+
+                            if reader.i32()? < 1 {
+                                return Err(reader.malformed("transform without frames"));
+                            }
+
+                            // Later frames only matter for animations
+                            let frame = reader.dict()?;
+
+                            let rotation = match lookup(&frame, "_r") {
+                                Some(value) => value.parse::<u8>().ok().filter(|&value| decode_rotation(value).is_some()).ok_or(reader.malformed("invalid rotation"))?,
+                                None => IDENTITY_ROTATION,
+                            };
+
+                            let translation = match lookup(&frame, "_t") {
+                                Some(value) => parse_translation(value).ok_or(reader.malformed("invalid translation"))?,
+                                None => IVec3::ZERO,
+                            };
+
+                            VoxNode::Transform {
+                                name: lookup(&attributes, "_name").map(str::to_string),
+                                child,
+                                layer,
+                                rotation,
+                                translation,
+                            }
+                        }
+                        b"nGRP" => {
+                            let count = reader.u32()?;
+                            let mut children = Vec::new();
+
+                            for _ in 0..count {
+                                children.push(reader.node_id()?);
+                            }
+
+                            VoxNode::Group {
+                                children,
+                            }
+                        }
+                        _ => {
+                            let count = reader.u32()?;
+                            let mut shape_models = Vec::new();
+
+                            for _ in 0..count {
+                                shape_models.push(reader.u32()?);
+                                reader.dict()?;
+                            }
+
+                            if shape_models.is_empty() {
+                                return Err(reader.malformed("shape without models"));
+                            }
+
+                            VoxNode::Shape {
+                                models: shape_models,
+                            }
+                        }
+                    };
+
+                    if nodes.insert(id, node).is_some() {
+                        return Err(reader.malformed("duplicate node id"));
+                    }
+
+                    node_offsets.insert(id, (chunk.id, position));
+                }
+                // PACK only announces the model count, materials, layers and cameras do not affect the voxels
+                _ => {}
+            }
+
+            position = end;
+        }
+
+        if models.is_empty() {
+            return Err(VoxError::Malformed { chunk: main.id, offset: 8, reason: "no models" });
+        }
+
+        for model in models.iter_mut() {
+            model.palette = palette.clone();
+        }
+
+        let file = Self {
+            models,
+            palette,
+            nodes,
+        };
+
+        file.validate_graph(&node_offsets)?;
+
+        return Ok(file);
+    }
+
+    /// Checks that every reference points at an existing node or model, that the graph is a tree below
+    /// node 0 and that its transforms stay within `MAX_SCENE_DISTANCE`, so `instances` can walk it.
+    fn validate_graph(&self, offsets: &BTreeMap<NodeId, (ChunkId, usize)>) -> Result<(), VoxError> {
+        if self.nodes.is_empty() {
+            return Ok(());
+        }
+
+        let malformed = |id: &NodeId, reason: &'static str| {
+            let (chunk, offset) = offsets[id];
+            return VoxError::Malformed { chunk, offset, reason };
+        };
+
+        for (id, node) in self.nodes.iter() {
+            let children = match node {
+                VoxNode::Transform { child, .. } => vec![*child],
+                VoxNode::Group { children } => children.clone(),
+                VoxNode::Shape { models } => {
+                    if models.iter().any(|&model| model as usize >= self.models.len()) {
+                        return Err(malformed(id, "shape references a missing model"));
+                    }
+
+                    Vec::new()
+                }
+            };
+
+            if children.iter().any(|child| !self.nodes.contains_key(child)) {
+                return Err(malformed(id, "reference to a missing node"));
+            }
+        }
+
+        let Some(VoxNode::Transform { .. }) = self.nodes.get(&0) else {
+            return Err(VoxError::Malformed { chunk: *b"MAIN", offset: 8, reason: "scene graph without a root transform" });
+        };
+
+        // Every node is reached at most once from the root, which also rules out cycles. Rotations
+        // permute axes, so the distance a path moves models is at most the sum of its translations.
+        let mut visited = Vec::new();
+        let mut stack = vec![(0, 0i64)];
+
+        while let Some((id, distance)) = stack.pop() {
+            if visited.contains(&id) {
+                return Err(malformed(&id, "node reached twice in the scene graph"));
+            }
+
+            visited.push(id);
+
+            match &self.nodes[&id] {
+                VoxNode::Transform { child, translation, .. } => {
+                    let distance = distance + translation.as_i64vec3().abs().max_element();
+
+                    if distance > MAX_SCENE_DISTANCE {
+                        return Err(malformed(&id, "transforms move models too far from the origin"));
+                    }
+
+                    stack.push((*child, distance));
+                }
+                VoxNode::Group { children } => stack.extend(children.iter().map(|child| (*child, distance))),
+                VoxNode::Shape { .. } => {}
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Every model drawn by the scene graph with its accumulated transform.
+    pub fn instances(&self) -> Vec<VoxInstance> {
+        let identity = VoxInstance {
+            model: 0,
+            rotation: [IVec3::X, IVec3::Y, IVec3::Z],
+            translation: IVec3::ZERO,
+        };
+
+        if self.nodes.is_empty() {
+            // Without a scene graph every model sits with its corner at the origin
+            return self.models.iter().enumerate().map(|(model, volume)| VoxInstance {
+                model,
+                translation: (volume.size() / 2).as_ivec3(),
+                ..identity
+            }).collect();
+        }
+
+        let mut instances = Vec::new();
+        let mut stack = vec![(0, identity)];
+
+        while let Some((id, parent)) = stack.pop() {
+            match &self.nodes[&id] {
+                VoxNode::Transform { child, rotation, translation, .. } => {
+                    let local = VoxInstance {
+                        model: 0,
+                        rotation: decode_rotation(*rotation).expect("Rotations are validated when parsing"),
+                        translation: *translation,
+                    };
+
+                    stack.push((*child, parent.then(&local)));
+                }
+                VoxNode::Group { children } => {
+                    // Reversed so models come out in file order
+                    for child in children.iter().rev() {
+                        stack.push((*child, parent));
+                    }
+                }
+                VoxNode::Shape { models } => {
+                    instances.push(VoxInstance {
+                        model: models[0] as usize,
+                        ..parent
+                    });
+                }
+            }
+        }
+
+        return instances;
+    }
+
+    /// Merges every instance into one volume covering the whole scene, one voxel per scene unit, with
+    /// its origin at the lowest scene voxel. Later instances overwrite earlier ones where they overlap.
+    /// Fails for scenes drawing nothing and for models spread over more than `MAX_VOLUME_VOXELS`.
+    pub fn to_volume(&self) -> Result<VoxelVolume, VoxError> {
+        let instances = self.instances();
+
+        if instances.is_empty() {
+            return Err(VoxError::EmptyScene);
+        }
+
+        let mut min = IVec3::MAX;
+        let mut max = IVec3::MIN;
+
+        for instance in instances.iter() {
+            let size = self.models[instance.model].size();
+
+            for corner in [UVec3::ZERO, size - 1] {
+                let placed = instance.place(corner, size);
+
+                min = min.min(placed);
+                max = max.max(placed);
+            }
+        }
+
+        let extent = max.as_i64vec3() - min.as_i64vec3() + 1;
+
+        let mut volume = u32::try_from(extent.max_element()).ok()
+            .and_then(|_| VoxelVolume::try_new(extent.as_uvec3()))
+            .ok_or(VoxError::TooLarge { extent })?;
+
+        volume.palette = self.palette.clone();
+        volume.origin = min.as_vec3();
+
+        for instance in instances.iter() {
+            let model = &self.models[instance.model];
+            let size = model.size();
+
+            for z in 0..size.z {
+                for y in 0..size.y {
+                    for x in 0..size.x {
+                        let value = model.get(UVec3::new(x, y, z));
+
+                        if value != EMPTY {
+                            volume.set((instance.place(UVec3::new(x, y, z), size) - min).as_uvec3(), value);
+                        }
+                    }
+                }
+            }
+        }
+
+        return Ok(volume);
+    }
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &ChunkId, content: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(content);
+}
+
+fn write_dict(bytes: &mut Vec<u8>, pairs: &[(&str, &str)]) {
+    bytes.extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+
+    for (key, value) in pairs {
+        for string in [key, value] {
+            bytes.extend_from_slice(&(string.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(string.as_bytes());
+        }
+    }
+}
+
+struct ChunkHeader {
+    id: ChunkId,
+    /// Start of the content, in bytes from the start of the file.
+    content: usize,
+    /// Start of the children, right after the content.
+    children: usize,
+}
+
+/// Reads the header of the chunk at `offset`, returning it with the end of its children. The chunk has
+/// to end before `limit`, the end of its parent.
+fn read_chunk_header(bytes: &[u8], offset: usize, limit: usize) -> Result<(ChunkHeader, usize), VoxError> {
+    let mut id = *b"????";
+    let available = limit.min(bytes.len()).saturating_sub(offset);
+
+    id[..available.min(4)].copy_from_slice(&bytes[offset..offset + available.min(4)]);
+
+    if available < 12 {
+        return Err(VoxError::Truncated { chunk: id, offset });
+    }
+
+    let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().expect("Words are 4 bytes")) as usize;
+    let (content_size, children_size) = (word(offset + 4), word(offset + 8));
+
+    let content = offset + 12;
+    let children = content.checked_add(content_size).ok_or(VoxError::Truncated { chunk: id, offset })?;
+    let end = children.checked_add(children_size).ok_or(VoxError::Truncated { chunk: id, offset })?;
+
+    if end > limit.min(bytes.len()) {
+        return Err(VoxError::Truncated { chunk: id, offset });
+    }
+
+    return Ok((ChunkHeader { id, content, children }, end));
+}
+
+/// Cursor over the content of a chunk, errors point at the chunk.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    chunk: ChunkId,
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn malformed(&self, reason: &'static str) -> VoxError {
+        return VoxError::Malformed { chunk: self.chunk, offset: self.offset, reason };
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], VoxError> {
+        if self.bytes.len() - self.position < count {
+            return Err(VoxError::Truncated { chunk: self.chunk, offset: self.offset });
+        }
+
+        let bytes = &self.bytes[self.position..self.position + count];
+        self.position += count;
+
+        return Ok(bytes);
+    }
+
+    fn i32(&mut self) -> Result<i32, VoxError> {
+        return Ok(i32::from_le_bytes(self.take(4)?.try_into().expect("Took 4 bytes")));
+    }
+
+    /// Sizes and counts, stored as signed integers but never negative.
+    fn u32(&mut self) -> Result<u32, VoxError> {
+        let value = self.i32()?;
+
+        return u32::try_from(value).map_err(|_| self.malformed("negative size or count"));
+    }
+
+    fn node_id(&mut self) -> Result<NodeId, VoxError> {
+        let value = self.i32()?;
+
+        return u32::try_from(value).map_err(|_| self.malformed("negative node id"));
+    }
+
+    fn string(&mut self) -> Result<String, VoxError> {
+        let length = self.u32()? as usize;
+
+        return String::from_utf8(self.take(length)?.to_vec()).map_err(|_| self.malformed("string is not UTF-8"));
+    }
+
+    fn dict(&mut self) -> Result<Vec<(String, String)>, VoxError> {
+        let count = self.u32()?;
+        let mut pairs = Vec::new();
+
+        for _ in 0..count {
+            pairs.push((self.string()?, self.string()?));
+        }
+
+        return Ok(pairs);
+    }
+}
+
+fn lookup<'a>(dict: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    return dict.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str());
+}
+
+fn parse_translation(value: &str) -> Option<IVec3> {
+    let mut parts = value.split_whitespace().map(|part| part.parse::<i32>());
+
+    let translation = IVec3::new(parts.next()?.ok()?, parts.next()?.ok()?, parts.next()?.ok()?);
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    return Some(translation);
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{
+        I64Vec3,
+        IVec3,
+        UVec3
+    };
+
+    use crate::voxel::{
+        Palette,
+        VoxelVolume,
+        EMPTY,
+        STONE,
+        WOOD
+    };
+
+    use super::{
+        decode_rotation,
+        VoxError,
+        VoxFile,
+        VoxNode,
+        MAX_MODEL_SIZE
+    };
+
+    /// Builds files the way MagicaVoxel lays them out, with chunks the parser has to skip.
+    struct FixtureWriter {
+        children: Vec<u8>,
+    }
+
+    impl FixtureWriter {
+        fn new() -> Self {
+            return Self {
+                children: Vec::new(),
+            };
+        }
+
+        fn chunk(&mut self, id: &[u8; 4], content: &[u8]) -> usize {
+            let offset = 20 + self.children.len();
+
+            self.children.extend_from_slice(id);
+            self.children.extend_from_slice(&(content.len() as i32).to_le_bytes());
+            self.children.extend_from_slice(&0i32.to_le_bytes());
+            self.children.extend_from_slice(content);
+
+            return offset;
+        }
+
+        fn model(&mut self, volume: &VoxelVolume) {
+            let size = volume.size();
+            self.chunk(b"SIZE", &[size.x as i32, size.y as i32, size.z as i32].map(i32::to_le_bytes).concat());
+
+            let mut xyzi = Vec::new();
+            let mut count = 0i32;
+
+            for z in 0..size.z {
+                for y in 0..size.y {
+                    for x in 0..size.x {
+                        let value = volume.get(UVec3::new(x, y, z));
+
+                        if value != EMPTY {
+                            xyzi.extend_from_slice(&[x as u8, y as u8, z as u8, value]);
+                            count += 1;
+                        }
+                    }
+                }
+            }
+
+            self.chunk(b"XYZI", &[count.to_le_bytes().to_vec(), xyzi].concat());
+        }
+
+        fn finish(self) -> Vec<u8> {
+            let mut bytes = b"VOX ".to_vec();
+            bytes.extend_from_slice(&200i32.to_le_bytes());
+            bytes.extend_from_slice(b"MAIN");
+            bytes.extend_from_slice(&0i32.to_le_bytes());
+            bytes.extend_from_slice(&(self.children.len() as i32).to_le_bytes());
+            bytes.extend_from_slice(&self.children);
+
+            return bytes;
+        }
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        return [(value.len() as i32).to_le_bytes().to_vec(), value.as_bytes().to_vec()].concat();
+    }
+
+    fn dict(pairs: &[(&str, &str)]) -> Vec<u8> {
+        let mut bytes = (pairs.len() as i32).to_le_bytes().to_vec();
+
+        for (key, value) in pairs {
+            bytes.extend(string(key));
+            bytes.extend(string(value));
+        }
+
+        return bytes;
+    }
+
+    fn ints(values: &[i32]) -> Vec<u8> {
+        return values.iter().flat_map(|value| value.to_le_bytes()).collect();
+    }
+
+    fn transform(id: i32, child: i32, frame: &[(&str, &str)]) -> Vec<u8> {
+        return [ints(&[id]), dict(&[]), ints(&[child, -1, 0, 1]), dict(frame)].concat();
+    }
+
+    /// A tree trunk and a stone slab, the slab rotated a quarter turn around Z and moved aside.
+    fn fixture() -> (Vec<u8>, VoxelVolume, VoxelVolume, Palette) {
+        let mut trunk = VoxelVolume::new(UVec3::new(1, 1, 4));
+        let mut slab = VoxelVolume::new(UVec3::new(4, 2, 1));
+
+        for z in 0..4 {
+            trunk.set(UVec3::new(0, 0, z), WOOD);
+        }
+
+        for x in 0..4 {
+            slab.set(UVec3::new(x, 0, 0), STONE);
+        }
+
+        let mut palette = Palette::new();
+        palette.colors[0] = [0; 4];
+        palette.colors[STONE as usize] = [10, 20, 30, 255];
+
+        let mut rgba = Vec::new();
+
+        for index in 1..=256 {
+            rgba.extend_from_slice(&palette.colors[index % 256]);
+        }
+
+        let mut writer = FixtureWriter::new();
+
+        writer.chunk(b"PACK", &ints(&[2]));
+        writer.model(&trunk);
+        writer.model(&slab);
+        writer.chunk(b"nTRN", &transform(0, 1, &[]));
+        writer.chunk(b"nGRP", &[ints(&[1]), dict(&[]), ints(&[2, 2, 4])].concat());
+        writer.chunk(b"nTRN", &transform(2, 3, &[("_t", "0 0 2")]));
+        writer.chunk(b"nSHP", &[ints(&[3]), dict(&[]), ints(&[1, 0]), dict(&[])].concat());
+        writer.chunk(b"nTRN", &transform(4, 5, &[("_r", "17"), ("_t", "5 0 0")]));
+        writer.chunk(b"nSHP", &[ints(&[5]), dict(&[]), ints(&[1, 1]), dict(&[])].concat());
+        writer.chunk(b"LAYR", &[ints(&[0]), dict(&[]), ints(&[-1])].concat());
+        writer.chunk(b"RGBA", &rgba);
+        writer.chunk(b"MATL", &[ints(&[1]), dict(&[("_type", "_diffuse")])].concat());
+
+        return (writer.finish(), trunk, slab, palette);
+    }
+
+    #[test]
+    fn parses_models_palette_and_scene_graph() {
+        let (bytes, trunk, slab, palette) = fixture();
+        let file = VoxFile::parse(&bytes).expect("Fixture should parse");
+
+        assert_eq!(file.palette, palette);
+        assert_eq!(file.models.len(), 2);
+        assert_eq!(file.models[0].voxels(), trunk.voxels());
+        assert_eq!(file.models[1].voxels(), slab.voxels());
+        assert_eq!(file.nodes.len(), 6);
+        assert_eq!(file.nodes[&1], VoxNode::Group { children: vec![2, 4] });
+
+        // _r 17 maps X to Y and Y to -X
+        assert_eq!(decode_rotation(17), Some([IVec3::new(0, -1, 0), IVec3::X, IVec3::Z]));
+
+        let instances = file.instances();
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[1].place(UVec3::new(3, 0, 0), slab.size()), IVec3::new(6, 1, 0));
+
+        // Both models round-trip into one volume through their transforms
+        let volume = file.to_volume().expect("Fixture draws both models");
+        let min = volume.origin.as_ivec3();
+
+        for z in 0..4 {
+            assert_eq!(volume.get((IVec3::new(0, 0, z) - min).as_uvec3()), WOOD);
+        }
+
+        for x in 0..4 {
+            assert_eq!(volume.get((instances[1].place(UVec3::new(x, 0, 0), slab.size()) - min).as_uvec3()), STONE);
+        }
+
+        assert_eq!(volume.voxels().iter().filter(|&&value| value != EMPTY).count(), 8);
+        assert_eq!(volume.palette, palette);
+    }
+
+    #[test]
+    fn malformed_files_report_the_chunk_offset() {
+        let (bytes, _, _, _) = fixture();
+
+        assert_eq!(VoxFile::parse(b"PNG nope"), Err(VoxError::NotVox));
+
+        // Cut inside the RGBA chunk, the MAIN chunk no longer fits
+        assert_eq!(VoxFile::parse(&bytes[..bytes.len() - 600]), Err(VoxError::Truncated { chunk: *b"MAIN", offset: 8 }));
+
+        let mut writer = FixtureWriter::new();
+        writer.chunk(b"SIZE", &ints(&[2, 2, 2]));
+        let offset = writer.chunk(b"XYZI", &[ints(&[1]), vec![0, 2, 0, 1]].concat());
+
+        assert_eq!(VoxFile::parse(&writer.finish()), Err(VoxError::Malformed { chunk: *b"XYZI", offset, reason: "voxel outside its model" }));
+
+        let mut writer = FixtureWriter::new();
+        writer.model(&VoxelVolume::new(UVec3::ONE));
+        writer.chunk(b"nTRN", &transform(0, 1, &[]));
+        let offset = writer.chunk(b"nSHP", &[ints(&[1]), dict(&[]), ints(&[1, 7]), dict(&[])].concat());
+
+        assert_eq!(VoxFile::parse(&writer.finish()), Err(VoxError::Malformed { chunk: *b"nSHP", offset, reason: "shape references a missing model" }));
+
+        let mut writer = FixtureWriter::new();
+        writer.model(&VoxelVolume::new(UVec3::ONE));
+        let offset = writer.chunk(b"nTRN", &transform(0, 0, &[]));
+
+        assert_eq!(VoxFile::parse(&writer.finish()), Err(VoxError::Malformed { chunk: *b"nTRN", offset, reason: "node reached twice in the scene graph" }));
+    }
+
+    #[test]
+    fn empty_scenes_have_no_volume() {
+        // A root transform pointing at an empty group is a valid graph drawing nothing
+        let mut writer = FixtureWriter::new();
+        writer.model(&VoxelVolume::new(UVec3::ONE));
+        writer.chunk(b"nTRN", &transform(0, 1, &[]));
+        writer.chunk(b"nGRP", &[ints(&[1]), dict(&[]), ints(&[0])].concat());
+
+        let file = VoxFile::parse(&writer.finish()).expect("Empty groups are valid");
+
+        assert!(file.instances().is_empty());
+        assert_eq!(file.to_volume(), Err(VoxError::EmptyScene));
+    }
+
+    #[test]
+    fn distant_models_are_rejected() {
+        let mut voxel = VoxelVolume::new(UVec3::ONE);
+        voxel.set(UVec3::ZERO, STONE);
+
+        let scene = |first: &str, second: &str| {
+            let mut writer = FixtureWriter::new();
+            writer.model(&voxel);
+            writer.chunk(b"nTRN", &transform(0, 1, &[]));
+            writer.chunk(b"nGRP", &[ints(&[1]), dict(&[]), ints(&[2, 2, 4])].concat());
+            writer.chunk(b"nTRN", &transform(2, 3, &[("_t", first)]));
+            writer.chunk(b"nSHP", &[ints(&[3]), dict(&[]), ints(&[1, 0]), dict(&[])].concat());
+            let offset = writer.chunk(b"nTRN", &transform(4, 5, &[("_t", second)]));
+            writer.chunk(b"nSHP", &[ints(&[5]), dict(&[]), ints(&[1, 0]), dict(&[])].concat());
+
+            return (writer.finish(), offset);
+        };
+
+        let (bytes, _) = scene("0 0 0", "40 0 -3");
+        let volume = VoxFile::parse(&bytes).unwrap().to_volume().expect("Nearby models fit a volume");
+
+        assert_eq!(volume.size(), UVec3::new(41, 1, 4));
+
+        // 2^48 voxels, far more than a volume holds
+        let (bytes, _) = scene("-8388608 -8388608 0", "8388607 8388607 0");
+
+        assert_eq!(VoxFile::parse(&bytes).unwrap().to_volume(), Err(VoxError::TooLarge { extent: I64Vec3::new(1 << 24, 1 << 24, 1) }));
+
+        // Translations that would overflow the placements are rejected when parsing
+        let (bytes, offset) = scene("0 0 0", "-2147483648 0 0");
+
+        assert_eq!(VoxFile::parse(&bytes), Err(VoxError::Malformed { chunk: *b"nTRN", offset, reason: "transforms move models too far from the origin" }));
+    }
+
+    #[test]
+    fn written_files_round_trip() {
+        let volume = VoxelVolume::demo();
+        let file = VoxFile::from_volume(&volume);
+        let parsed = VoxFile::parse(&file.write()).expect("Written file should parse");
+
+        assert_eq!(parsed.palette, volume.palette);
+        assert_eq!(parsed.nodes, file.nodes);
+        assert_eq!(parsed.models.len(), 1);
+
+        let merged = parsed.to_volume().expect("Written file draws the volume");
+
+        assert_eq!(merged.size(), volume.size());
+        assert_eq!(merged.voxels(), volume.voxels());
+
+        // Writing what was read gives the same bytes
+        assert_eq!(parsed.write(), file.write());
+    }
+
+    #[test]
+    fn large_volumes_split_into_models() {
+        let mut volume = VoxelVolume::new(UVec3::new(300, 4, 260));
+
+        for voxel in [UVec3::ZERO, UVec3::new(255, 0, 0), UVec3::new(256, 1, 0), UVec3::new(299, 3, 259), UVec3::new(10, 2, 256)] {
+            volume.set(voxel, STONE);
+        }
+
+        let file = VoxFile::from_volume(&volume);
+
+        assert_eq!(file.models.len(), 4);
+        assert!(file.models.iter().all(|model| model.size().max_element() <= MAX_MODEL_SIZE));
+
+        let merged = VoxFile::parse(&file.write()).expect("Written file should parse").to_volume().expect("Written file draws the volume");
+
+        assert_eq!(merged.size(), volume.size());
+        assert_eq!(merged.voxels(), volume.voxels());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,262 @@
+use glam::{
+    IVec3,
+    Vec3
+};
+
+use crate::{
+    renderer::pipeline::ColorVertex,
+    voxel::{
+        world::{
+            World,
+            CHUNK_SIZE
+        },
+        Palette,
+        EMPTY
+    }
+};
+
+const SIZE: i32 = CHUNK_SIZE as i32;
+
+/// Brightness of a vertex by the number of solid voxels around it, from three to none.
+const OCCLUSION_LEVELS: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+/// Triangles of a chunk in world space, ready for `ColorPipeline`, which lights them with the scene
+/// lights. Vertex colors are the palette colors darkened by ambient occlusion.
+#[derive(Clone)]
+pub struct ChunkMesh {
+    pub vertices: Vec<ColorVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl ChunkMesh {
+    pub fn new() -> Self {
+        return Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.indices.is_empty();
+    }
+}
+
+/// Visible face of a voxel, faces merge into one quad only when all of this matches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Face {
+    value: u8,
+    /// Occlusion of the corners, counter-clockwise from the lowest one.
+    occlusion: [u8; 4],
+}
+
+fn unit(axis: usize) -> IVec3 {
+    let mut unit = IVec3::ZERO;
+    unit[axis] = 1;
+
+    return unit;
+}
+
+/// Occlusion of the four corners of a face, from the voxels next to `front`, the empty voxel the
+/// face looks into. A corner between two solid sides is fully occluded whatever the diagonal.
+fn occlusion(solid: &impl Fn(IVec3) -> bool, front: IVec3, u: IVec3, v: IVec3) -> [u8; 4] {
+    return [(-1, -1), (1, -1), (1, 1), (-1, 1)].map(|(du, dv)| {
+        let side_u = solid(front + u * du);
+        let side_v = solid(front + v * dv);
+
+        if side_u && side_v {
+            return 0;
+        }
+
+        return 3 - side_u as u8 - side_v as u8 - solid(front + u * du + v * dv) as u8;
+    });
+}
+
+fn occlude(color: [u8; 4], occlusion: u8) -> [u8; 4] {
+    let light = OCCLUSION_LEVELS[occlusion as usize];
+
+    return [
+        (color[0] as f32 * light).round() as u8,
+        (color[1] as f32 * light).round() as u8,
+        (color[2] as f32 * light).round() as u8,
+        color[3],
+    ];
+}
+
+/// Greedy mesh of a loaded chunk, with faces towards its neighbours culled and occluded by the
+/// voxels they have loaded.
+pub fn mesh_chunk(world: &World, coord: IVec3) -> ChunkMesh {
+    if world.chunk(coord).is_none_or(|chunk| chunk.is_empty()) {
+        return ChunkMesh::new();
+    }
+
+    let min = coord * SIZE;
+
+    return greedy_mesh(|local| world.get(min + local), world.origin + min.as_vec3() * world.voxel_size, world.voxel_size, &world.palette);
+}
+
+/// Meshes the chunk `voxel` returns the palette indices of, queried from -1 to `CHUNK_SIZE` on each
+/// axis. Coplanar faces of the same color and occlusion merge into the largest rectangles found
+/// row by row.
+fn greedy_mesh(voxel: impl Fn(IVec3) -> u8, origin: Vec3, voxel_size: f32, palette: &Palette) -> ChunkMesh {
+    let solid = |p: IVec3| voxel(p) != EMPTY;
+
+    let mut mesh = ChunkMesh::new();
+    let mut mask = vec![None::<Face>; (SIZE * SIZE) as usize];
+
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        for sign in [-1, 1] {
+            let normal = unit(axis) * sign;
+
+            for layer in 0..SIZE {
+                for b in 0..SIZE {
+                    for a in 0..SIZE {
+                        let p = unit(axis) * layer + unit(u) * a + unit(v) * b;
+                        let value = voxel(p);
+
+                        mask[(a + b * SIZE) as usize] = if value == EMPTY || solid(p + normal) {
+                            None
+                        } else {
+                            Some(Face {
+                                value,
+                                occlusion: occlusion(&solid, p + normal, unit(u), unit(v)),
+                            })
+                        };
+                    }
+                }
+
+                for b in 0..SIZE {
+                    let mut a = 0;
+
+                    while a < SIZE {
+                        let Some(face) = mask[(a + b * SIZE) as usize] else {
+                            a += 1;
+                            continue;
+                        };
+
+                        let mut width = 1;
+
+                        while a + width < SIZE && mask[(a + width + b * SIZE) as usize] == Some(face) {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+
+                        while b + height < SIZE && (a..a + width).all(|k| mask[(k + (b + height) * SIZE) as usize] == Some(face)) {
+                            height += 1;
+                        }
+
+                        for row in b..b + height {
+                            for column in a..a + width {
+                                mask[(column + row * SIZE) as usize] = None;
+                            }
+                        }
+
+                        // Faces towards positive axes lie on the far side of their voxel
+                        let corner = unit(axis) * (layer + (sign > 0) as i32) + unit(u) * a + unit(v) * b;
+                        let corners = [corner, corner + unit(u) * width, corner + unit(u) * width + unit(v) * height, corner + unit(v) * height];
+
+                        // Counter-clockwise seen from the side the face looks at
+                        let order = if sign > 0 { [0, 1, 2, 3] } else { [0, 3, 2, 1] };
+                        let first = mesh.vertices.len() as u32;
+
+                        for i in order {
+                            mesh.vertices.push(ColorVertex {
+                                position: (origin + corners[i].as_vec3() * voxel_size).to_array(),
+                                color: occlude(palette.colors[face.value as usize], face.occlusion[i]),
+                                normal: normal.as_vec3().to_array(),
+                            });
+                        }
+
+                        // Split along the brighter diagonal, so occlusion fades the same way on both triangles
+                        let occlusion = order.map(|i| face.occlusion[i]);
+
+                        if occlusion[0] + occlusion[2] < occlusion[1] + occlusion[3] {
+                            mesh.indices.extend_from_slice(&[first, first + 1, first + 3, first + 1, first + 2, first + 3]);
+                        } else {
+                            mesh.indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+                        }
+
+                        a += width;
+                    }
+                }
+            }
+        }
+    }
+
+    return mesh;
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{
+        IVec3,
+        Vec3
+    };
+
+    use crate::voxel::{
+        Palette,
+        EMPTY,
+        GRASS,
+        STONE
+    };
+
+    use super::{
+        greedy_mesh,
+        ChunkMesh,
+        CHUNK_SIZE
+    };
+
+    fn vertex_position(mesh: &ChunkMesh, index: u32) -> Vec3 {
+        return Vec3::from_array(mesh.vertices[index as usize].position);
+    }
+
+    #[test]
+    fn slabs_merge_into_one_quad_per_side() {
+        let mesh = greedy_mesh(|p| if p.z == 3 && p.cmpge(IVec3::ZERO).all() && p.cmplt(IVec3::splat(CHUNK_SIZE as i32)).all() { STONE } else { EMPTY }, Vec3::ZERO, 1.0, &Palette::new());
+
+        assert_eq!(mesh.vertices.len(), 6 * 4);
+        assert_eq!(mesh.indices.len(), 6 * 6);
+
+        // Triangles face away from the slab
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| vertex_position(&mesh, index));
+            let center = (a + b + c) / 3.0 - Vec3::new(16.0, 16.0, 3.5);
+
+            assert!((b - a).cross(c - a).dot(center) > 0.0);
+        }
+    }
+
+    #[test]
+    fn faces_are_occluded_by_their_neighbours() {
+        // A grass voxel standing on a stone floor darkens the floor around it and splits its top face
+        let voxel = |p: IVec3| {
+            return match p {
+                IVec3 { x: 10, y: 10, z: 1 } => GRASS,
+                IVec3 { z: 0, .. } => STONE,
+                _ => EMPTY,
+            };
+        };
+
+        let mesh = greedy_mesh(voxel, Vec3::ZERO, 1.0, &Palette::new());
+
+        // Stone is gray whatever its shade, grass is greener than it is red
+        let is_grass = |color: [u8; 4]| color[1] > color[0];
+
+        let floor_top = mesh.vertices.iter()
+            .filter(|vertex| vertex.position[2] == 1.0 && !is_grass(vertex.color))
+            .map(|vertex| vertex.color[0])
+            .collect::<Vec<_>>();
+
+        let brightest = *floor_top.iter().max().unwrap();
+
+        assert!(floor_top.len() > 4);
+        assert!(floor_top.iter().any(|&red| red < brightest));
+
+        // Only the top of the grass voxel and its four sides, its bottom is hidden by the floor
+        let grass = mesh.vertices.iter().filter(|vertex| is_grass(vertex.color)).count();
+
+        assert_eq!(grass, 5 * 4);
+    }
+}
\ No newline at end of file
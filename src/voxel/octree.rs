@@ -0,0 +1,337 @@
+use std::array;
+
+use bytemuck::{
+    Pod,
+    Zeroable
+};
+
+use glam::{
+    IVec3,
+    UVec3,
+    Vec3
+};
+
+use crate::voxel::{
+    entry_normal,
+    Palette,
+    VoxelHit,
+    VoxelVolume,
+    EMPTY
+};
+
+/// Set on slots holding a solid region, the palette index sits in the low byte.
+pub const LEAF: u32 = 0x8000_0000;
+
+/// Width of the node texture in texels, the flat node buffer wraps into rows of that many slots.
+/// Keep in sync with voxel_octree.wgsl.
+pub const NODE_TEXTURE_WIDTH: u32 = 2048;
+
+/// Sparse voxel octree over a cube of `2^depth` voxels per side, flattened into a node buffer.
+///
+/// Every node is a block of 8 consecutive slots, one per child, with the X offset in bit 0, Y in bit
+/// 1 and Z in bit 2 of the child index. A slot is either 0 for an empty region, `LEAF | value` for a
+/// region filled with a single palette index, or the block index of the child node. Uniform regions
+/// are collapsed at any level, and the root always sits at block 0, so 0 is never a child index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseVoxelOctree {
+    depth: u32,
+    nodes: Vec<u32>,
+
+    pub palette: Palette,
+    pub origin: Vec3,
+    pub voxel_size: f32,
+}
+
+impl SparseVoxelOctree {
+    /// Builds the octree bottom up, the volume is padded with empty voxels to the next power of two.
+    pub fn new(volume: &VoxelVolume) -> Self {
+        let extent = volume.size().max_element().next_power_of_two().max(2);
+        let depth = extent.trailing_zeros();
+
+        let mut nodes = vec![0; 8];
+
+        for child in 0..8 {
+            let slot = build_region(volume, child_offset(child) << (depth - 1), depth - 1, &mut nodes);
+            nodes[child] = slot;
+        }
+
+        return Self {
+            depth,
+            nodes,
+
+            palette: volume.palette.clone(),
+            origin: volume.origin,
+            voxel_size: volume.voxel_size,
+        };
+    }
+
+    pub fn depth(&self) -> u32 {
+        return self.depth;
+    }
+
+    /// Voxels per side of the cube covered by the root.
+    pub fn extent(&self) -> u32 {
+        return 1 << self.depth;
+    }
+
+    pub fn nodes(&self) -> &[u32] {
+        return &self.nodes;
+    }
+
+    pub fn node_count(&self) -> usize {
+        return self.nodes.len() / 8;
+    }
+
+    /// Rows of the node texture, at least one so empty octrees still get a valid texture.
+    pub fn node_texture_rows(&self) -> u32 {
+        return (self.nodes.len() as u32).div_ceil(NODE_TEXTURE_WIDTH).max(1);
+    }
+
+    /// The node buffer padded to whole rows of the node texture.
+    pub fn node_texture_data(&self) -> Vec<u32> {
+        let mut data = self.nodes.clone();
+        data.resize((self.node_texture_rows() * NODE_TEXTURE_WIDTH) as usize, 0);
+
+        return data;
+    }
+
+    /// Slot of the largest region containing `voxel` that is either empty or solid, with the level of
+    /// that region, whose side is `2^level` voxels.
+    fn lookup(&self, voxel: UVec3) -> (u32, u32) {
+        let mut node = 0;
+        let mut level = self.depth;
+
+        loop {
+            level -= 1;
+
+            let child = ((voxel >> level) & 1).dot(UVec3::new(1, 2, 4));
+            let slot = self.nodes[(node * 8 + child) as usize];
+
+            if slot == 0 || slot & LEAF != 0 || level == 0 {
+                return (slot, level);
+            }
+
+            node = slot;
+        }
+    }
+
+    pub fn get(&self, voxel: UVec3) -> u8 {
+        if voxel.cmpge(UVec3::splat(self.extent())).any() {
+            return EMPTY;
+        }
+
+        let (slot, _) = self.lookup(voxel);
+
+        return (slot & 0xff) as u8;
+    }
+
+    /// Finds the first non empty voxel along the ray, leaping over whole empty regions: each step
+    /// descends from the root to the region around the current voxel and leaves it through its exit
+    /// face. CPU counterpart of `trace_volume` in voxel_octree.wgsl.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<VoxelHit> {
+        // Grid space, where voxels are unit cubes starting at zero
+        let o = (origin - self.origin) / self.voxel_size;
+        let d = Vec3::select(direction.abs().cmplt(Vec3::splat(1e-8)), Vec3::splat(1e-8), direction);
+        let extent = self.extent() as i32;
+
+        let t1 = -o / d;
+        let t2 = (Vec3::splat(extent as f32) - o) / d;
+        let t_near = t1.min(t2);
+        let t_far = t1.max(t2);
+
+        let t_enter = t_near.max_element();
+        let t_exit = t_far.min_element();
+
+        if t_enter > t_exit || t_exit < 0.0 {
+            return None;
+        }
+
+        let step = d.signum().as_ivec3();
+
+        let mut t = t_enter.max(0.0);
+        let mut voxel = (o + d * t).floor().as_ivec3().clamp(IVec3::ZERO, IVec3::splat(extent - 1));
+        let mut normal = entry_normal(t_near, step);
+
+        // Never more regions than voxels a plain DDA would visit
+        for _ in 0..3 * extent {
+            let (slot, level) = self.lookup(voxel.as_uvec3());
+
+            if slot & LEAF != 0 {
+                return Some(VoxelHit {
+                    voxel: voxel.as_uvec3(),
+                    value: (slot & 0xff) as u8,
+                    normal,
+                    distance: t * self.voxel_size,
+                });
+            }
+
+            let cell_size = 1 << level;
+            let cell_min = (voxel >> level) << level;
+
+            let boundary = cell_min.as_vec3() + Vec3::select(step.cmpgt(IVec3::ZERO), Vec3::splat(cell_size as f32), Vec3::ZERO);
+            let t_max = (boundary - o) / d;
+
+            let axis = if t_max.x < t_max.y && t_max.x < t_max.z { 0 } else if t_max.y < t_max.z { 1 } else { 2 };
+
+            // The exit axis is stepped exactly, the others are clamped so rounding never leaves the region sideways
+            t = t_max[axis];
+            voxel = (o + d * t).floor().as_ivec3().clamp(cell_min, cell_min + cell_size - 1);
+            voxel[axis] = if step[axis] > 0 { cell_min[axis] + cell_size } else { cell_min[axis] - 1 };
+
+            normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+
+            if voxel.cmplt(IVec3::ZERO).any() || voxel.cmpge(IVec3::splat(extent)).any() {
+                return None;
+            }
+        }
+
+        return None;
+    }
+}
+
+fn child_offset(child: usize) -> UVec3 {
+    return UVec3::new(child as u32 & 1, (child as u32 >> 1) & 1, (child as u32 >> 2) & 1);
+}
+
+/// Slot of the region of `2^level` voxels per side starting at `min`, appending the nodes it needs.
+fn build_region(volume: &VoxelVolume, min: UVec3, level: u32, nodes: &mut Vec<u32>) -> u32 {
+    if min.cmpge(volume.size()).any() {
+        return 0;
+    }
+
+    if level == 0 {
+        let value = volume.get(min);
+
+        return if value == EMPTY { 0 } else { LEAF | value as u32 };
+    }
+
+    let children: [u32; 8] = array::from_fn(|child| build_region(volume, min + (child_offset(child) << (level - 1)), level - 1, nodes));
+
+    if children.iter().all(|&slot| slot == children[0]) && (children[0] == 0 || children[0] & LEAF != 0) {
+        return children[0];
+    }
+
+    let index = (nodes.len() / 8) as u32;
+    nodes.extend_from_slice(&children);
+
+    return index;
+}
+
+/// Layout of the octree uniform buffer in voxel_octree.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct OctreeUniform {
+    pub origin: [f32; 3],
+    pub voxel_size: f32,
+    pub depth: u32,
+    pub _padding: [u32; 3],
+}
+
+impl OctreeUniform {
+    pub fn new(octree: &SparseVoxelOctree) -> Self {
+        return Self {
+            origin: octree.origin.to_array(),
+            voxel_size: octree.voxel_size,
+            depth: octree.depth,
+            _padding: [0; 3],
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{
+        UVec3,
+        Vec3
+    };
+
+    use crate::{
+        renderer::{
+            pipeline::VoxelTraversal,
+            probe::ProbeTexture
+        },
+        voxel::{
+            tests::{
+                check_gpu_parity,
+                sample_rays
+            },
+            VoxelVolume,
+            STONE
+        }
+    };
+
+    use super::{
+        OctreeUniform,
+        SparseVoxelOctree,
+        NODE_TEXTURE_WIDTH
+    };
+
+    #[test]
+    fn octree_matches_volume() {
+        let volume = VoxelVolume::demo();
+        let octree = SparseVoxelOctree::new(&volume);
+
+        assert_eq!(octree.extent(), 32);
+        assert!(octree.node_count() < (32 * 32 * 32) / 8, "Uniform regions should collapse");
+
+        for x in 0..32 {
+            for y in 0..32 {
+                for z in 0..32 {
+                    let voxel = UVec3::new(x, y, z);
+
+                    assert_eq!(octree.get(voxel), volume.get(voxel), "Octree and volume disagree at {}", voxel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn raycast_leaps_over_empty_space() {
+        let mut volume = VoxelVolume::new(UVec3::new(64, 8, 8));
+        volume.set(UVec3::new(60, 2, 3), STONE);
+
+        let octree = SparseVoxelOctree::new(&volume);
+        let hit = octree.raycast(Vec3::new(-2.0, 2.5, 3.5), Vec3::X).expect("Ray should hit");
+
+        assert_eq!(octree.extent(), 64);
+        assert_eq!(hit.voxel, UVec3::new(60, 2, 3));
+        assert_eq!(hit.normal, glam::IVec3::new(-1, 0, 0));
+        assert!((hit.distance - 62.0).abs() < 1e-4);
+
+        assert!(octree.raycast(Vec3::new(-2.0, 6.5, 3.5), Vec3::X).is_none());
+    }
+
+    #[test]
+    fn raycast_matches_dense_traversal() {
+        let volume = VoxelVolume::demo();
+        let octree = SparseVoxelOctree::new(&volume);
+        let (origins, directions) = sample_rays(&volume);
+
+        for (origin, direction) in origins.iter().zip(directions.iter()) {
+            let (origin, direction) = (Vec3::from_slice(origin), Vec3::from_slice(direction));
+
+            let dense = volume.raycast(origin, direction, 96).map(|hit| (hit.voxel, hit.value));
+            let sparse = octree.raycast(origin, direction).map(|hit| (hit.voxel, hit.value));
+
+            assert_eq!(sparse, dense, "Octree and dense traversal disagree for ray {} {}", origin, direction);
+        }
+    }
+
+    #[test]
+    fn raycast_matches_gpu() {
+        let volume = VoxelVolume::demo();
+        let octree = SparseVoxelOctree::new(&volume);
+        let data = octree.node_texture_data();
+
+        let nodes = ProbeTexture {
+            binding: 5,
+            size: UVec3::new(NODE_TEXTURE_WIDTH, octree.node_texture_rows(), 1),
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Uint,
+            data: bytemuck::cast_slice(&data),
+        };
+
+        check_gpu_parity(&volume, VoxelTraversal::Octree, bytemuck::bytes_of(&OctreeUniform::new(&octree)), &[nodes], |origin, direction| octree.raycast(origin, direction));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,541 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{
+        self,
+        Read,
+        Seek,
+        SeekFrom,
+        Write
+    }
+};
+
+use glam::IVec3;
+
+use crate::voxel::world::{
+    Chunk,
+    CHUNK_SIZE
+};
+
+pub const MAGIC: [u8; 4] = *b"VOXW";
+
+/// Version written by `write_save`, older versions are migrated when read.
+///
+/// 1. Chunks stored raw, no world seed.
+/// 2. World seed in the header, chunks stored uniform, run-length or palette compressed.
+pub const VERSION: u32 = 2;
+
+const CHUNK_VOXELS: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Bytes of an index entry: the chunk coordinates, then the offset and length of its record.
+const INDEX_ENTRY: usize = 3 * 4 + 8 + 4;
+
+// Encodings of a chunk record, the first byte of the record
+const EMPTY_CHUNK: u8 = 0;
+const RAW: u8 = 1;
+const UNIFORM: u8 = 2;
+const RUN_LENGTH: u8 = 3;
+const PALETTE: u8 = 4;
+
+/// Palette compression stores indices with at most that many bits, bigger palettes use runs.
+const MAX_PALETTE_BITS: u32 = 4;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    /// The file does not start with the save magic.
+    NotASave,
+    /// Saved by a newer engine.
+    UnsupportedVersion(u32),
+    /// Part of the file makes no sense, at the given byte offset.
+    Corrupt { offset: u64, reason: &'static str },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            SaveError::Io(error) => write!(f, "{}", error),
+            SaveError::NotASave => write!(f, "not a world save"),
+            SaveError::UnsupportedVersion(version) => write!(f, "save version {} is newer than the supported version {}", version, VERSION),
+            SaveError::Corrupt { offset, reason } => write!(f, "corrupt save at byte {}: {}", offset, reason),
+        };
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(error: io::Error) -> Self {
+        return SaveError::Io(error);
+    }
+}
+
+/// Error of a read starting at `offset`. Reads past the end come from headers or records cut short.
+fn read_error(error: io::Error, offset: u64) -> SaveError {
+    if error.kind() == io::ErrorKind::UnexpectedEof {
+        return SaveError::Corrupt { offset, reason: "unexpected end of file" };
+    }
+
+    return SaveError::Io(error);
+}
+
+/// Writes a save of the current version holding `chunks`, followed by the index of their records.
+///
+/// Layout: magic, version (u32), seed (u64), chunk count (u32), index offset (u64), the chunk
+/// records, then the index. Integers are little endian.
+pub fn write_save<'a>(mut writer: impl Write, seed: u64, chunks: impl IntoIterator<Item = (IVec3, &'a Chunk)>) -> io::Result<()> {
+    let header = 4 + 4 + 8 + 4 + 8;
+
+    let mut records = Vec::new();
+    let mut index = Vec::new();
+
+    for (coord, chunk) in chunks {
+        let record = encode(chunk);
+
+        index.extend_from_slice(&coord.x.to_le_bytes());
+        index.extend_from_slice(&coord.y.to_le_bytes());
+        index.extend_from_slice(&coord.z.to_le_bytes());
+        index.extend_from_slice(&((header + records.len()) as u64).to_le_bytes());
+        index.extend_from_slice(&(record.len() as u32).to_le_bytes());
+
+        records.extend(record);
+    }
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&seed.to_le_bytes())?;
+    writer.write_all(&((index.len() / INDEX_ENTRY) as u32).to_le_bytes())?;
+    writer.write_all(&((header + records.len()) as u64).to_le_bytes())?;
+    writer.write_all(&records)?;
+    writer.write_all(&index)?;
+
+    return Ok(());
+}
+
+/// Record of a chunk in its smallest encoding.
+fn encode(chunk: &Chunk) -> Vec<u8> {
+    let voxels = chunk.voxels();
+
+    if voxels.is_empty() {
+        return vec![EMPTY_CHUNK];
+    }
+
+    if voxels.iter().all(|&value| value == voxels[0]) {
+        return vec![UNIFORM, voxels[0]];
+    }
+
+    // Runs of up to 65536 voxels, the length is stored minus one
+    let mut runs = vec![RUN_LENGTH];
+    let mut start = 0;
+
+    while start < voxels.len() {
+        let value = voxels[start];
+        let length = voxels[start..].iter().take(1 << 16).take_while(|&&other| other == value).count();
+
+        runs.push(value);
+        runs.extend_from_slice(&((length - 1) as u16).to_le_bytes());
+
+        start += length;
+    }
+
+    let mut candidates = vec![runs];
+
+    let mut palette: Vec<u8> = Vec::new();
+
+    for &value in voxels.iter() {
+        if !palette.contains(&value) {
+            palette.push(value);
+        }
+    }
+
+    let bits = palette_bits(palette.len());
+
+    if bits <= MAX_PALETTE_BITS {
+        let mut packed = vec![PALETTE, (palette.len() - 1) as u8];
+        packed.extend_from_slice(&palette);
+
+        let per_byte = 8 / bits as usize;
+
+        for group in voxels.chunks(per_byte) {
+            let mut byte = 0u8;
+
+            for (slot, value) in group.iter().enumerate() {
+                let entry = palette.iter().position(|other| other == value).expect("Every value is in the palette") as u8;
+                byte |= entry << (slot * bits as usize);
+            }
+
+            packed.push(byte);
+        }
+
+        candidates.push(packed);
+    }
+
+    let mut raw = vec![RAW];
+    raw.extend_from_slice(voxels);
+    candidates.push(raw);
+
+    return candidates.into_iter().min_by_key(|record| record.len()).expect("There is always a raw candidate");
+}
+
+/// Bits per index of a palette of `count` values, rounded up to a divisor of 8.
+fn palette_bits(count: usize) -> u32 {
+    let bits = (usize::BITS - (count - 1).leading_zeros()).max(1);
+
+    return bits.next_power_of_two();
+}
+
+/// Chunk of a record in the given file version, `offset` only locates errors.
+fn decode(version: u32, record: &[u8], offset: u64) -> Result<Chunk, SaveError> {
+    let corrupt = |reason| SaveError::Corrupt { offset, reason };
+
+    let (&encoding, payload) = record.split_first().ok_or(corrupt("empty chunk record"))?;
+
+    // Version 1 only knew empty and raw chunks, both unchanged since
+    if version == 1 && encoding != EMPTY_CHUNK && encoding != RAW {
+        return Err(corrupt("unknown chunk encoding"));
+    }
+
+    let voxels = match encoding {
+        EMPTY_CHUNK => Vec::new(),
+        RAW => payload.to_vec(),
+        UNIFORM => {
+            let &[value] = payload else {
+                return Err(corrupt("uniform chunk without a single value"));
+            };
+
+            vec![value; CHUNK_VOXELS]
+        }
+        RUN_LENGTH => {
+            if payload.len() % 3 != 0 {
+                return Err(corrupt("incomplete run"));
+            }
+
+            let mut voxels = Vec::with_capacity(CHUNK_VOXELS);
+
+            for run in payload.chunks_exact(3) {
+                let length = u16::from_le_bytes([run[1], run[2]]) as usize + 1;
+
+                if voxels.len() + length > CHUNK_VOXELS {
+                    return Err(corrupt("runs overflow the chunk"));
+                }
+
+                voxels.resize(voxels.len() + length, run[0]);
+            }
+
+            voxels
+        }
+        PALETTE => {
+            let (&count, rest) = payload.split_first().ok_or(corrupt("palette chunk without a palette"))?;
+            let count = count as usize + 1;
+
+            if rest.len() < count {
+                return Err(corrupt("palette chunk without a palette"));
+            }
+
+            let (palette, packed) = rest.split_at(count);
+            let bits = palette_bits(count);
+
+            // Bigger palettes are never packed, their indices would not fit the mask
+            if bits > MAX_PALETTE_BITS {
+                return Err(corrupt("palette too large to pack"));
+            }
+
+            let bits = bits as usize;
+            let mask = (1u8 << bits) - 1;
+
+            let mut voxels = Vec::with_capacity(CHUNK_VOXELS);
+
+            for byte in packed.iter() {
+                for slot in 0..8 / bits {
+                    let entry = ((byte >> (slot * bits)) & mask) as usize;
+                    voxels.push(*palette.get(entry).ok_or(corrupt("palette index out of range"))?);
+                }
+            }
+
+            voxels.truncate(CHUNK_VOXELS);
+            voxels
+        }
+        _ => return Err(corrupt("unknown chunk encoding")),
+    };
+
+    if !voxels.is_empty() && voxels.len() != CHUNK_VOXELS {
+        return Err(corrupt("chunk record does not hold a whole chunk"));
+    }
+
+    return Ok(Chunk::from_voxels(voxels));
+}
+
+/// Save opened for random access: the header and index are read up front, chunk records only when
+/// asked for.
+pub struct SaveFile<R> {
+    reader: R,
+    version: u32,
+    seed: u64,
+    index: HashMap<IVec3, (u64, u32)>,
+}
+
+impl<R: Read + Seek> SaveFile<R> {
+    pub fn new(mut reader: R) -> Result<Self, SaveError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).map_err(|_| SaveError::NotASave)?;
+
+        if magic != MAGIC {
+            return Err(SaveError::NotASave);
+        }
+
+        let version = read_u32(&mut reader)?;
+
+        // Worlds saved before the seed was recorded were all generated from seed 0
+        let seed = match version {
+            1 => 0,
+            2 => read_u64(&mut reader)?,
+            _ => return Err(SaveError::UnsupportedVersion(version)),
+        };
+
+        let count = read_u32(&mut reader)?;
+        let index_offset = read_u64(&mut reader)?;
+
+        let end = reader.seek(SeekFrom::End(0))?;
+
+        if index_offset.checked_add(count as u64 * INDEX_ENTRY as u64) != Some(end) {
+            return Err(SaveError::Corrupt { offset: index_offset, reason: "index does not end the file" });
+        }
+
+        reader.seek(SeekFrom::Start(index_offset))?;
+
+        let mut index = HashMap::new();
+
+        for entry in 0..count as u64 {
+            let coord = IVec3::new(read_i32(&mut reader)?, read_i32(&mut reader)?, read_i32(&mut reader)?);
+            let offset = read_u64(&mut reader)?;
+            let length = read_u32(&mut reader)?;
+
+            if offset.checked_add(length as u64).is_none_or(|record_end| record_end > index_offset) {
+                return Err(SaveError::Corrupt { offset: index_offset + entry * INDEX_ENTRY as u64, reason: "chunk record outside the file" });
+            }
+
+            index.insert(coord, (offset, length));
+        }
+
+        return Ok(Self {
+            reader,
+            version,
+            seed,
+            index,
+        });
+    }
+
+    /// Version the file was written with, below `VERSION` until it is written again.
+    pub fn version(&self) -> u32 {
+        return self.version;
+    }
+
+    pub fn seed(&self) -> u64 {
+        return self.seed;
+    }
+
+    pub fn contains(&self, coord: IVec3) -> bool {
+        return self.index.contains_key(&coord);
+    }
+
+    pub fn coords(&self) -> impl Iterator<Item = IVec3> + '_ {
+        return self.index.keys().copied();
+    }
+
+    /// Reads and decodes one chunk, `None` when the save does not hold it.
+    pub fn read_chunk(&mut self, coord: IVec3) -> Result<Option<Chunk>, SaveError> {
+        let Some(&(offset, length)) = self.index.get(&coord) else {
+            return Ok(None);
+        };
+
+        let mut record = vec![0; length as usize];
+
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(&mut record).map_err(|error| read_error(error, offset))?;
+
+        return decode(self.version, &record, offset).map(Some);
+    }
+}
+
+fn read_bytes<const N: usize>(reader: &mut (impl Read + Seek)) -> Result<[u8; N], SaveError> {
+    let offset = reader.stream_position()?;
+
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes).map_err(|error| read_error(error, offset))?;
+
+    return Ok(bytes);
+}
+
+fn read_u32(reader: &mut (impl Read + Seek)) -> Result<u32, SaveError> {
+    return Ok(u32::from_le_bytes(read_bytes(reader)?));
+}
+
+fn read_i32(reader: &mut (impl Read + Seek)) -> Result<i32, SaveError> {
+    return Ok(read_u32(reader)? as i32);
+}
+
+fn read_u64(reader: &mut (impl Read + Seek)) -> Result<u64, SaveError> {
+    return Ok(u64::from_le_bytes(read_bytes(reader)?));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use glam::{
+        IVec3,
+        UVec3
+    };
+
+    use crate::voxel::{
+        terrain::Terrain,
+        world::{
+            Chunk,
+            ChunkGenerator,
+            CHUNK_SIZE
+        },
+        STONE
+    };
+
+    use super::{
+        decode,
+        encode,
+        write_save,
+        SaveError,
+        SaveFile,
+        CHUNK_VOXELS,
+        PALETTE,
+        RUN_LENGTH,
+        VERSION
+    };
+
+    fn chunks() -> Vec<(IVec3, Chunk)> {
+        let terrain = Terrain::new(5);
+        let mut chunks: Vec<(IVec3, Chunk)> = [IVec3::new(0, 0, -1), IVec3::new(1, 0, -1), IVec3::new(0, 0, 3), IVec3::new(0, 0, -4)].iter().map(|&coord| (coord, terrain.generate(coord))).collect();
+
+        // A noisy chunk, where only the raw encoding fits
+        let mut noise = Chunk::new();
+
+        for index in 0..CHUNK_VOXELS as u32 {
+            let local = UVec3::new(index % CHUNK_SIZE, (index / CHUNK_SIZE) % CHUNK_SIZE, index / (CHUNK_SIZE * CHUNK_SIZE));
+            noise.set(local, (index.wrapping_mul(2_654_435_761) >> 24) as u8);
+        }
+
+        chunks.push((IVec3::new(-7, 3, 2), noise));
+
+        return chunks;
+    }
+
+    #[test]
+    fn saves_round_trip_with_random_access() {
+        let chunks = chunks();
+        let mut bytes = Vec::new();
+
+        write_save(&mut bytes, 5, chunks.iter().map(|(coord, chunk)| (*coord, chunk))).expect("Writing to memory does not fail");
+
+        let mut save = SaveFile::new(Cursor::new(bytes)).expect("Save should open");
+
+        assert_eq!(save.version(), VERSION);
+        assert_eq!(save.seed(), 5);
+        assert_eq!(save.coords().count(), chunks.len());
+
+        // Read back in reverse, each record is found through the index
+        for (coord, chunk) in chunks.iter().rev() {
+            assert_eq!(save.read_chunk(*coord).expect("Chunk should decode").as_ref(), Some(chunk));
+        }
+
+        assert!(save.read_chunk(IVec3::new(9, 9, 9)).expect("Missing chunks are not errors").is_none());
+
+        // Terrain compresses well, empty and solid chunks take a couple of bytes
+        assert!(encode(&chunks[0].1).len() < CHUNK_VOXELS / 4);
+        assert!(encode(&chunks[2].1).len() <= 2);
+        assert!(encode(&Chunk::filled(STONE)).len() <= 2);
+    }
+
+    #[test]
+    fn version_1_saves_are_migrated() {
+        let chunks = chunks();
+
+        // Version 1: no seed, chunks stored raw or empty
+        let mut records = Vec::new();
+        let mut index = Vec::new();
+
+        for (coord, chunk) in chunks.iter() {
+            let record = if chunk.is_empty() { vec![0] } else { [vec![1], chunk.voxels().to_vec()].concat() };
+
+            index.extend([coord.x, coord.y, coord.z].iter().flat_map(|value| value.to_le_bytes()));
+            index.extend_from_slice(&(20 + records.len() as u64).to_le_bytes());
+            index.extend_from_slice(&(record.len() as u32).to_le_bytes());
+
+            records.extend(record);
+        }
+
+        let mut bytes = b"VOXW".to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(20 + records.len() as u64).to_le_bytes());
+        bytes.extend(records);
+        bytes.extend(index);
+
+        let mut old = SaveFile::new(Cursor::new(bytes)).expect("Version 1 saves should open");
+        assert_eq!(old.version(), 1);
+        assert_eq!(old.seed(), 0);
+
+        let migrated: Vec<(IVec3, Chunk)> = chunks.iter().map(|(coord, _)| (*coord, old.read_chunk(*coord).unwrap().unwrap())).collect();
+
+        let mut upgraded = Vec::new();
+        write_save(&mut upgraded, old.seed(), migrated.iter().map(|(coord, chunk)| (*coord, chunk))).unwrap();
+
+        let mut save = SaveFile::new(Cursor::new(upgraded)).expect("Upgraded save should open");
+        assert_eq!(save.version(), VERSION);
+
+        for (coord, chunk) in chunks.iter() {
+            assert_eq!(save.read_chunk(*coord).unwrap().as_ref(), Some(chunk));
+        }
+    }
+
+    #[test]
+    fn corrupt_saves_are_rejected() {
+        let mut bytes = Vec::new();
+        write_save(&mut bytes, 1, [(IVec3::ZERO, &Chunk::filled(STONE))]).unwrap();
+
+        assert!(matches!(SaveFile::new(Cursor::new(b"nope".to_vec())), Err(SaveError::NotASave)));
+
+        let mut future = bytes.clone();
+        future[4] = 9;
+        assert!(matches!(SaveFile::new(Cursor::new(future)), Err(SaveError::UnsupportedVersion(9))));
+
+        let mut truncated = bytes.clone();
+        truncated.pop();
+        assert!(matches!(SaveFile::new(Cursor::new(truncated)), Err(SaveError::Corrupt { offset: 30, .. })));
+
+        // Uniform record holding two values
+        let mut bad = bytes.clone();
+        bad.insert(29, STONE);
+        let index_offset = u64::from_le_bytes(bad[20..28].try_into().unwrap()) + 1;
+        bad[20..28].copy_from_slice(&index_offset.to_le_bytes());
+        bad[index_offset as usize + 20] = 3;
+
+        let mut save = SaveFile::new(Cursor::new(bad)).expect("The index is still valid");
+        assert!(matches!(save.read_chunk(IVec3::ZERO), Err(SaveError::Corrupt { offset: 28, .. })));
+
+        // Cut inside the seed, which starts after the magic and the version
+        assert!(matches!(SaveFile::new(Cursor::new(bytes[..14].to_vec())), Err(SaveError::Corrupt { offset: 8, reason: "unexpected end of file" })));
+    }
+
+    #[test]
+    fn corrupt_records_are_rejected_before_decoding() {
+        // 17 entries need 8 bits per index, more than packed palettes use
+        let mut palette = vec![PALETTE, 16];
+        palette.extend(0..=16u8);
+        palette.push(0xff);
+
+        assert!(matches!(decode(VERSION, &palette, 40), Err(SaveError::Corrupt { offset: 40, reason: "palette too large to pack" })));
+
+        // Each run of 65536 voxels is already twice a chunk, decoding stops at the first one
+        let mut runs = vec![RUN_LENGTH];
+        runs.extend([STONE, 0xff, 0xff].repeat(1000));
+
+        assert!(matches!(decode(VERSION, &runs, 40), Err(SaveError::Corrupt { offset: 40, reason: "runs overflow the chunk" })));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,387 @@
+use glam::{
+    IVec3,
+    UVec3,
+    Vec2,
+    Vec3
+};
+
+use crate::voxel::{
+    world::{
+        Chunk,
+        ChunkGenerator,
+        CHUNK_SIZE
+    },
+    DIRT,
+    EMPTY,
+    GRASS,
+    SAND,
+    SNOW,
+    STONE,
+    WATER
+};
+
+/// Height of the water surface, in voxels.
+pub const SEA_LEVEL: i32 = -16;
+
+/// Mountains above this height are capped with snow.
+const SNOW_LINE: i32 = SEA_LEVEL + 40;
+
+/// No caves are carved below this height, so deep chunks stay solid and cheap.
+const CAVE_FLOOR: i32 = SEA_LEVEL - 80;
+
+/// Voxels between the samples of the cave noise, interpolated in between.
+const CAVE_STEP: i32 = 4;
+
+const CAVE_THRESHOLD: f32 = 0.42;
+
+/// Voxels of dirt or sand between the surface and the stone.
+const TOPSOIL: i32 = 3;
+
+// Every noise layer gets its own seed, derived from the terrain seed
+const HEIGHT_LAYER: u64 = 0x68_6569_6768_7400;
+const RIDGE_LAYER: u64 = 0x72_6964_6765_0000;
+const ROUGHNESS_LAYER: u64 = 0x72_6f75_6768_0000;
+const TEMPERATURE_LAYER: u64 = 0x74_656d_7000_0000;
+const CAVE_LAYER: u64 = 0x63_6176_6500_0000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+}
+
+/// Deterministic terrain: fractal noise hills and mountains, biome dependent surface blocks, seas
+/// and caves carved from 3D noise.
+///
+/// Generation only uses integer hashing and plain float arithmetic, no transcendental functions, so
+/// identical seeds give byte-identical chunks on every platform.
+pub struct Terrain {
+    seed: u64,
+}
+
+impl Terrain {
+    pub fn new(seed: u64) -> Self {
+        return Self {
+            seed,
+        };
+    }
+
+    /// Height of the topmost solid voxel of a column, ignoring caves, and the biome it belongs to.
+    pub fn column(&self, x: i32, y: i32) -> (i32, Biome) {
+        let position = Vec2::new(x as f32, y as f32);
+
+        let roughness = fractal_2d(self.seed ^ ROUGHNESS_LAYER, position / 384.0, 2);
+        let mountains = smoothstep(0.05, 0.35, roughness);
+
+        let base = fractal_2d(self.seed ^ HEIGHT_LAYER, position / 128.0, 5);
+        let ridge = 1.0 - fractal_2d(self.seed ^ RIDGE_LAYER, position / 64.0, 4).abs();
+
+        let height = SEA_LEVEL as f32 + 4.0 + base * (12.0 + 32.0 * mountains) + ridge * ridge * 40.0 * mountains;
+
+        let biome = if mountains > 0.5 {
+            Biome::Mountains
+        } else if fractal_2d(self.seed ^ TEMPERATURE_LAYER, position / 512.0, 2) > 0.15 {
+            Biome::Desert
+        } else {
+            Biome::Plains
+        };
+
+        return (height.floor() as i32, biome);
+    }
+
+    /// Cave noise at a voxel, caves are hollowed out where it exceeds `CAVE_THRESHOLD`.
+    fn cave_density(&self, voxel: IVec3) -> f32 {
+        // Squashed vertically, caves run more along the ground than down into it
+        let position = voxel.as_vec3() * Vec3::new(1.0 / 24.0, 1.0 / 24.0, 1.0 / 12.0);
+
+        return fractal_3d(self.seed ^ CAVE_LAYER, position, 2);
+    }
+
+    /// Block of a solid voxel at height `z` in a column whose surface is at `height`.
+    fn block(biome: Biome, height: i32, z: i32) -> u8 {
+        let depth = height - z;
+
+        if depth > TOPSOIL {
+            return STONE;
+        }
+
+        return match biome {
+            Biome::Mountains => if height >= SNOW_LINE && depth == 0 { SNOW } else { STONE },
+            Biome::Desert => SAND,
+            // Beaches and sea floors
+            Biome::Plains if height <= SEA_LEVEL + 1 => SAND,
+            Biome::Plains => if depth == 0 { GRASS } else { DIRT },
+        };
+    }
+}
+
+impl ChunkGenerator for Terrain {
+    fn generate(&self, coord: IVec3) -> Chunk {
+        let size = CHUNK_SIZE as i32;
+        let min = coord * size;
+
+        let mut columns = Vec::with_capacity((CHUNK_SIZE * CHUNK_SIZE) as usize);
+
+        for y in 0..size {
+            for x in 0..size {
+                columns.push(self.column(min.x + x, min.y + y));
+            }
+        }
+
+        let lowest = columns.iter().map(|&(height, _)| height).min().expect("Chunks have columns");
+        let highest = columns.iter().map(|&(height, _)| height).max().expect("Chunks have columns");
+
+        // Most chunks are entirely above the surface and the sea, or below the topsoil and the caves
+        if min.z > highest.max(SEA_LEVEL) {
+            return Chunk::new();
+        }
+
+        if min.z + size <= (lowest - TOPSOIL).min(CAVE_FLOOR) {
+            return Chunk::filled(STONE);
+        }
+
+        // The cave noise is sampled on a coarse lattice covering the chunk and its far faces
+        let samples = size / CAVE_STEP + 1;
+        let mut caves = Vec::with_capacity((samples * samples * samples) as usize);
+
+        for z in 0..samples {
+            for y in 0..samples {
+                for x in 0..samples {
+                    caves.push(self.cave_density(min + IVec3::new(x, y, z) * CAVE_STEP));
+                }
+            }
+        }
+
+        let is_cave = |local: IVec3| {
+            if min.z + local.z < CAVE_FLOOR {
+                return false;
+            }
+
+            let cell = local / CAVE_STEP;
+            let f = (local % CAVE_STEP).as_vec3() / CAVE_STEP as f32;
+            let sample = |offset: IVec3| {
+                let p = cell + offset;
+                return caves[(p.x + samples * (p.y + samples * p.z)) as usize];
+            };
+
+            let mut layers = [0.0; 2];
+
+            for (dz, layer) in layers.iter_mut().enumerate() {
+                let dz = dz as i32;
+
+                let bottom = lerp(sample(IVec3::new(0, 0, dz)), sample(IVec3::new(1, 0, dz)), f.x);
+                let top = lerp(sample(IVec3::new(0, 1, dz)), sample(IVec3::new(1, 1, dz)), f.x);
+
+                *layer = lerp(bottom, top, f.y);
+            }
+
+            return lerp(layers[0], layers[1], f.z) > CAVE_THRESHOLD;
+        };
+
+        let mut chunk = Chunk::new();
+
+        for y in 0..size {
+            for x in 0..size {
+                let (height, biome) = columns[(x + y * size) as usize];
+
+                for z in 0..size {
+                    let local = IVec3::new(x, y, z);
+                    let voxel = min + local;
+
+                    let value = if voxel.z > height {
+                        if voxel.z <= SEA_LEVEL { WATER } else { EMPTY }
+                    } else if (height >= SEA_LEVEL || voxel.z < height - TOPSOIL) && is_cave(local) {
+                        // Caves only open on dry land, the sea floor stays sealed
+                        EMPTY
+                    } else {
+                        Self::block(biome, height, voxel.z)
+                    };
+
+                    chunk.set(UVec3::new(x as u32, y as u32, z as u32), value);
+                }
+            }
+        }
+
+        return chunk;
+    }
+
+    fn seed(&self) -> u64 {
+        return self.seed;
+    }
+}
+
+/// Well mixed 32 bits for a lattice point of a noise layer.
+fn hash(seed: u64, x: i32, y: i32, z: i32) -> u32 {
+    let mut h = seed;
+
+    for coordinate in [x, y, z] {
+        // SplitMix64 finalizer over each coordinate in turn
+        h = h.wrapping_add(coordinate as u32 as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+    }
+
+    return (h >> 32) as u32;
+}
+
+/// Random value in [-1, 1] at a lattice point.
+fn lattice(seed: u64, x: i32, y: i32, z: i32) -> f32 {
+    return (hash(seed, x, y, z) >> 8) as f32 / (1 << 23) as f32 - 1.0;
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+
+    return t * t * (3.0 - 2.0 * t);
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    return a + (b - a) * t;
+}
+
+/// Value noise in [-1, 1], interpolating lattice values with a smoothstep fade.
+fn noise_2d(seed: u64, position: Vec2) -> f32 {
+    let cell = position.floor();
+    let (x, y) = (cell.x as i32, cell.y as i32);
+
+    let f = position - cell;
+    let f = f * f * (3.0 - 2.0 * f);
+
+    let bottom = lerp(lattice(seed, x, y, 0), lattice(seed, x + 1, y, 0), f.x);
+    let top = lerp(lattice(seed, x, y + 1, 0), lattice(seed, x + 1, y + 1, 0), f.x);
+
+    return lerp(bottom, top, f.y);
+}
+
+fn noise_3d(seed: u64, position: Vec3) -> f32 {
+    let cell = position.floor();
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+
+    let f = position - cell;
+    let f = f * f * (3.0 - 2.0 * f);
+
+    let mut layers = [0.0; 2];
+
+    for (dz, layer) in layers.iter_mut().enumerate() {
+        let z = z + dz as i32;
+
+        let bottom = lerp(lattice(seed, x, y, z), lattice(seed, x + 1, y, z), f.x);
+        let top = lerp(lattice(seed, x, y + 1, z), lattice(seed, x + 1, y + 1, z), f.x);
+
+        *layer = lerp(bottom, top, f.y);
+    }
+
+    return lerp(layers[0], layers[1], f.z);
+}
+
+/// Octaves of noise, each twice the frequency and half the amplitude of the previous one,
+/// normalized back to [-1, 1].
+fn fractal_2d(seed: u64, position: Vec2, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+
+    for octave in 0..octaves {
+        sum += amplitude * noise_2d(seed.wrapping_add(octave as u64), position * frequency);
+        total += amplitude;
+
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    return sum / total;
+}
+
+fn fractal_3d(seed: u64, position: Vec3, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+
+    for octave in 0..octaves {
+        sum += amplitude * noise_3d(seed.wrapping_add(octave as u64), position * frequency);
+        total += amplitude;
+
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    return sum / total;
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::voxel::{
+        world::ChunkGenerator,
+        EMPTY,
+        STONE
+    };
+
+    use super::{
+        Terrain,
+        SEA_LEVEL
+    };
+
+    /// FNV-1a, enough to pin the generated bytes.
+    fn checksum(bytes: &[u8]) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+
+        for byte in bytes {
+            hash = (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+
+        return hash;
+    }
+
+    #[test]
+    fn identical_seeds_generate_identical_chunks() {
+        let coords = [IVec3::new(0, 0, -1), IVec3::new(-3, 7, -1), IVec3::new(12, -5, -2), IVec3::new(40, 40, 0)];
+
+        let (first, second, other) = (Terrain::new(42), Terrain::new(42), Terrain::new(43));
+
+        for coord in coords {
+            assert_eq!(first.generate(coord), second.generate(coord), "Seed 42 gave two different chunks at {}", coord);
+        }
+
+        assert!(coords.iter().any(|&coord| first.generate(coord) != other.generate(coord)), "Seeds 42 and 43 should differ");
+
+        // Pinned bytes of a surface chunk, a change here changes every world generated so far
+        assert_eq!(checksum(first.generate(IVec3::new(0, 0, -1)).voxels()), 16699987885118532886);
+    }
+
+    #[test]
+    fn terrain_has_biomes_seas_and_caves() {
+        let terrain = Terrain::new(7);
+        let mut biomes = Vec::new();
+        let mut seas = 0;
+
+        for y in (-4096..4096).step_by(64) {
+            for x in (-4096..4096).step_by(64) {
+                let (height, biome) = terrain.column(x, y);
+
+                if !biomes.contains(&biome) {
+                    biomes.push(biome);
+                }
+
+                if height < SEA_LEVEL {
+                    seas += 1;
+                }
+            }
+        }
+
+        assert_eq!(biomes.len(), 3, "Only found {:?}", biomes);
+        assert!(seas > 0, "No column below the sea level");
+
+        // Deep chunks are solid stone apart from the caves
+        let chunk = terrain.generate(IVec3::new(0, 0, -3));
+        let caves = chunk.voxels().iter().filter(|&&value| value == EMPTY).count();
+
+        assert!(chunk.voxels().iter().all(|&value| value == STONE || value == EMPTY));
+        assert!(caves > 0 && caves < chunk.voxels().len() / 2, "{} cave voxels", caves);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,733 @@
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+        VecDeque
+    },
+    fs::{
+        self,
+        File
+    },
+    path::Path,
+    sync::{
+        mpsc,
+        Arc
+    }
+};
+
+use bytemuck::{
+    Pod,
+    Zeroable
+};
+
+use glam::{
+    IVec3,
+    UVec3,
+    Vec3
+};
+
+use crate::voxel::{
+    next_revision,
+    save::{
+        write_save,
+        SaveError,
+        SaveFile
+    },
+    terrain::Terrain,
+    Palette,
+    VoxelVolume,
+    EMPTY
+};
+
+/// Voxels per side of a chunk. Keep in sync with voxel_world.wgsl.
+pub const CHUNK_SIZE: u32 = 32;
+const CHUNK_VOXELS: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Chunks per side of the window kept around the camera, both loaded on the CPU and resident on the
+/// GPU. Keep in sync with voxel_world.wgsl.
+pub const WINDOW_CHUNKS: i32 = 8;
+
+/// Chunks generated at once on the background threads.
+pub const GENERATIONS_IN_FLIGHT: usize = 16;
+
+/// Chunks uploaded to the GPU per frame.
+pub const UPLOADS_PER_FRAME: usize = 8;
+
+/// Cube of `CHUNK_SIZE³` palette indices. Chunks of air keep no voxels at all.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chunk {
+    voxels: Vec<u8>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        return Self {
+            voxels: Vec::new(),
+        };
+    }
+
+    /// Chunk from voxels in the layout of `voxels`, collapsing chunks of air.
+    pub fn from_voxels(voxels: Vec<u8>) -> Self {
+        assert!(voxels.is_empty() || voxels.len() == CHUNK_VOXELS, "Chunks hold {} voxels, got {}", CHUNK_VOXELS, voxels.len());
+
+        if voxels.iter().all(|&value| value == EMPTY) {
+            return Self::new();
+        }
+
+        return Self {
+            voxels,
+        };
+    }
+
+    /// Chunk entirely made of `value`.
+    pub fn filled(value: u8) -> Self {
+        if value == EMPTY {
+            return Self::new();
+        }
+
+        return Self {
+            voxels: vec![value; CHUNK_VOXELS],
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.voxels.is_empty();
+    }
+
+    /// Voxels in X, then Y, then Z order, the layout of the GPU upload. Empty for chunks of air.
+    pub fn voxels(&self) -> &[u8] {
+        return &self.voxels;
+    }
+
+    fn index(local: UVec3) -> usize {
+        return (local.x + CHUNK_SIZE * (local.y + CHUNK_SIZE * local.z)) as usize;
+    }
+
+    pub fn get(&self, local: UVec3) -> u8 {
+        if self.voxels.is_empty() {
+            return EMPTY;
+        }
+
+        return self.voxels[Self::index(local)];
+    }
+
+    pub fn set(&mut self, local: UVec3, value: u8) {
+        if self.voxels.is_empty() {
+            if value == EMPTY {
+                return;
+            }
+
+            self.voxels = vec![EMPTY; CHUNK_VOXELS];
+        }
+
+        self.voxels[Self::index(local)] = value;
+    }
+}
+
+/// Fills the chunk at the given chunk coordinates, the same coordinates always giving the same chunk.
+/// Called from background threads.
+pub trait ChunkGenerator: Send + Sync {
+    fn generate(&self, coord: IVec3) -> Chunk;
+
+    /// Seed of the generated world, recorded in saves.
+    fn seed(&self) -> u64;
+}
+
+/// Unbounded voxel world split into chunks, only the window around the camera is kept in memory.
+///
+/// Chunks come from, in order: edits not saved yet, the save the world was opened from, and the
+/// generator. Saves only hold edited chunks, the others are generated again from the seed.
+pub struct World {
+    chunks: HashMap<IVec3, Chunk>,
+    generator: Arc<dyn ChunkGenerator>,
+
+    save: Option<SaveFile<File>>,
+    /// Chunks edited since the last save, whether loaded or parked.
+    edited: HashSet<IVec3>,
+    /// Edited chunks that left the window before being saved.
+    parked: HashMap<IVec3, Chunk>,
+    /// Revision of the last edit of each edited chunk, so renderers can upload them again.
+    revisions: HashMap<IVec3, u64>,
+    /// Chunks the save failed to load since the last `take_load_errors`, they were generated instead.
+    load_errors: Vec<(IVec3, SaveError)>,
+
+    generating: HashSet<IVec3>,
+    sender: mpsc::Sender<(IVec3, Chunk)>,
+    receiver: mpsc::Receiver<(IVec3, Chunk)>,
+
+    pub palette: Palette,
+    /// World space position of the corner of voxel `(0, 0, 0)`.
+    pub origin: Vec3,
+    pub voxel_size: f32,
+}
+
+impl World {
+    pub fn new(generator: Box<dyn ChunkGenerator>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        return Self {
+            chunks: HashMap::new(),
+            generator: Arc::from(generator),
+
+            save: None,
+            edited: HashSet::new(),
+            parked: HashMap::new(),
+            revisions: HashMap::new(),
+            load_errors: Vec::new(),
+
+            generating: HashSet::new(),
+            sender,
+            receiver,
+
+            palette: Palette::new(),
+            origin: Vec3::ZERO,
+            voxel_size: 0.125,
+        };
+    }
+
+    /// Reopens a saved world, the terrain is generated from the seed of the save.
+    pub fn open(path: &Path) -> Result<Self, SaveError> {
+        let save = SaveFile::new(File::open(path)?)?;
+
+        let mut world = Self::new(Box::new(Terrain::new(save.seed())));
+        world.save = Some(save);
+
+        return Ok(world);
+    }
+
+    /// Writes the saved and edited chunks to `path`, through a temporary file so a failed save never
+    /// loses the previous one. Saves of older versions come out migrated to the current one.
+    pub fn save(&mut self, path: &Path) -> Result<(), SaveError> {
+        let mut chunks = Vec::new();
+
+        if let Some(save) = self.save.as_mut() {
+            let coords: Vec<IVec3> = save.coords().filter(|coord| !self.edited.contains(coord)).collect();
+
+            for coord in coords {
+                chunks.push((coord, save.read_chunk(coord)?.expect("Indexed chunks exist")));
+            }
+        }
+
+        for coord in self.edited.iter() {
+            let chunk = self.chunks.get(coord).or(self.parked.get(coord)).expect("Edited chunks are loaded or parked");
+            chunks.push((*coord, chunk.clone()));
+        }
+
+        chunks.sort_by_key(|(coord, _)| (coord.z, coord.y, coord.x));
+
+        let temporary = path.with_extension("tmp");
+
+        write_save(File::create(&temporary)?, self.generator.seed(), chunks.iter().map(|(coord, chunk)| (*coord, chunk)))?;
+        fs::rename(&temporary, path)?;
+
+        self.save = Some(SaveFile::new(File::open(path)?)?);
+        self.edited.clear();
+        self.parked.clear();
+
+        return Ok(());
+    }
+
+    /// Coordinates of the chunk containing a world space position.
+    pub fn chunk_coord(&self, position: Vec3) -> IVec3 {
+        let voxel = ((position - self.origin) / self.voxel_size).floor().as_ivec3();
+
+        return voxel.div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+    }
+
+    pub fn chunk(&self, coord: IVec3) -> Option<&Chunk> {
+        return self.chunks.get(&coord);
+    }
+
+    pub fn loaded_count(&self) -> usize {
+        return self.chunks.len();
+    }
+
+    /// Palette index at a voxel, empty when its chunk is not loaded.
+    pub fn get(&self, voxel: IVec3) -> u8 {
+        let size = IVec3::splat(CHUNK_SIZE as i32);
+
+        return match self.chunks.get(&voxel.div_euclid(size)) {
+            Some(chunk) => chunk.get(voxel.rem_euclid(size).as_uvec3()),
+            None => EMPTY,
+        };
+    }
+
+    /// Changes a voxel of a loaded chunk, returns false when its chunk is not loaded.
+    pub fn set(&mut self, voxel: IVec3, value: u8) -> bool {
+        let size = IVec3::splat(CHUNK_SIZE as i32);
+        let coord = voxel.div_euclid(size);
+
+        let Some(chunk) = self.chunks.get_mut(&coord) else {
+            return false;
+        };
+
+        chunk.set(voxel.rem_euclid(size).as_uvec3(), value);
+
+        self.edited.insert(coord);
+        self.revisions.insert(coord, next_revision());
+
+        return true;
+    }
+
+    /// Revision of the last edit of a chunk, 0 for chunks never edited during this run.
+    pub fn chunk_revision(&self, coord: IVec3) -> u64 {
+        return self.revisions.get(&coord).copied().unwrap_or(0);
+    }
+
+    /// Chunks edited during this run with the revision of their last edit.
+    pub fn revisions(&self) -> impl Iterator<Item = (IVec3, u64)> + '_ {
+        return self.revisions.iter().map(|(coord, revision)| (*coord, *revision));
+    }
+
+    /// Chunks edited since the last save.
+    pub fn edited_count(&self) -> usize {
+        return self.edited.len();
+    }
+
+    /// Copies `count` chunks per side starting at chunk `min` into a volume placed where they sit in
+    /// the world. Chunks that are not loaded stay empty.
+    pub fn region(&self, min: IVec3, count: UVec3) -> VoxelVolume {
+        let mut volume = VoxelVolume::new(count * CHUNK_SIZE);
+        volume.palette = self.palette.clone();
+        volume.origin = self.origin + (min * CHUNK_SIZE as i32).as_vec3() * self.voxel_size;
+        volume.voxel_size = self.voxel_size;
+
+        for z in 0..count.z {
+            for y in 0..count.y {
+                for x in 0..count.x {
+                    let Some(chunk) = self.chunks.get(&(min + UVec3::new(x, y, z).as_ivec3())) else {
+                        continue;
+                    };
+
+                    if chunk.is_empty() {
+                        continue;
+                    }
+
+                    let offset = UVec3::new(x, y, z) * CHUNK_SIZE;
+
+                    for (index, value) in chunk.voxels().iter().enumerate() {
+                        if *value != EMPTY {
+                            let local = UVec3::new(index as u32 % CHUNK_SIZE, (index as u32 / CHUNK_SIZE) % CHUNK_SIZE, index as u32 / (CHUNK_SIZE * CHUNK_SIZE));
+
+                            volume.set(offset + local, *value);
+                        }
+                    }
+                }
+            }
+        }
+
+        return volume;
+    }
+
+    /// Chunks the save failed to load since the last call, with the reason. They were generated from
+    /// the seed instead and only overwrite their record if edited and saved.
+    pub fn take_load_errors(&mut self) -> Vec<(IVec3, SaveError)> {
+        return std::mem::take(&mut self.load_errors);
+    }
+
+    /// Evicts the chunks that left the window around `position`, collects the chunks the background
+    /// threads finished and starts generating the missing ones, nearest first. Never blocks, returns
+    /// the chunks of the window still missing. Chunks failing to load from the save are generated and
+    /// reported by `take_load_errors`.
+    pub fn stream(&mut self, position: Vec3) -> usize {
+        let center = self.chunk_coord(position);
+        let window_min = window_min(center);
+
+        // One chunk of slack, so hovering over a border does not evict and regenerate the same chunks
+        let keep = |coord: &IVec3| coord.cmpge(window_min - 1).all() && coord.cmplt(window_min + WINDOW_CHUNKS + 1).all();
+
+        let evicted: Vec<IVec3> = self.chunks.keys().filter(|coord| !keep(coord)).copied().collect();
+
+        for coord in evicted {
+            let chunk = self.chunks.remove(&coord).expect("Evicted chunks are loaded");
+
+            if self.edited.contains(&coord) {
+                self.parked.insert(coord, chunk);
+            }
+        }
+
+        while let Ok((coord, chunk)) = self.receiver.try_recv() {
+            self.generating.remove(&coord);
+
+            // Parked and saved chunks may have been loaded while this one was generating
+            if keep(&coord) && !self.chunks.contains_key(&coord) {
+                self.chunks.insert(coord, chunk);
+            }
+        }
+
+        let mut missing: Vec<IVec3> = nearest_first(center).into_iter().filter(|coord| !self.chunks.contains_key(coord)).collect();
+
+        // Edited and saved chunks are at hand, only the others need generating
+        missing.retain(|coord| {
+            if let Some(chunk) = self.parked.remove(coord) {
+                self.chunks.insert(*coord, chunk);
+                return false;
+            }
+
+            let Some(save) = self.save.as_mut() else {
+                return true;
+            };
+
+            return match save.read_chunk(*coord) {
+                Ok(Some(chunk)) => {
+                    self.chunks.insert(*coord, chunk);
+                    false
+                }
+                Ok(None) => true,
+                Err(error) => {
+                    self.load_errors.push((*coord, error));
+                    true
+                }
+            };
+        });
+
+        for coord in missing.iter() {
+            if self.generating.len() >= GENERATIONS_IN_FLIGHT {
+                break;
+            }
+
+            if self.generating.insert(*coord) {
+                let generator = Arc::clone(&self.generator);
+                let sender = self.sender.clone();
+                let coord = *coord;
+
+                // The world may be gone by the time the chunk is ready, nobody is listening then
+                rayon::spawn(move || {
+                    let _ = sender.send((coord, generator.generate(coord)));
+                });
+            }
+        }
+
+        return missing.len();
+    }
+
+    /// Streams until the whole window around `position` is loaded, waiting on the background threads.
+    pub fn load_window(&mut self, position: Vec3) {
+        while self.stream(position) > 0 {
+            let (coord, chunk) = self.receiver.recv().expect("The world holds a sender");
+            self.generating.remove(&coord);
+            self.chunks.entry(coord).or_insert(chunk);
+        }
+    }
+}
+
+/// First chunk of the window centered on `center`.
+pub fn window_min(center: IVec3) -> IVec3 {
+    return center - WINDOW_CHUNKS / 2;
+}
+
+/// Chunks of the window centered on `center`, ordered by distance to it.
+fn nearest_first(center: IVec3) -> Vec<IVec3> {
+    let window_min = window_min(center);
+    let mut coords = Vec::with_capacity((WINDOW_CHUNKS * WINDOW_CHUNKS * WINDOW_CHUNKS) as usize);
+
+    for z in 0..WINDOW_CHUNKS {
+        for y in 0..WINDOW_CHUNKS {
+            for x in 0..WINDOW_CHUNKS {
+                coords.push(window_min + IVec3::new(x, y, z));
+            }
+        }
+    }
+
+    coords.sort_by_key(|coord| (*coord - center).length_squared());
+
+    return coords;
+}
+
+/// Chunks resident on the GPU. The window is mapped onto a fixed ring of slots, chunk `c` living in
+/// slot `c mod WINDOW_CHUNKS`, so moving the camera only replaces the slab of chunks it left behind.
+pub struct Residency {
+    center: Option<IVec3>,
+    /// Chunk in each slot, with its revision when it was uploaded.
+    slots: Vec<Option<(IVec3, u64)>>,
+    queue: VecDeque<IVec3>,
+}
+
+impl Residency {
+    pub fn new() -> Self {
+        return Self {
+            center: None,
+            slots: vec![None; (WINDOW_CHUNKS * WINDOW_CHUNKS * WINDOW_CHUNKS) as usize],
+            queue: VecDeque::new(),
+        };
+    }
+
+    /// Slot of a chunk, as a position in the ring of slots.
+    pub fn slot(coord: IVec3) -> UVec3 {
+        return coord.rem_euclid(IVec3::splat(WINDOW_CHUNKS)).as_uvec3();
+    }
+
+    fn slot_index(coord: IVec3) -> usize {
+        let slot = Self::slot(coord);
+
+        return (slot.x + WINDOW_CHUNKS as u32 * (slot.y + WINDOW_CHUNKS as u32 * slot.z)) as usize;
+    }
+
+    pub fn resident_count(&self) -> usize {
+        return self.slots.iter().filter(|slot| slot.is_some()).count();
+    }
+
+    /// Chunks of the window waiting for an upload.
+    pub fn pending(&self) -> usize {
+        return self.queue.len();
+    }
+
+    /// Moves the window to `center`, queueing the chunks it now needs nearest first. Returns the
+    /// slots whose chunk left the window, to be cleared before their replacement arrives.
+    pub fn recenter(&mut self, center: IVec3) -> Vec<UVec3> {
+        if self.center == Some(center) {
+            return Vec::new();
+        }
+
+        self.center = Some(center);
+
+        let mut cleared = Vec::new();
+        self.queue.clear();
+
+        for coord in nearest_first(center) {
+            let index = Self::slot_index(coord);
+
+            if self.slots[index].map(|(resident, _)| resident) == Some(coord) {
+                continue;
+            }
+
+            if self.slots[index].take().is_some() {
+                cleared.push(Self::slot(coord));
+            }
+
+            self.queue.push_back(coord);
+        }
+
+        return cleared;
+    }
+
+    /// Queues the resident chunks edited since their upload again, ahead of the others.
+    pub fn refresh(&mut self, world: &World) {
+        for (coord, revision) in world.revisions() {
+            let Some((resident, uploaded)) = self.slots[Self::slot_index(coord)] else {
+                continue;
+            };
+
+            if resident == coord && uploaded != revision && !self.queue.contains(&coord) {
+                self.queue.push_front(coord);
+            }
+        }
+    }
+
+    /// Takes up to `UPLOADS_PER_FRAME` queued chunks that `world` has loaded, marking them resident.
+    pub fn next_uploads(&mut self, world: &World) -> Vec<IVec3> {
+        let mut uploads = Vec::new();
+
+        self.queue.retain(|coord| {
+            if uploads.len() < UPLOADS_PER_FRAME && world.chunk(*coord).is_some() {
+                uploads.push(*coord);
+                return false;
+            }
+
+            return true;
+        });
+
+        for coord in uploads.iter() {
+            self.slots[Self::slot_index(*coord)] = Some((*coord, world.chunk_revision(*coord)));
+        }
+
+        return uploads;
+    }
+}
+
+/// Layout of the world uniform buffer in voxel_world.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct WorldUniform {
+    /// World space position of the corner of the window.
+    pub origin: [f32; 3],
+    pub voxel_size: f32,
+    pub window_min: [i32; 3],
+    pub _padding: u32,
+}
+
+impl WorldUniform {
+    pub fn new(world: &World, center: IVec3) -> Self {
+        let window_min = window_min(center);
+
+        return Self {
+            origin: (world.origin + (window_min * CHUNK_SIZE as i32).as_vec3() * world.voxel_size).to_array(),
+            voxel_size: world.voxel_size,
+            window_min: window_min.to_array(),
+            _padding: 0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs,
+        process
+    };
+
+    use glam::{
+        IVec3,
+        UVec3,
+        Vec3
+    };
+
+    use crate::voxel::{
+        save::SaveError,
+        terrain::Terrain,
+        STONE,
+        WOOD
+    };
+
+    use super::{
+        Residency,
+        World,
+        CHUNK_SIZE,
+        GENERATIONS_IN_FLIGHT,
+        UPLOADS_PER_FRAME,
+        WINDOW_CHUNKS
+    };
+
+    const WINDOW_VOLUME: usize = (WINDOW_CHUNKS * WINDOW_CHUNKS * WINDOW_CHUNKS) as usize;
+
+    #[test]
+    fn streaming_keeps_a_bounded_window_around_the_camera() {
+        let mut world = World::new(Box::new(Terrain::new(0)));
+        let mut position = Vec3::ZERO;
+
+        // Nothing is generated on the calling thread
+        assert_eq!(world.stream(position), WINDOW_VOLUME);
+        assert!(world.generating.len() <= GENERATIONS_IN_FLIGHT);
+        assert!(world.generating.contains(&world.chunk_coord(position)), "The chunk holding the camera comes first");
+
+        world.load_window(position);
+        assert_eq!(world.loaded_count(), WINDOW_VOLUME);
+
+        // Fly far along X, the world never holds more than the window and its slack
+        for _ in 0..400 {
+            position.x += 0.5;
+            world.stream(position);
+
+            assert!(world.loaded_count() <= ((WINDOW_CHUNKS + 2) * (WINDOW_CHUNKS + 2) * (WINDOW_CHUNKS + 2)) as usize);
+        }
+
+        assert!(world.chunk(IVec3::ZERO).is_none(), "Chunks far behind should be evicted");
+    }
+
+    #[test]
+    fn edits_survive_saving_and_reopening() {
+        let path = env::temp_dir().join(format!("vox-world-{}.save", process::id()));
+
+        let mut world = World::new(Box::new(Terrain::new(3)));
+        world.load_window(Vec3::ZERO);
+
+        let (near, far) = (IVec3::new(3, 4, -20), IVec3::new(-60, 50, 10));
+
+        assert!(world.set(near, WOOD));
+        assert!(world.set(far, WOOD));
+        assert!(!world.set(IVec3::splat(10_000), WOOD), "Chunks that are not loaded cannot be edited");
+
+        // The far edit leaves the window and is parked until the save
+        world.load_window(Vec3::new(20.0, 0.0, 0.0));
+        assert!(world.chunk(far.div_euclid(IVec3::splat(CHUNK_SIZE as i32))).is_none());
+
+        world.save(&path).expect("Saving should succeed");
+        assert_eq!(world.edited_count(), 0);
+
+        let mut reopened = World::open(&path).expect("Save should open");
+        reopened.load_window(Vec3::ZERO);
+
+        assert_eq!(reopened.get(near), WOOD);
+        assert_eq!(reopened.get(far), WOOD);
+        assert_eq!(reopened.get(near + IVec3::X), world.get(near + IVec3::X), "Unedited voxels come from the seed");
+
+        fs::remove_file(&path).expect("Save should be removable");
+    }
+
+    #[test]
+    fn corrupt_chunks_are_reported_and_generated() {
+        let path = env::temp_dir().join(format!("vox-world-corrupt-{}.save", process::id()));
+        let voxel = IVec3::new(3, 4, 5);
+
+        let mut world = World::new(Box::new(Terrain::new(3)));
+        world.load_window(Vec3::ZERO);
+
+        let generated = world.get(voxel);
+        assert!(world.set(voxel, WOOD));
+        world.save(&path).expect("Saving should succeed");
+
+        // The only chunk record follows the 28 bytes of the header, give it an unknown encoding
+        let mut bytes = fs::read(&path).expect("Save should be readable");
+        bytes[28] = 0xff;
+        fs::write(&path, bytes).expect("Save should be writable");
+
+        let mut reopened = World::open(&path).expect("Save should open");
+        reopened.load_window(Vec3::ZERO);
+
+        let errors = reopened.take_load_errors();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, IVec3::ZERO);
+        assert!(matches!(errors[0].1, SaveError::Corrupt { offset: 28, .. }));
+        assert_eq!(reopened.get(voxel), generated);
+        assert!(reopened.take_load_errors().is_empty());
+
+        fs::remove_file(&path).expect("Save should be removable");
+    }
+
+    #[test]
+    fn region_copies_the_loaded_chunks() {
+        let mut world = World::new(Box::new(Terrain::new(0)));
+        world.load_window(Vec3::ZERO);
+
+        let min = IVec3::new(-1, 0, -1);
+        let volume = world.region(min, UVec3::new(2, 1, 1));
+
+        assert_eq!(volume.size(), UVec3::new(64, 32, 32));
+        assert_eq!(volume.origin, Vec3::new(-4.0, 0.0, -4.0));
+
+        for voxel in [UVec3::ZERO, UVec3::new(40, 7, 20), UVec3::new(63, 31, 31)] {
+            assert_eq!(volume.get(voxel), world.get(min * CHUNK_SIZE as i32 + voxel.as_ivec3()));
+        }
+    }
+
+    #[test]
+    fn residency_only_replaces_chunks_that_left_the_window() {
+        let mut world = World::new(Box::new(Terrain::new(0)));
+        let mut residency = Residency::new();
+
+        world.load_window(Vec3::ZERO);
+
+        assert!(residency.recenter(IVec3::ZERO).is_empty());
+        assert_eq!(residency.pending(), WINDOW_VOLUME);
+
+        let uploads = residency.next_uploads(&world);
+        assert_eq!(uploads.len(), UPLOADS_PER_FRAME);
+        assert_eq!(uploads[0], IVec3::ZERO);
+
+        while !residency.next_uploads(&world).is_empty() {}
+        assert_eq!(residency.resident_count(), WINDOW_VOLUME);
+
+        // One chunk along +X swaps a single slab of the ring
+        let cleared = residency.recenter(IVec3::X);
+        let slab = (WINDOW_CHUNKS * WINDOW_CHUNKS) as usize;
+
+        assert_eq!(cleared.len(), slab);
+        assert_eq!(residency.pending(), slab);
+        assert!(residency.next_uploads(&world).is_empty(), "The new slab is not generated yet");
+
+        world.load_window(Vec3::new(4.0, 0.0, 0.0));
+
+        while !residency.next_uploads(&world).is_empty() {}
+        assert_eq!(residency.resident_count(), WINDOW_VOLUME);
+
+        // Edits upload their chunk again, ahead of everything else
+        assert!(world.set(IVec3::new(40, 5, 5), STONE));
+        residency.refresh(&world);
+
+        assert_eq!(residency.next_uploads(&world), vec![IVec3::new(1, 0, 0)]);
+        residency.refresh(&world);
+        assert_eq!(residency.pending(), 0);
+    }
+}
\ No newline at end of file